@@ -0,0 +1,84 @@
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+/// GNOME settings schema holding the mouse's pointer acceleration settings
+const GNOME_MOUSE_SCHEMA: &str = "org.gnome.desktop.peripherals.mouse";
+
+/// Pointer acceleration profile, which turns the speed of the source device
+/// into a gain the same way that libinput does for the cursor
+///
+/// `speed` is libinput's speed setting, from -1.0 (slowest) to 1.0 (fastest).
+#[derive(Debug, Clone, Copy)]
+pub enum AccelProfile {
+    /// libinput's `flat` profile, a constant gain set by `speed`
+    Flat { speed: f32 },
+    /// libinput's `adaptive` profile, where the gain depends on how fast the
+    /// device is moving, and `speed` shifts the curve
+    Adaptive { speed: f32 },
+}
+
+impl AccelProfile {
+    /// Reads the mouse acceleration settings of the current GNOME session
+    ///
+    /// The `default` profile is treated as `adaptive`, since that is what
+    /// libinput uses for mice unless told otherwise.
+    pub fn read_gnome() -> Result<Self> {
+        let profile = gsettings_get(GNOME_MOUSE_SCHEMA, "accel-profile")?;
+        let speed = gsettings_get(GNOME_MOUSE_SCHEMA, "speed")?
+            .parse::<f32>()
+            .with_context(|| "mouse speed is not a number")?
+            .clamp(-1.0, 1.0);
+        match profile.trim_matches('\'') {
+            "flat" => Ok(Self::Flat { speed }),
+            "default" | "adaptive" => Ok(Self::Adaptive { speed }),
+            profile => bail!("unsupported acceleration profile {profile:?}"),
+        }
+    }
+
+    /// Gets the gain to apply to motion from a device moving at `speed`, in
+    /// device units per second
+    ///
+    /// Device units are assumed to be at 1000 DPI, which libinput also
+    /// normalizes to.
+    pub fn gain(self, speed: f32) -> f32 {
+        match self {
+            // see libinput's `filter-flat.c`
+            Self::Flat { speed: adjustment } => (1.0 + adjustment).max(0.005),
+            // see `pointer_accel_profile_linear` in libinput's `filter.c`
+            Self::Adaptive { speed: adjustment } => {
+                let max_accel = 2.0 + 2.0 * adjustment;
+                let threshold = (0.4 - 0.25 * adjustment).max(0.2);
+                let incline = 1.1 + adjustment;
+
+                // units per millisecond
+                let speed = speed / 1000.0;
+                let gain = if speed < 0.07 {
+                    10.0 * speed + 0.3
+                } else if speed < threshold {
+                    1.0
+                } else {
+                    incline * (speed - threshold) + 1.0
+                };
+                gain.min(max_accel)
+            }
+        }
+    }
+}
+
+fn gsettings_get(schema: &str, key: &str) -> Result<String> {
+    let output = Command::new("gsettings")
+        .args(["get", schema, key])
+        .output()
+        .with_context(|| "failed to run gsettings")?;
+    if !output.status.success() {
+        bail!(
+            "gsettings failed to get {schema} {key}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)
+        .with_context(|| "gsettings output is not UTF-8")?
+        .trim()
+        .to_owned())
+}
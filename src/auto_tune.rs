@@ -0,0 +1,105 @@
+use std::{collections::VecDeque, sync::PoisonError};
+
+use log::info;
+
+use crate::{
+    config::{Config, Multipliers},
+    states::Fingers,
+};
+
+/// How many of the most recent swipes with a finger count are considered
+const WINDOW: usize = 5;
+
+/// How many swipes in the window must be too short for the multipliers to be
+/// raised
+const SHORT_LIMIT: usize = 3;
+
+/// Factor that the multipliers are raised by each time
+const STEP: f32 = 1.25;
+
+/// Most times the multipliers for a finger count are raised, so that swipes
+/// which are short for some other reason don't raise them forever
+const MAX_ADJUSTMENTS: u32 = 8;
+
+/// Raises the swipe speed multipliers of finger counts whose swipes are often
+/// too short for the compositor to recognize
+///
+/// Each finger count is tuned separately. Once a window of swipes all move far
+/// enough, that finger count has converged and isn't adjusted again.
+#[derive(Debug, Default)]
+pub struct AutoTune {
+    tunings: Vec<(Fingers, Tuning)>,
+}
+
+#[derive(Debug, Default)]
+struct Tuning {
+    /// Whether each recent swipe moved far enough, oldest first
+    recent: VecDeque<bool>,
+    adjustments: u32,
+    converged: bool,
+}
+
+impl AutoTune {
+    /// Records whether a swipe with `fingers` moved far enough to be
+    /// recognized, adjusting the multipliers if needed
+    pub fn record(&mut self, config: &Config, fingers: Fingers, distance_met: bool) {
+        let index = if let Some(index) = self.tunings.iter().position(|(f, _)| *f == fingers) {
+            index
+        } else {
+            self.tunings.push((fingers, Tuning::default()));
+            self.tunings.len() - 1
+        };
+        let tuning = &mut self.tunings[index].1;
+        if tuning.converged || tuning.adjustments >= MAX_ADJUSTMENTS {
+            return;
+        }
+
+        if tuning.recent.len() == WINDOW {
+            tuning.recent.pop_front();
+        }
+        tuning.recent.push_back(distance_met);
+
+        let count = fingers.count();
+        let short = tuning.recent.iter().filter(|met| !**met).count();
+        if short >= SHORT_LIMIT {
+            let (x_mult, y_mult) = raise(config, fingers);
+            tuning.recent.clear();
+            tuning.adjustments += 1;
+            info!(
+                "Auto-tune: {short} of the last {WINDOW} {count}-finger swipes were too short, \
+                 raised multipliers to x = {x_mult}, y = {y_mult}"
+            );
+            if tuning.adjustments == MAX_ADJUSTMENTS {
+                info!(
+                    "Auto-tune: {count}-finger multipliers have been raised {MAX_ADJUSTMENTS} \
+                     times, not raising them any further"
+                );
+            }
+        } else if tuning.recent.len() == WINDOW && short == 0 {
+            tuning.converged = true;
+            let (x_mult, y_mult) = config.base_multipliers_for(fingers);
+            info!(
+                "Auto-tune: {count}-finger swipes converged at x = {x_mult}, y = {y_mult}, put \
+                 these in the config file and stop using `--auto-tune` to keep them"
+            );
+        }
+    }
+}
+
+/// Raises the multipliers for `fingers` by [`STEP`], returning the new ones
+fn raise(config: &Config, fingers: Fingers) -> (f32, f32) {
+    let (x_mult, y_mult) = config.base_multipliers_for(fingers);
+    let (x_mult, y_mult) = (x_mult * STEP, y_mult * STEP);
+    config
+        .multipliers
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .set(
+            fingers,
+            Multipliers {
+                x_mult: Some(x_mult),
+                y_mult: Some(y_mult),
+            },
+        );
+    (x_mult, y_mult)
+}
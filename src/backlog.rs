@@ -0,0 +1,207 @@
+use evdev::{EventStream, EventType, InputEvent, InputEventKind, RelativeAxisType};
+use futures::FutureExt;
+use log::{debug, trace};
+
+/// Most events which are read from a device's backlog at once, so that one
+/// busy device can't hold up the others
+const MAX_BACKLOG: usize = 1024;
+
+/// How motion which a device has queued up, because it was sent faster than
+/// it could be handled, is dealt with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputQueue {
+    /// Add the oldest motion events together, keeping the net motion but
+    /// dropping the positions in between
+    #[default]
+    DropOldest,
+    /// Drop the newest motion events, losing their motion
+    DropNewest,
+    /// Handle every event in turn, however far behind that falls
+    Block,
+}
+
+/// Reads the events which `first` was queued up with on `events`, and shapes
+/// the backlog with `queue`, so that no more than `depth` motion events in a
+/// row are handled one by one
+///
+/// Only the backlog already buffered is read, without waiting for more. Runs
+/// of `REL_X` and `REL_Y` events, and the `SYN_REPORT`s between them, are
+/// shaped. Any other event, like a key press, is kept in order and splits the
+/// runs, so motion is never moved past it. A read error ends the backlog,
+/// since reading the device again reports it.
+pub fn read(
+    queue: InputQueue,
+    depth: usize,
+    events: &mut EventStream,
+    first: InputEvent,
+) -> Vec<InputEvent> {
+    let mut backlog = vec![first];
+    if queue == InputQueue::Block {
+        return backlog;
+    }
+    while backlog.len() < MAX_BACKLOG {
+        match events.next_event().now_or_never() {
+            Some(Ok(input)) => backlog.push(input),
+            Some(Err(err)) => {
+                debug!(
+                    "Failed to read backlog, handling the events read so far: {:#}",
+                    anyhow::Error::new(err)
+                );
+                break;
+            }
+            None => break,
+        }
+    }
+
+    let read = backlog.len();
+    let mut shaped = Vec::with_capacity(read);
+    let mut run = Vec::new();
+    for input in backlog {
+        match input.kind() {
+            InputEventKind::RelAxis(RelativeAxisType::REL_X | RelativeAxisType::REL_Y) => {
+                run.push(input);
+            }
+            InputEventKind::Synchronization(_) => {}
+            _ => {
+                shape_run(queue, depth, &mut run, &mut shaped);
+                shaped.push(input);
+            }
+        }
+    }
+    shape_run(queue, depth, &mut run, &mut shaped);
+    if read > 1 {
+        trace!("Read {read} events of backlog, handling {}", shaped.len());
+    }
+    shaped
+}
+
+/// Moves a run of motion events into `shaped`, keeping at most `depth` of
+/// them separate
+fn shape_run(
+    queue: InputQueue,
+    depth: usize,
+    run: &mut Vec<InputEvent>,
+    shaped: &mut Vec<InputEvent>,
+) {
+    let excess = run.len().saturating_sub(depth);
+    if excess == 0 {
+        shaped.append(run);
+        return;
+    }
+
+    match queue {
+        InputQueue::DropOldest => {
+            // the oldest events are summed into one for each axis, which
+            // carry the newest of their timestamps
+            let (mut dx, mut dy) = (0i32, 0i32);
+            for input in &run[..=excess] {
+                if input.code() == RelativeAxisType::REL_X.0 {
+                    dx = dx.saturating_add(input.value());
+                } else {
+                    dy = dy.saturating_add(input.value());
+                }
+            }
+            let time = run[excess].as_ref().time;
+            for (axis, value) in [(RelativeAxisType::REL_X, dx), (RelativeAxisType::REL_Y, dy)] {
+                if value != 0 {
+                    shaped.push(InputEvent::from(libc::input_event {
+                        time,
+                        type_: EventType::RELATIVE.0,
+                        code: axis.0,
+                        value,
+                    }));
+                }
+            }
+            shaped.extend(run.drain(..).skip(excess + 1));
+        }
+        InputQueue::DropNewest => {
+            shaped.extend(run.drain(..).take(depth));
+        }
+        InputQueue::Block => shaped.append(run),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const X: u16 = RelativeAxisType::REL_X.0;
+    const Y: u16 = RelativeAxisType::REL_Y.0;
+
+    fn rel(axis: RelativeAxisType, value: i32, tv_usec: i64) -> InputEvent {
+        InputEvent::from(libc::input_event {
+            time: libc::timeval { tv_sec: 0, tv_usec },
+            type_: EventType::RELATIVE.0,
+            code: axis.0,
+            value,
+        })
+    }
+
+    fn shape(queue: InputQueue, depth: usize, run: &[InputEvent]) -> Vec<(u16, i32, i64)> {
+        let mut shaped = Vec::new();
+        shape_run(queue, depth, &mut run.to_vec(), &mut shaped);
+        shaped
+            .iter()
+            .map(|input| (input.code(), input.value(), input.as_ref().time.tv_usec))
+            .collect()
+    }
+
+    fn run_x(len: i32) -> Vec<InputEvent> {
+        (1..=len)
+            .map(|value| rel(RelativeAxisType::REL_X, value, value.into()))
+            .collect()
+    }
+
+    #[test]
+    fn short_runs_are_kept() {
+        for queue in [InputQueue::DropOldest, InputQueue::DropNewest] {
+            assert_eq!(
+                shape(queue, 3, &run_x(3)),
+                [(X, 1, 1), (X, 2, 2), (X, 3, 3)]
+            );
+        }
+    }
+
+    #[test]
+    fn drop_oldest_sums_oldest_motion() {
+        // the net motion is kept, with the newest timestamp of the sum
+        assert_eq!(
+            shape(InputQueue::DropOldest, 3, &run_x(6)),
+            [(X, 10, 4), (X, 5, 5), (X, 6, 6)]
+        );
+
+        let run = [
+            rel(RelativeAxisType::REL_X, 1, 1),
+            rel(RelativeAxisType::REL_Y, 2, 1),
+            rel(RelativeAxisType::REL_X, 3, 2),
+            rel(RelativeAxisType::REL_Y, 4, 2),
+        ];
+        assert_eq!(
+            shape(InputQueue::DropOldest, 2, &run),
+            [(X, 4, 2), (Y, 2, 2), (Y, 4, 2)]
+        );
+    }
+
+    #[test]
+    fn drop_oldest_skips_axes_which_sum_to_zero() {
+        let run = [
+            rel(RelativeAxisType::REL_X, 5, 1),
+            rel(RelativeAxisType::REL_X, -5, 2),
+            rel(RelativeAxisType::REL_Y, 1, 3),
+        ];
+        assert_eq!(shape(InputQueue::DropOldest, 1, &run), [(Y, 1, 3)]);
+    }
+
+    #[test]
+    fn drop_newest_keeps_oldest_motion() {
+        assert_eq!(
+            shape(InputQueue::DropNewest, 3, &run_x(6)),
+            [(X, 1, 1), (X, 2, 2), (X, 3, 3)]
+        );
+    }
+
+    #[test]
+    fn block_keeps_every_event() {
+        assert_eq!(shape(InputQueue::Block, 1, &run_x(3)).len(), 3);
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::info;
+
+use crate::{
+    config::Config,
+    states::{Fingers, Touch},
+    trackpad,
+};
+
+/// How far the fingers move back and forth while benchmarking, in trackpad
+/// units
+const SWEEP: i32 = 1000;
+
+/// Creates a virtual trackpad and moves fingers on it as fast as possible for
+/// `duration`, then prints how many frames and events were emitted per
+/// second, and how long each frame took to emit
+///
+/// Frames which fail to emit are counted and the benchmark carries on, since
+/// failures under load are part of what's being measured. The fingers are
+/// lifted at the end either way.
+pub async fn run(config: &Config, fingers: Fingers, duration: Duration) -> Result<()> {
+    info!("Creating virtual trackpad");
+    let (mut sink, _) = trackpad::create_trackpad(config).await?;
+
+    let touch = Touch::down(config, &mut sink, fingers)?;
+    let (frames_at_start, events_at_start) = (sink.frames(), sink.events());
+    let mut emit_times = Vec::new();
+    let mut errors = 0u64;
+    let mut first_error = None;
+    info!(
+        "Emitting as fast as possible with {} fingers for {duration:?}",
+        fingers.count()
+    );
+
+    let start = Instant::now();
+    let mut step = 0i32;
+    while start.elapsed() < duration {
+        // a triangle wave, so that every frame moves the fingers
+        let x = SWEEP - (step % (2 * SWEEP) - SWEEP).abs();
+        step = step.wrapping_add(1) & i32::MAX;
+
+        let emit_start = Instant::now();
+        if let Err(err) = touch.emit_position(config, &mut sink, x, 0) {
+            errors += 1;
+            first_error.get_or_insert(err);
+        }
+        emit_times.push(emit_start.elapsed());
+    }
+    let elapsed = start.elapsed();
+    touch.lift(config, &mut sink)?;
+
+    let frames = sink.frames() - frames_at_start;
+    let events = sink.events() - events_at_start;
+    let secs = elapsed.as_secs_f64();
+    #[allow(clippy::cast_precision_loss)]
+    let (frames_per_sec, events_per_sec) = (frames as f64 / secs, events as f64 / secs);
+    println!("Emitted {frames} frames ({events} events) in {elapsed:.2?}");
+    println!("  {frames_per_sec:.0} frames/s, {events_per_sec:.0} events/s");
+
+    emit_times.sort_unstable();
+    let percentile = |p: usize| emit_times[(emit_times.len() * p / 100).min(emit_times.len() - 1)];
+    if !emit_times.is_empty() {
+        println!(
+            "  time per frame: p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+            percentile(50),
+            percentile(90),
+            percentile(99),
+            percentile(100)
+        );
+    }
+    match first_error {
+        Some(err) => println!("  {errors} frames failed to emit, the first with: {err:#}"),
+        None => println!("  no frames failed to emit"),
+    }
+    Ok(())
+}
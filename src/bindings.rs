@@ -0,0 +1,179 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use evdev::Key;
+use log::{info, warn};
+use notify::{RecommendedWatcher, Watcher};
+use serde::Deserialize;
+
+use crate::{keys, states::Fingers};
+
+/// Keys which start a swipe with each finger count
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Triggers {
+    by_fingers: [Option<Key>; 4],
+}
+
+impl Triggers {
+    /// Makes triggers from the key codes for 2 to 5 fingers
+    ///
+    /// A code of 0 (`KEY_RESERVED`, which no device sends) leaves its finger
+    /// count unbound.
+    pub fn from_codes(codes: [Option<u16>; 4]) -> Self {
+        Self {
+            by_fingers: codes.map(|code| code.filter(|&code| code != 0).map(Key::new)),
+        }
+    }
+
+    /// Gets each finger count from 2 to 5, with the key which starts a swipe
+    /// with it, if any
+    pub fn iter(&self) -> impl Iterator<Item = (Fingers, Option<Key>)> + '_ {
+        [Fingers::Two, Fingers::Three, Fingers::Four, Fingers::Five]
+            .into_iter()
+            .zip(self.by_fingers)
+    }
+
+    /// Logs which key starts each finger count
+    pub fn log(&self) {
+        for (fingers, key) in self.iter() {
+            let count = fingers.count();
+            if let Some(key) = key {
+                info!("  {count} fingers = {}", keys::describe(key));
+            } else {
+                info!("  {count} fingers = unbound");
+            }
+        }
+    }
+}
+
+/// Contents of a bindings file, with a key for each finger count, given by
+/// code or by name
+///
+/// ```toml
+/// swipe_3 = 277
+/// swipe_4 = "BTN_EXTRA"
+/// ```
+// the field names are the keys in the file, named after the `-2` to `-5`
+// arguments
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingsFile {
+    swipe_2: Option<FileKey>,
+    swipe_3: Option<FileKey>,
+    swipe_4: Option<FileKey>,
+    swipe_5: Option<FileKey>,
+}
+
+/// Key in a bindings file, given by code or by name
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FileKey {
+    Code(u16),
+    Name(String),
+}
+
+impl FileKey {
+    fn code(&self) -> Result<u16> {
+        match self {
+            Self::Code(code) => Ok(*code),
+            Self::Name(name) => keys::parse_code(name).map_err(|err| anyhow!(err)),
+        }
+    }
+}
+
+/// Reads the triggers from the bindings file at `path`
+///
+/// Finger counts which the file leaves out have no trigger.
+pub fn load(path: &Path) -> Result<Triggers> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let file = toml::from_str::<BindingsFile>(&contents)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let mut codes = [None; 4];
+    let keys = [file.swipe_2, file.swipe_3, file.swipe_4, file.swipe_5];
+    for (code, key) in codes.iter_mut().zip(keys) {
+        *code = key
+            .map(|key| key.code())
+            .transpose()
+            .with_context(|| format!("invalid key in {}", path.display()))?;
+    }
+    let bound = codes
+        .iter()
+        .flatten()
+        .filter(|&&code| code != 0)
+        .collect::<Vec<_>>();
+    if bound.is_empty() {
+        bail!("{} doesn't bind any finger counts", path.display());
+    }
+    for (i, code) in bound.iter().enumerate() {
+        if bound[..i].contains(code) {
+            bail!(
+                "key code {code} is bound to more than one finger count in {}",
+                path.display()
+            );
+        }
+    }
+
+    Ok(Triggers::from_codes(codes))
+}
+
+/// Reloads `triggers` from the bindings file at `path` whenever it changes,
+/// until the returned watcher is dropped
+///
+/// If the changed file is invalid, the previous triggers are kept. The
+/// directory holding the file is watched rather than the file itself, since
+/// many editors save by replacing the file.
+pub fn watch(path: &Path, triggers: Arc<RwLock<Triggers>>) -> Result<RecommendedWatcher> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("failed to find {}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("/")).to_owned();
+
+    let file = path.clone();
+    let mut bindings_watcher = notify::recommended_watcher(move |res| match res {
+        Ok(notify::Event { kind, paths, .. })
+            if (kind.is_create() || kind.is_modify()) && paths.contains(&file) =>
+        {
+            reload(&file, &triggers);
+        }
+        Ok(_) => {}
+        Err(err) => {
+            warn!(
+                "Error while watching {}: {:#}",
+                file.display(),
+                anyhow::Error::new(err)
+            );
+        }
+    })
+    .with_context(|| format!("failed to create {} watcher", path.display()))?;
+
+    bindings_watcher
+        .watch(&dir, notify::RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to start watching {}", dir.display()))?;
+    info!("Watching {} for binding changes", path.display());
+    Ok(bindings_watcher)
+}
+
+fn reload(path: &Path, triggers: &RwLock<Triggers>) {
+    let new = match load(path) {
+        Ok(new) => new,
+        Err(err) => {
+            warn!("Failed to reload bindings, keeping previous ones: {err:#}");
+            return;
+        }
+    };
+    let mut triggers = triggers.write().unwrap_or_else(PoisonError::into_inner);
+    // saving a file often shows up as several changes
+    if *triggers == new {
+        return;
+    }
+    *triggers = new;
+    info!("Reloaded bindings from {}", path.display());
+    new.log();
+}
@@ -0,0 +1,96 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use log::{debug, warn};
+
+/// How many times in a row a device can fail to be tracked before it's
+/// blacklisted
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// How long a device is first blacklisted for, doubling with each further
+/// failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Longest time a device is blacklisted for
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// How long after its last failure a device's failures are forgotten
+const COOLDOWN: Duration = Duration::from_secs(30 * 60);
+
+/// Devices which keep failing to be tracked, so that a broken device node
+/// isn't retried every time it's created again
+///
+/// This only lives in memory, so restarting tries every device again.
+#[derive(Debug, Default)]
+pub struct Blacklist {
+    entries: AHashMap<PathBuf, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    failures: u32,
+    last_failed: Instant,
+    retry_at: Option<Instant>,
+}
+
+impl Blacklist {
+    /// Gets how much longer the device at `path` is blacklisted for, or `None`
+    /// if it may be tracked now
+    pub fn remaining(&mut self, path: &Path) -> Option<Duration> {
+        let entry = self.entries.get(path)?;
+        if entry.last_failed.elapsed() >= COOLDOWN {
+            debug!("Forgetting failures of {path:?} after cooldown");
+            self.entries.remove(path);
+            return None;
+        }
+        entry
+            .retry_at?
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Records that the device at `path` failed to be tracked, blacklisting it
+    /// if it has failed too many times in a row
+    pub fn record_failure(&mut self, path: &Path) {
+        let now = Instant::now();
+        let entry = self
+            .entries
+            .entry(path.to_owned())
+            .and_modify(|entry| {
+                if entry.last_failed.elapsed() >= COOLDOWN {
+                    entry.failures = 0;
+                }
+            })
+            .or_insert(Entry {
+                failures: 0,
+                last_failed: now,
+                retry_at: None,
+            });
+        entry.failures += 1;
+        entry.last_failed = now;
+        if entry.failures < FAILURE_THRESHOLD {
+            return;
+        }
+
+        let doublings = (entry.failures - FAILURE_THRESHOLD).min(u32::BITS - 1);
+        let backoff = INITIAL_BACKOFF
+            .checked_mul(1 << doublings)
+            .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+        entry.retry_at = Some(now + backoff);
+        warn!(
+            "Ignoring {path:?} for {backoff:?} after it failed to be tracked {} times in a row",
+            entry.failures
+        );
+    }
+
+    /// Forgets the failures of the device at `path`, now that it's been
+    /// tracked
+    pub fn record_success(&mut self, path: &Path) {
+        if self.entries.remove(path).is_some() {
+            debug!("Forgetting failures of {path:?} after tracking it");
+        }
+    }
+}
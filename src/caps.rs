@@ -0,0 +1,65 @@
+use evdev::{AbsoluteAxisType, Device, Key, RelativeAxisType};
+
+/// Kind of input device, as guessed from the events that it supports
+///
+/// A device may be more than one kind at once, e.g. a wireless receiver which
+/// is both a keyboard and a mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DeviceCaps {
+    /// Has at least [`MIN_KEYBOARD_KEYS`] keyboard keys, and is not a pointer
+    /// or touchpad
+    ///
+    /// Mice often report a few keyboard keys for their extra buttons, so a
+    /// handful of keys isn't enough to count as a keyboard.
+    Keyboard,
+    /// Has relative X and Y axes, like a mouse, trackball or trackpoint
+    Pointer,
+    /// Has multi-touch position axes and reports finger counts, like a
+    /// trackpad
+    Touchpad,
+    /// Has the south face button of a gamepad
+    Gamepad,
+}
+
+/// How many keyboard keys (codes below `BTN_MISC`, which is `BTN_0`) a device
+/// needs to be counted as a keyboard
+pub const MIN_KEYBOARD_KEYS: usize = 20;
+
+impl DeviceCaps {
+    /// Determines if `device` looks like this kind of device
+    pub fn matches(self, device: &Device) -> bool {
+        let has_key = |key: Key| {
+            device
+                .supported_keys()
+                .is_some_and(|keys| keys.contains(key))
+        };
+        let has_rel = |axis: RelativeAxisType| {
+            device
+                .supported_relative_axes()
+                .is_some_and(|axes| axes.contains(axis))
+        };
+        let has_abs = |axis: AbsoluteAxisType| {
+            device
+                .supported_absolute_axes()
+                .is_some_and(|axes| axes.contains(axis))
+        };
+
+        match self {
+            Self::Keyboard => {
+                let keyboard_keys = device.supported_keys().map_or(0, |keys| {
+                    keys.iter()
+                        .filter(|key| key.code() < Key::BTN_0.code())
+                        .count()
+                });
+                keyboard_keys >= MIN_KEYBOARD_KEYS
+                    && !Self::Pointer.matches(device)
+                    && !Self::Touchpad.matches(device)
+            }
+            Self::Pointer => has_rel(RelativeAxisType::REL_X) && has_rel(RelativeAxisType::REL_Y),
+            Self::Touchpad => {
+                has_abs(AbsoluteAxisType::ABS_MT_POSITION_X) && has_key(Key::BTN_TOOL_FINGER)
+            }
+            Self::Gamepad => has_key(Key::BTN_SOUTH),
+        }
+    }
+}
@@ -0,0 +1,722 @@
+use std::{
+    collections::HashMap,
+    fs,
+    net::SocketAddr,
+    os::fd::RawFd,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use evdev::Key;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::{
+    accel::AccelProfile,
+    backlog::InputQueue,
+    bindings::Triggers,
+    caps::DeviceCaps,
+    states::{Clock, Fingers, GrabTiming, LiftOrder, MotionSource, RetouchId, TrackingIds},
+    swipe_command::SwipeCommand,
+    trackpad::{Bus, DeviceType, MtProtocol, Origin},
+    watch::WatchMode,
+};
+
+/// Effective settings for a run of the simulator, built from the command line
+/// [`Args`](crate::Args)
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    pub input_allow: Vec<PathBuf>,
+    pub input_deny: Vec<PathBuf>,
+    /// If not empty, only devices which look like one of these kinds are
+    /// tracked
+    pub allow_caps: Vec<DeviceCaps>,
+    /// Devices which look like any of these kinds are never tracked
+    pub deny_caps: Vec<DeviceCaps>,
+    /// If set, only keys from this device start swipes
+    pub trigger_device: Option<PathBuf>,
+    /// If set, swipes are moved (and grabbed) by this device rather than the
+    /// device which triggered them
+    pub motion_device: Option<PathBuf>,
+    /// Which devices' motion moves a swipe, if there is no `motion_device`
+    pub motion_source: MotionSource,
+    /// If set, swipes only start while this file exists
+    pub enable_file: Option<PathBuf>,
+    /// If not empty, swipes only start while one of these apps is focused
+    #[cfg(feature = "focus")]
+    pub focus_apps: Vec<String>,
+    /// Keys which start swipes with each finger count
+    ///
+    /// This is shared so that it can be replaced when the bindings file
+    /// changes.
+    pub triggers: Arc<RwLock<Triggers>>,
+    /// If set, `triggers` were loaded from this file, and are reloaded
+    /// whenever it changes
+    pub bindings_file: Option<PathBuf>,
+    /// If set, swipes with a finger count outside of this list use the
+    /// nearest count in it instead
+    pub supported_fingers: Option<Vec<Fingers>>,
+    /// Finger counts which have fallen back to a supported count, so that
+    /// each is only warned about once
+    pub fallen_back: Arc<Mutex<Vec<Fingers>>>,
+    /// If set, releasing a trigger within this long of pressing it, without
+    /// moving, taps instead of swiping
+    pub tap_window: Option<Duration>,
+    /// Key which cancels the current swipe instead of committing it
+    pub cancel_key: Option<Key>,
+    /// If set, swipes can also be started as held drags
+    pub drag: Option<Drag>,
+    /// If set, a key performs a one-finger tap-and-drag
+    pub drag_lock: Option<DragLock>,
+    /// If set, swipe motion is sped up while a key is held
+    pub turbo: Option<Turbo>,
+    /// Multiplier for the first `anchor_events` motion events of a swipe
+    pub anchor_boost: f32,
+    pub anchor_events: u32,
+    /// Dead zone and smoothing of swipe motion on the X axis
+    pub filter_x: AxisFilter,
+    /// Dead zone and smoothing of swipe motion on the Y axis
+    pub filter_y: AxisFilter,
+    /// Key which turns mouse motion into scrolling while held
+    pub scroll_key: Option<Key>,
+    /// High-resolution scroll units per unit of mouse motion
+    pub scroll_factor: f32,
+    /// Whether two-finger swipes scroll the companion pointer instead of
+    /// touching the trackpad
+    pub scroll_two_finger: bool,
+    /// How long the fingers take to move back to the start when cancelling
+    pub cancel_duration: Duration,
+    /// How long the fingers rest at their final position before lifting when
+    /// a swipe ends
+    pub hold_end: Duration,
+    pub resolution: u16,
+    /// Movement which compositors need to see to recognize a swipe, compared
+    /// against when a swipe finishes
+    pub thresholds: Thresholds,
+    /// If set, events are emitted through this already-created `uinput`
+    /// device instead of creating a new one
+    pub uinput_fd: Option<RawFd>,
+    /// Kind of input device that the virtual trackpad presents itself as
+    pub device_type: DeviceType,
+    /// Where the coordinate origin of the position axes is, or `None` for the
+    /// default of the device type
+    pub origin: Option<Origin>,
+    /// Fuzz of the position axes, which the kernel filters noise with
+    pub abs_fuzz: i32,
+    /// Flat of the position axes, which some consumers use as a deadzone
+    pub abs_flat: i32,
+    /// Bus that the virtual trackpad appears to be connected through
+    pub bus: Bus,
+    pub mt_protocol: MtProtocol,
+    /// If set, fingers report their pressure, which ramps up from zero over
+    /// this long when a swipe starts, and back down when it ends
+    pub pressure_ramp: Option<Duration>,
+    /// Width and height of the screen in pixels, which the position axes of a
+    /// touchscreen cover
+    pub screen_size: (u16, u16),
+    /// Real trackpad to copy the position axis ranges and resolution from
+    pub match_device: Option<PathBuf>,
+    pub x_mult: f32,
+    pub y_mult: f32,
+    /// Whether to invert the X axis, on top of the multipliers
+    pub invert_x: bool,
+    /// Whether to invert the Y axis, on top of the multipliers
+    pub invert_y: bool,
+    /// Gains for motion in each direction, on top of the multipliers
+    pub direction_gains: DirectionGains,
+    /// Config files which `multipliers` were loaded from, in order, and are
+    /// reloaded on `SIGHUP`
+    pub files: Vec<PathBuf>,
+    /// Multipliers for specific finger counts, which override `x_mult` and
+    /// `y_mult`
+    ///
+    /// This is shared so that it can be replaced while swipes are running.
+    pub multipliers: Arc<RwLock<FingerMultipliers>>,
+    /// Gain applied to motion when the source device is moving slowly
+    pub gain_low: f32,
+    /// Gain applied to motion when the source device is moving at or above
+    /// `transition_speed`
+    pub gain_high: f32,
+    /// Speed of the source device, in units per second, at which `gain_high`
+    /// is fully applied
+    pub transition_speed: f32,
+    /// If set, this acceleration profile is used instead of `gain_low` and
+    /// `gain_high`
+    pub accel: Option<AccelProfile>,
+    pub grab: bool,
+    /// Devices (by path or name) which are always grabbed, regardless of
+    /// `grab`
+    pub grab_devices: Vec<String>,
+    /// Devices (by path or name) which are never grabbed, regardless of `grab`
+    pub skip_grab_devices: Vec<String>,
+    /// Whether swipe devices are grabbed when the trigger is pressed, or once
+    /// they move
+    pub grab_timing: GrabTiming,
+    /// Whether to cancel out cursor motion from source devices which aren't
+    /// grabbed while swiping
+    pub cursor_lock: bool,
+    /// Whether to re-emit events from grabbed devices which aren't part of the
+    /// swipe
+    pub passthrough: bool,
+    /// Real trackpad whose touches are mirrored with scaled motion, instead of
+    /// simulating gestures
+    pub passthrough_device: Option<PathBuf>,
+    /// How many reads from a swipe's source device can fail in a row before
+    /// the swipe is stopped, or 0 to never stop
+    pub max_read_errors: u32,
+    /// How motion which a device sends faster than it can be handled is dealt
+    /// with
+    pub input_queue: InputQueue,
+    /// How many queued motion events in a row are handled one by one before
+    /// `input_queue` applies
+    pub input_queue_depth: usize,
+    /// Maximum number of events in a single write to the virtual trackpad, or
+    /// 0 for no limit
+    pub max_batch_events: usize,
+    pub clock: Clock,
+    pub tracking_ids: TrackingIds,
+    pub lift_order: LiftOrder,
+    /// If set, the fingers are lifted and put back down at the origin once
+    /// they move further than this from it on either axis, in trackpad units
+    pub recenter_distance: Option<u32>,
+    /// Whether fingers keep their tracking IDs when they're recentered
+    pub retouch_id: RetouchId,
+    /// If set, swipe distance is scaled along a curve whose slope doubles
+    /// every this many trackpad units
+    pub log_scale: Option<f32>,
+    /// If set, swipes follow an arc which turns by this many radians per
+    /// trackpad unit travelled
+    pub curvature: Option<f32>,
+    /// If set, moves further than this many trackpad units are split into
+    /// smaller steps over a frame
+    pub interpolate: Option<u32>,
+    /// How far the fingers are moved straight after touching down for a
+    /// swipe, in trackpad units
+    pub start_offset: (i32, i32),
+    /// If set, the fingers are moved back to the start position over this
+    /// duration before being lifted
+    pub boomerang: Option<Duration>,
+    /// How long after touching down the fingers are held still, with motion
+    /// buffered until it has passed
+    pub emit_delay: Duration,
+    /// If set, every slot is lifted again this long after a gesture ends, to
+    /// clear fingers which the compositor thinks are stuck down
+    pub post_stop_clear: Option<Duration>,
+    /// If set, the fingers stay down for this long after a swipe stops, and a
+    /// swipe in the same direction started in that time continues the same
+    /// gesture
+    pub merge_window: Option<Duration>,
+    /// Whether to stop and pause gestures while the screen is locked
+    #[cfg(feature = "lock-detect")]
+    pub pause_when_locked: bool,
+    /// Whether to serve the control interface on the session bus
+    #[cfg(feature = "dbus")]
+    pub dbus: bool,
+    /// Kinds of events which show desktop notifications
+    #[cfg(feature = "notifications")]
+    pub notify: Vec<crate::notifications::NotifyOn>,
+    /// If set, remote clients can perform gestures over TCP
+    pub listen_gestures: Option<ListenGestures>,
+    /// If set, device changes are collected for this long and merged per path
+    /// before being acted on
+    pub device_debounce: Option<Duration>,
+    /// How to detect devices being added and removed
+    pub watch_mode: WatchMode,
+    /// How often to list devices when polling for changes
+    pub poll_interval: Duration,
+    /// If set, fast swipes end with momentum and a staggered lift, like a
+    /// flick on a real trackpad
+    pub physical: Option<Physical>,
+    /// File to record emitted events to, in `evemu-record` format
+    pub evemu_record: Option<PathBuf>,
+    /// File to record emitted events to, in `libinput record` format
+    pub libinput_record: Option<PathBuf>,
+    /// File to record emitted events to, in fukomaster's own format
+    pub record: Option<PathBuf>,
+    /// File to write the path of the emitted fingers to, as CSV
+    pub visualize: Option<PathBuf>,
+    /// Whether to measure the delay between source motion and emitting it
+    pub measure_latency: bool,
+    /// Whether to raise the multipliers of finger counts whose swipes are
+    /// often too short
+    pub auto_tune: bool,
+    /// Commands which run when swipes end
+    pub swipe_cmd: Vec<SwipeCommand>,
+    /// Whether swipes only run commands, and nothing is emitted through the
+    /// virtual trackpad
+    pub swipe_cmd_only: bool,
+    /// Shortest time between two swipe commands running
+    pub swipe_cmd_debounce: Duration,
+    /// Maximum random offset added to each emitted finger position, on each
+    /// axis, or 0 for none
+    pub jitter: u32,
+    /// Seed for the jitter, or `None` to pick one at random
+    pub jitter_seed: Option<u64>,
+    /// If set, the virtual trackpad isn't used until another process has
+    /// opened it, or this long has passed
+    pub wait_for_consumer: Option<Duration>,
+    /// If set, a tiny gesture is performed as soon as the virtual trackpad is
+    /// created
+    pub warmup: Option<Warmup>,
+    /// If set, a frame with only a timestamp is emitted to the virtual
+    /// trackpad whenever it has been idle for this long
+    pub idle_keepalive: Option<Duration>,
+    /// If set, a gamepad's right analog stick moves the fingers while swiping
+    pub stick: Option<Stick>,
+}
+
+/// Swipe which stays held after its trigger is released, until a separate key
+/// ends it
+#[derive(Debug, Clone, Copy)]
+pub struct Drag {
+    /// Key which starts the drag
+    pub key: Key,
+    /// Key which ends the drag when pressed, which may be the same as `key`
+    pub end_key: Key,
+    pub fingers: Fingers,
+}
+
+/// Movement that compositors need to see before they act on a swipe
+///
+/// These vary between libinput versions and compositors, so they're only used
+/// to diagnose swipes which weren't recognized.
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    /// Distance the fingers must move, in millimetres, before libinput decides
+    /// that a touch is a gesture rather than nothing
+    pub distance_mm: f32,
+    /// Speed at release, in millimetres per second, above which a swipe that
+    /// is too short to commit counts as a flick and commits anyway
+    pub speed_mm_s: f32,
+}
+
+/// Where to accept remote gesture clients
+#[derive(Debug, Clone)]
+pub struct ListenGestures {
+    pub addr: SocketAddr,
+    /// File containing the token which clients must authenticate with
+    pub token_file: PathBuf,
+}
+
+/// Key which speeds up swipe motion while held
+#[derive(Debug, Clone, Copy)]
+pub struct Turbo {
+    pub key: Key,
+    /// Multiplier for motion while `key` is held
+    pub factor: f32,
+}
+
+/// How swipe motion on one axis is filtered before it's applied
+#[derive(Debug, Clone, Copy)]
+pub struct AxisFilter {
+    /// How far a swipe must move along the axis, in source motion units,
+    /// before its motion is applied
+    pub dead_zone: u32,
+    /// Weight of previous motion when smoothing, from 0 to 0.99
+    pub smoothing: f32,
+}
+
+/// Key which taps once, then touches down again and drags while held
+#[derive(Debug, Clone, Copy)]
+pub struct DragLock {
+    pub key: Key,
+    /// Time between lifting the finger after the tap and touching down again
+    pub gap: Duration,
+}
+
+/// How swipes which end with a fast movement are turned into a flick
+#[derive(Debug, Clone, Copy)]
+pub struct Physical {
+    /// Scale applied to the release speed before checking if it is fast
+    /// enough to be a flick, so that higher values make flicks easier
+    pub flick_sensitivity: f32,
+}
+
+/// Gesture performed on the virtual trackpad at startup, so that the
+/// compositor has already set up its gesture handling before the first real
+/// swipe
+#[derive(Debug, Clone, Copy)]
+pub struct Warmup {
+    pub fingers: Fingers,
+    /// How far the fingers move on the X axis, in trackpad units
+    pub distance: i32,
+}
+
+/// How a gamepad's analog stick is turned into finger motion
+#[derive(Debug, Clone, Copy)]
+pub struct Stick {
+    /// Deflection, from 0.0 to 1.0, below which the stick is treated as
+    /// centered
+    pub deadzone: f32,
+    /// Speed of the fingers at full deflection, in trackpad units per second
+    /// before multipliers
+    pub speed: f32,
+}
+
+/// Swipe speed multipliers for motion in each direction, which are applied on
+/// top of the X and Y multipliers
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionGains {
+    pub up: f32,
+    pub down: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
+impl DirectionGains {
+    /// Gets the gains for X and Y motion which moves the fingers by
+    /// `(dx, dy)`, after multipliers
+    pub fn for_motion(self, dx: f32, dy: f32) -> (f32, f32) {
+        (
+            if dx < 0.0 { self.left } else { self.right },
+            if dy < 0.0 { self.up } else { self.down },
+        )
+    }
+}
+
+/// Multipliers for each finger count, as loaded from the config file
+#[derive(Debug, Clone, Default)]
+pub struct FingerMultipliers {
+    by_fingers: [Multipliers; 4],
+    /// Factor that both multipliers are scaled by while each monitor is
+    /// focused, by monitor name
+    by_monitor: HashMap<String, f32>,
+}
+
+/// Swipe speed multipliers which replace `--x-mult` and `--y-mult`
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Multipliers {
+    pub x_mult: Option<f32>,
+    pub y_mult: Option<f32>,
+}
+
+impl Multipliers {
+    /// Checks that the multipliers which are set are usable, naming them as
+    /// being for `subject`, like `3 fingers`
+    pub fn check(&self, subject: &str) -> Result<()> {
+        for (axis, mult) in [("x_mult", self.x_mult), ("y_mult", self.y_mult)] {
+            if let Some(mult) = mult {
+                check_multiplier(&format!("{axis} for {subject}"), mult)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FingerMultipliers {
+    /// Gets the multipliers for `fingers`, which are never set for a single
+    /// finger
+    fn get(&self, fingers: Fingers) -> Multipliers {
+        fingers
+            .count()
+            .checked_sub(2)
+            .map_or_else(Multipliers::default, |index| {
+                self.by_fingers[usize::from(index)]
+            })
+    }
+
+    /// Replaces the multipliers for `fingers`, unless it's a single finger
+    pub fn set(&mut self, fingers: Fingers, multipliers: Multipliers) {
+        if let Some(index) = fingers.count().checked_sub(2) {
+            self.by_fingers[usize::from(index)] = multipliers;
+        }
+    }
+}
+
+impl Multipliers {
+    /// Gets these multipliers with any values set in `other` replacing them
+    fn merged_with(self, other: Self) -> Self {
+        Self {
+            x_mult: other.x_mult.or(self.x_mult),
+            y_mult: other.y_mult.or(self.y_mult),
+        }
+    }
+}
+
+/// Contents of a config file
+///
+/// ```toml
+/// # loaded first, then overridden by the values in this file
+/// include = ["base.toml"]
+///
+/// # used instead of --x-mult and --y-mult when swiping with 3 fingers
+/// [fingers.3]
+/// x_mult = 1.5
+/// y_mult = 1.0
+///
+/// # scales the multipliers of swipes started while this monitor is focused
+/// [monitors.DP-1]
+/// scale = 0.5
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    /// Files to load before this one, relative to its directory
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(default)]
+    fingers: HashMap<String, Multipliers>,
+    #[serde(default)]
+    monitors: HashMap<String, MonitorScale>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MonitorScale {
+    scale: f32,
+}
+
+/// Reads the per-finger multipliers from the config files at `paths`, merged
+/// in order so that later files replace values from earlier ones
+pub fn load_multipliers(paths: &[PathBuf]) -> Result<FingerMultipliers> {
+    let mut multipliers = FingerMultipliers::default();
+    for path in paths {
+        load_file(path, &mut Vec::new(), &mut multipliers)?;
+    }
+
+    if !paths.is_empty() {
+        for (count, mults) in (2..).zip(multipliers.by_fingers) {
+            debug!(
+                "Effective multipliers for {count} fingers: x_mult = {:?}, y_mult = {:?}",
+                mults.x_mult, mults.y_mult
+            );
+        }
+        for (monitor, scale) in &multipliers.by_monitor {
+            debug!("Effective scale for monitor {monitor:?}: {scale}");
+        }
+    }
+    Ok(multipliers)
+}
+
+/// Checks that the multiplier `name` is usable, warning if it stops the
+/// motion which it applies to
+///
+/// Negative multipliers are allowed, since they invert the motion.
+pub fn check_multiplier(name: &str, mult: f32) -> Result<()> {
+    if !mult.is_finite() {
+        bail!("{name} must be a finite number, but is {mult}");
+    }
+    if mult == 0.0 {
+        warn!("{name} is 0, so the motion it applies to is stopped");
+    }
+    Ok(())
+}
+
+/// Checks that the speed-dependent gain settings are usable
+///
+/// The transition speed is divided by, so it must be above 0.
+pub fn check_gains(gain_low: f32, gain_high: f32, transition_speed: f32) -> Result<()> {
+    for (name, gain) in [("--gain-low", gain_low), ("--gain-high", gain_high)] {
+        if !gain.is_finite() {
+            bail!("{name} must be a finite number, but is {gain}");
+        }
+    }
+    if !(transition_speed.is_finite() && transition_speed > 0.0) {
+        bail!("--transition-speed must be a finite number above 0, but is {transition_speed}");
+    }
+    Ok(())
+}
+
+/// Merges the config file at `path`, after its includes, into `multipliers`
+///
+/// `including` holds the files which are part way through loading, which
+/// include this one, to detect circular includes.
+fn load_file(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    multipliers: &mut FingerMultipliers,
+) -> Result<()> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("failed to find {}", path.display()))?;
+    if including.contains(&path) {
+        let chain = including
+            .iter()
+            .chain([&path])
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>();
+        bail!("circular include: {}", chain.join(" -> "));
+    }
+
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+    let file = toml::from_str::<ConfigFile>(&contents)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    including.push(path.clone());
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    for include in &file.include {
+        load_file(&dir.join(include), including, multipliers)
+            .with_context(|| format!("failed to include from {}", path.display()))?;
+    }
+    including.pop();
+
+    for (count, mults) in file.fingers {
+        let fingers = count
+            .parse::<u8>()
+            .ok()
+            .and_then(Fingers::from_count)
+            .with_context(|| {
+                format!(
+                    "invalid finger count {count:?} in {}, must be 2 to 5",
+                    path.display()
+                )
+            })?;
+        mults.check(&format!("{count} fingers in {}", path.display()))?;
+        let index = usize::from(fingers.count() - 2);
+        multipliers.by_fingers[index] = multipliers.by_fingers[index].merged_with(mults);
+    }
+    for (monitor, MonitorScale { scale }) in file.monitors {
+        check_multiplier(
+            &format!("scale for monitor {monitor:?} in {}", path.display()),
+            scale,
+        )?;
+        multipliers.by_monitor.insert(monitor, scale);
+    }
+    debug!("Loaded config file {}", path.display());
+    Ok(())
+}
+
+impl Config {
+    /// Gets the keys which start swipes right now
+    pub fn triggers(&self) -> Triggers {
+        *self.triggers.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Gets the X and Y swipe speed multipliers for a swipe with `fingers`
+    ///
+    /// If `monitor` is set and has a scale in the config file, both
+    /// multipliers are scaled by it. This is read on every motion event, so
+    /// that a reloaded config file takes effect immediately.
+    pub fn multipliers_for(&self, fingers: Fingers, monitor: Option<&str>) -> (f32, f32) {
+        let (x_mult, y_mult) = self.base_multipliers_for(fingers);
+        let scale = monitor.map_or(1.0, |monitor| {
+            self.multipliers.read().map_or(1.0, |mults| {
+                mults.by_monitor.get(monitor).copied().unwrap_or(1.0)
+            })
+        });
+        let (sign_x, sign_y) = self.invert_signs();
+        (x_mult * scale * sign_x, y_mult * scale * sign_y)
+    }
+
+    /// Gets what `--invert-x` and `--invert-y` multiply the X and Y
+    /// multipliers by
+    pub fn invert_signs(&self) -> (f32, f32) {
+        let sign = |invert: bool| if invert { -1.0 } else { 1.0 };
+        (sign(self.invert_x), sign(self.invert_y))
+    }
+
+    /// Determines if any monitor has a scale in the config file
+    pub fn has_monitor_scales(&self) -> bool {
+        self.multipliers
+            .read()
+            .is_ok_and(|mults| !mults.by_monitor.is_empty())
+    }
+
+    /// Gets the multipliers for `fingers` like [`Config::multipliers_for`],
+    /// but without inverting them
+    pub fn base_multipliers_for(&self, fingers: Fingers) -> (f32, f32) {
+        let mults = self
+            .multipliers
+            .read()
+            .map_or_else(|_| Multipliers::default(), |mults| mults.get(fingers));
+        (
+            mults.x_mult.unwrap_or(self.x_mult),
+            mults.y_mult.unwrap_or(self.y_mult),
+        )
+    }
+
+    /// Gets the finger count to swipe with when `fingers` are requested,
+    /// falling back to the nearest supported count if needed
+    ///
+    /// Ties go to the smaller count.
+    pub fn supported_fingers_for(&self, fingers: Fingers) -> Fingers {
+        // a single finger is never a gesture, and two fingers scroll, so
+        // they're always supported
+        let Some(supported) = self
+            .supported_fingers
+            .as_ref()
+            .filter(|_| !matches!(fingers, Fingers::One | Fingers::Two))
+        else {
+            return fingers;
+        };
+        if supported.contains(&fingers) {
+            return fingers;
+        }
+        let Some(&fallback) = supported.iter().min_by_key(|supported| {
+            (
+                supported.count().abs_diff(fingers.count()),
+                supported.count(),
+            )
+        }) else {
+            return fingers;
+        };
+        let mut fallen_back = self
+            .fallen_back
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if !fallen_back.contains(&fingers) {
+            fallen_back.push(fingers);
+            warn!(
+                "Swiping with {} fingers instead of {}, which the compositor doesn't support",
+                fallback.count(),
+                fingers.count()
+            );
+        }
+        fallback
+    }
+
+    /// Determines if a swipe source device should be grabbed while swiping
+    pub fn should_grab(&self, path: &Path, name: Option<&str>) -> bool {
+        let matches =
+            |device: &String| Path::new(device) == path || name.is_some_and(|name| name == device);
+
+        if self.skip_grab_devices.iter().any(matches) {
+            false
+        } else if self.grab_devices.iter().any(matches) {
+            true
+        } else {
+            self.grab
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness;
+
+    #[test]
+    fn fingers_fall_back_to_nearest_supported() {
+        let config = harness::config(&["--finger-fallback", "--supported-fingers", "3,4"]);
+        assert_eq!(config.supported_fingers_for(Fingers::Three), Fingers::Three);
+        assert_eq!(config.supported_fingers_for(Fingers::Five), Fingers::Four);
+        assert_eq!(config.supported_fingers_for(Fingers::Five), Fingers::Four);
+        assert_eq!(*config.fallen_back.lock().unwrap(), [Fingers::Five]);
+    }
+
+    #[test]
+    fn one_and_two_fingers_never_fall_back() {
+        let config = harness::config(&["--finger-fallback", "--supported-fingers", "3,4"]);
+        assert_eq!(config.supported_fingers_for(Fingers::One), Fingers::One);
+        assert_eq!(config.supported_fingers_for(Fingers::Two), Fingers::Two);
+        assert!(config.fallen_back.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn multipliers_must_be_finite() {
+        let mults = |x_mult| Multipliers {
+            x_mult: Some(x_mult),
+            y_mult: None,
+        };
+        assert!(mults(-2.0).check("3 fingers").is_ok());
+        for x_mult in [f32::NAN, f32::INFINITY, 1e300_f64 as f32] {
+            assert!(mults(x_mult).check("3 fingers").is_err());
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// without the D-Bus controller, some requests are handled but never sent
+#![cfg_attr(not(feature = "dbus"), allow(dead_code))]
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    config::{Config, Multipliers},
+    gesture_loop::LoopGesture,
+    states::{Fingers, State},
+};
+
+/// Request from an external controller, handled by the `simulate` loop
+#[derive(Debug)]
+pub enum Request {
+    /// Starts a swipe driven by the motion of `device`, or the motion device
+    /// if not set, which lasts until a [`Request::StopSwipe`] or
+    /// [`Request::CancelSwipe`]
+    StartSwipe {
+        fingers: Fingers,
+        device: Option<PathBuf>,
+    },
+    /// Performs a whole swipe on its own, without following any device
+    Perform { gesture: LoopGesture },
+    /// Stops the swipe or scroll in progress, as if its trigger was released
+    StopSwipe,
+    /// Cancels the swipe in progress, as if the cancel key was pressed
+    CancelSwipe,
+    /// Stops new gestures from being started by trigger keys
+    Pause,
+    /// Allows new gestures to be started by trigger keys again
+    Resume,
+    /// Replaces the swipe speed multipliers for `fingers`, until the config
+    /// file is next reloaded
+    SetMultipliers {
+        fingers: Fingers,
+        multipliers: Multipliers,
+    },
+    /// Allows or stops trigger keys starting gestures with `fingers`, without
+    /// changing which keys are bound
+    SetFingersEnabled { fingers: Fingers, enabled: bool },
+    /// Does nothing, only replying with the status
+    Status,
+}
+
+/// Settings which controllers can change while running
+#[derive(Debug, Default)]
+pub struct Controls {
+    /// Whether new gestures are paused
+    pub paused: bool,
+    /// Finger counts which trigger keys don't start gestures with
+    pub disabled_fingers: Vec<Fingers>,
+}
+
+/// Snapshot of what the `simulate` loop is doing
+#[derive(Debug, Clone)]
+pub struct Status {
+    /// `normal`, `swiping` or `scrolling`
+    pub state: &'static str,
+    /// Whether new gestures are paused, either by a request or because the
+    /// screen is locked
+    pub paused: bool,
+    /// Finger count of the swipe in progress
+    pub fingers: Option<Fingers>,
+    /// Finger counts which have a trigger key bound, and aren't disabled
+    pub enabled_fingers: Vec<Fingers>,
+    /// Device which the gesture in progress is driven by
+    pub device: Option<PathBuf>,
+}
+
+impl Status {
+    pub fn of(config: &Config, state: &State, controls: &Controls, locked: bool) -> Self {
+        let (name, fingers, device) = match state {
+            State::Normal(_) => ("normal", None, None),
+            State::Swiping(swiping) => (
+                "swiping",
+                Some(swiping.touch.fingers),
+                Some(swiping.input_path.clone()),
+            ),
+            State::Scrolling(scrolling) => ("scrolling", None, Some(scrolling.input_path.clone())),
+        };
+        let mut enabled_fingers = config
+            .triggers()
+            .iter()
+            .filter(|(_, trigger)| trigger.is_some())
+            .map(|(fingers, _)| fingers)
+            .chain(config.drag.map(|drag| drag.fingers))
+            .filter(|fingers| !controls.disabled_fingers.contains(fingers))
+            .collect::<Vec<_>>();
+        enabled_fingers.sort_by_key(|fingers| fingers.count());
+        enabled_fingers.dedup();
+
+        Self {
+            state: name,
+            paused: controls.paused || locked,
+            fingers,
+            enabled_fingers,
+            device,
+        }
+    }
+}
+
+/// [`Request`] along with where to send the status once it's been handled
+#[derive(Debug)]
+pub struct Command {
+    pub request: Request,
+    pub reply: oneshot::Sender<Result<Status>>,
+}
+
+/// Sends `request` to the `simulate` loop and waits for it to be handled
+pub async fn send(commands: &mpsc::UnboundedSender<Command>, request: Request) -> Result<Status> {
+    let (reply, recv_reply) = oneshot::channel();
+    commands
+        .send(Command { request, reply })
+        .ok()
+        .with_context(|| "gesture loop is not running")?;
+    recv_reply
+        .await
+        .with_context(|| "gesture loop stopped before replying")?
+}
@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tokio::sync::mpsc;
+use zbus::{connection, fdo, interface, Connection};
+
+use crate::{
+    config::Multipliers,
+    control::{self, Command, Request, Status},
+    states::Fingers,
+};
+
+/// Well-known name requested on the session bus
+const BUS_NAME: &str = "io.github.aecsocket.Fukomaster";
+
+/// Path of the object implementing [`Control`]
+const OBJECT_PATH: &str = "/io/github/aecsocket/Fukomaster";
+
+/// D-Bus interface for controlling fukomaster, with a method for each
+/// [`Request`]
+///
+/// Every method returns the status after the request has been handled, as
+/// `(state, paused, fingers, device, enabled_fingers)`. `fingers` is 0 and
+/// `device` is empty if there is no gesture in progress.
+struct Control {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+type StatusTuple = (String, bool, u8, String, Vec<u8>);
+
+impl Control {
+    async fn send(&self, request: Request) -> fdo::Result<StatusTuple> {
+        let status = control::send(&self.commands, request)
+            .await
+            .map_err(|err| fdo::Error::Failed(format!("{err:#}")))?;
+        Ok(to_tuple(status))
+    }
+}
+
+fn to_tuple(status: Status) -> StatusTuple {
+    (
+        status.state.to_owned(),
+        status.paused,
+        status.fingers.map_or(0, Fingers::count),
+        status
+            .device
+            .map(|path| path.display().to_string())
+            .unwrap_or_default(),
+        status
+            .enabled_fingers
+            .into_iter()
+            .map(Fingers::count)
+            .collect(),
+    )
+}
+
+fn parse_fingers(fingers: u8) -> fdo::Result<Fingers> {
+    Fingers::from_count(fingers).ok_or_else(|| {
+        fdo::Error::InvalidArgs(format!("invalid finger count {fingers}, must be 2 to 5"))
+    })
+}
+
+#[interface(name = "io.github.aecsocket.Fukomaster1")]
+impl Control {
+    /// Starts a swipe driven by the motion of `device`, or the motion device
+    /// if empty, until `StopSwipe` or `CancelSwipe` is called
+    async fn start_swipe(&self, fingers: u8, device: String) -> fdo::Result<StatusTuple> {
+        let fingers = parse_fingers(fingers)?;
+        let device = (!device.is_empty()).then(|| PathBuf::from(device));
+        self.send(Request::StartSwipe { fingers, device }).await
+    }
+
+    async fn stop_swipe(&self) -> fdo::Result<StatusTuple> {
+        self.send(Request::StopSwipe).await
+    }
+
+    async fn cancel_swipe(&self) -> fdo::Result<StatusTuple> {
+        self.send(Request::CancelSwipe).await
+    }
+
+    async fn pause(&self) -> fdo::Result<StatusTuple> {
+        self.send(Request::Pause).await
+    }
+
+    async fn resume(&self) -> fdo::Result<StatusTuple> {
+        self.send(Request::Resume).await
+    }
+
+    /// Sets the swipe speed multipliers for `fingers`
+    ///
+    /// Multipliers which aren't finite, including those too large for an
+    /// `f32`, are rejected.
+    #[allow(clippy::cast_possible_truncation)]
+    async fn set_multipliers(
+        &self,
+        fingers: u8,
+        x_mult: f64,
+        y_mult: f64,
+    ) -> fdo::Result<StatusTuple> {
+        let fingers = parse_fingers(fingers)?;
+        let multipliers = Multipliers {
+            x_mult: Some(x_mult as f32),
+            y_mult: Some(y_mult as f32),
+        };
+        self.send(Request::SetMultipliers {
+            fingers,
+            multipliers,
+        })
+        .await
+    }
+
+    /// Allows or stops trigger keys starting gestures with `fingers`
+    async fn set_fingers_enabled(&self, fingers: u8, enabled: bool) -> fdo::Result<StatusTuple> {
+        let fingers = parse_fingers(fingers)?;
+        self.send(Request::SetFingersEnabled { fingers, enabled })
+            .await
+    }
+
+    async fn status(&self) -> fdo::Result<StatusTuple> {
+        self.send(Request::Status).await
+    }
+}
+
+/// Serves the control interface on the session bus, forwarding requests to
+/// `commands`
+///
+/// The interface is served for as long as the returned connection is kept.
+pub async fn serve(commands: mpsc::UnboundedSender<Command>) -> Result<Connection> {
+    connection::Builder::session()
+        .with_context(|| "failed to connect to the session bus")?
+        .name(BUS_NAME)
+        .with_context(|| format!("invalid bus name {BUS_NAME:?}"))?
+        .serve_at(OBJECT_PATH, Control { commands })
+        .with_context(|| format!("invalid object path {OBJECT_PATH:?}"))?
+        .build()
+        .await
+        .with_context(|| format!("failed to serve {BUS_NAME} on the session bus"))
+}
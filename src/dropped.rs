@@ -0,0 +1,103 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use ahash::AHashMap;
+use log::{debug, warn};
+
+/// Shortest time between warnings about the same device dropping events
+const WARN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Devices which the kernel has dropped events from, because they were sent
+/// faster than we read them
+///
+/// The kernel reports this with `SYN_DROPPED`, which evdev's event stream
+/// handles by reading the device's key and axis state again, so the only
+/// trace of it is the time which that state was read at changing.
+#[derive(Debug, Default)]
+pub struct DroppedEvents {
+    entries: AHashMap<PathBuf, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    synced_at: SystemTime,
+    warned_at: Option<Instant>,
+    suppressed: u32,
+}
+
+impl DroppedEvents {
+    /// Records the time which the state of the device at `path` was last
+    /// read again at, warning if it's changed since the last check
+    ///
+    /// Warnings for each device are limited to one every [`WARN_INTERVAL`],
+    /// with the drops in between counted in the next one.
+    pub fn check(&mut self, path: &Path, synced_at: SystemTime) {
+        let entry = self.entries.entry(path.to_owned()).or_insert(Entry {
+            synced_at: SystemTime::UNIX_EPOCH,
+            warned_at: None,
+            suppressed: 0,
+        });
+        // a device which was opened again starts out never read again
+        if synced_at == entry.synced_at || synced_at == SystemTime::UNIX_EPOCH {
+            entry.synced_at = synced_at;
+            return;
+        }
+        entry.synced_at = synced_at;
+
+        let now = Instant::now();
+        if entry
+            .warned_at
+            .is_some_and(|warned_at| now.duration_since(warned_at) < WARN_INTERVAL)
+        {
+            entry.suppressed += 1;
+            debug!("{path:?} dropped events again");
+            return;
+        }
+        entry.warned_at = Some(now);
+        let suppressed = std::mem::take(&mut entry.suppressed);
+        if suppressed == 0 {
+            warn!(
+                "{path:?} dropped events because they weren't read fast enough, so motion was lost"
+            );
+        } else {
+            warn!(
+                "{path:?} dropped events because they weren't read fast enough, so motion was lost \
+                 ({suppressed} more times since the last warning)"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warnings_are_rate_limited() {
+        let path = Path::new("/dev/input/event0");
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let mut dropped = DroppedEvents::default();
+        let entry = |dropped: &DroppedEvents| {
+            let entry = &dropped.entries[path];
+            (entry.warned_at.is_some(), entry.suppressed)
+        };
+
+        // never read again
+        dropped.check(path, SystemTime::UNIX_EPOCH);
+        assert_eq!(entry(&dropped), (false, 0));
+
+        dropped.check(path, at(1));
+        assert_eq!(entry(&dropped), (true, 0));
+        dropped.check(path, at(1));
+        assert_eq!(entry(&dropped), (true, 0));
+        dropped.check(path, at(2));
+        assert_eq!(entry(&dropped), (true, 1));
+
+        // opened again, then reading its state again
+        dropped.check(path, SystemTime::UNIX_EPOCH);
+        dropped.check(path, at(2));
+        assert_eq!(entry(&dropped), (true, 2));
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+
+use ahash::AHashMap;
+use clap::ValueEnum;
+use evdev::EventStream;
+
+use crate::{caps::DeviceCaps, config::Config, control::Controls, states::State, trackpad::Sink};
+
+/// Everything the `simulate` loop knows, gathered up to be printed for bug
+/// reports
+pub struct Snapshot<'a> {
+    pub config: &'a Config,
+    pub state: &'a State,
+    pub controls: &'a Controls,
+    pub locked: bool,
+    pub devices: &'a AHashMap<PathBuf, EventStream>,
+    pub sink: &'a Sink,
+    pub sink_dev_nodes: &'a [PathBuf],
+}
+
+impl Display for Snapshot<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "State dump")?;
+        writeln!(f, "  paused = {}", self.controls.paused)?;
+        writeln!(f, "  screen locked = {}", self.locked)?;
+        writeln!(
+            f,
+            "  disabled fingers = {:?}",
+            self.controls.disabled_fingers
+        )?;
+
+        writeln!(f, "  virtual trackpad:")?;
+        writeln!(f, "    sys path = {}", self.sink.sys_path().display())?;
+        writeln!(f, "    frames emitted = {}", self.sink.frames())?;
+        for dev_node in self.sink_dev_nodes {
+            writeln!(f, "    dev node = {}", dev_node.display())?;
+        }
+
+        writeln!(f, "  tracked devices:")?;
+        let mut paths = self.devices.keys().collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            let device = self.devices[path].device();
+            let caps = DeviceCaps::value_variants()
+                .iter()
+                .filter(|caps| caps.matches(device))
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "    {}: name = {:?}, caps = {caps:?}",
+                path.display(),
+                device.name().unwrap_or_default()
+            )?;
+        }
+
+        writeln!(f, "  state = {:#?}", self.state)?;
+        write!(f, "  config = {:#?}", self.config)
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, Synchronization, UinputAbsSetup};
+
+use crate::{
+    keys,
+    trackpad::{self, TrackpadSpec},
+};
+
+/// Size in bytes of the bitmask of each event type which evemu writes out
+///
+/// These are `(<TYPE>_MAX + 1) / 8` from the kernel headers, rounded up.
+const MASK_SIZES: [(u16, usize); 10] = [
+    (0x00, 4),  // EV_SYN, masks EV_MAX
+    (0x01, 96), // EV_KEY, masks KEY_MAX
+    (0x02, 2),  // EV_REL, masks REL_MAX
+    (0x03, 8),  // EV_ABS, masks ABS_MAX
+    (0x04, 1),  // EV_MSC, masks MSC_MAX
+    (0x05, 3),  // EV_SW, masks SW_MAX
+    (0x11, 2),  // EV_LED, masks LED_MAX
+    (0x12, 1),  // EV_SND, masks SND_MAX
+    (0x14, 1),  // EV_REP, masks REP_MAX
+    (0x15, 16), // EV_FF, masks FF_MAX
+];
+
+/// Opens `path` for writing, treating `-` as stdout
+pub fn open_output(path: &Path) -> io::Result<Box<dyn Write + Send>> {
+    if path == Path::new("-") {
+        Ok(Box::new(io::stdout()))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Writes the capabilities of a virtual trackpad in `evemu-describe` format
+///
+/// The output can be passed to `evemu-device` to create an identical device.
+/// See <https://www.freedesktop.org/wiki/Evemu/>.
+pub fn describe(spec: &TrackpadSpec, w: &mut impl Write) -> io::Result<()> {
+    let id = &spec.input_id;
+    let event_types = [
+        EventType::SYNCHRONIZATION,
+        EventType::KEY,
+        EventType::ABSOLUTE,
+        EventType::MISC,
+    ];
+
+    writeln!(w, "# EVEMU 1.3")?;
+    write_capabilities(spec, w, "# ")?;
+
+    writeln!(w, "N: {}", spec.name)?;
+    writeln!(
+        w,
+        "I: {:04x} {:04x} {:04x} {:04x}",
+        id.bus_type().0,
+        id.vendor(),
+        id.product(),
+        id.version()
+    )?;
+    write_mask(w, "P:", &mask(spec.properties.iter().map(|prop| prop.0), 8))?;
+    for (event_type, size) in MASK_SIZES {
+        let codes = match EventType(event_type) {
+            EventType::SYNCHRONIZATION => mask(event_types.iter().map(|ty| ty.0), size),
+            EventType::KEY => mask(spec.keys.iter().map(Key::code), size),
+            EventType::ABSOLUTE => mask(spec.abs_axes.iter().map(UinputAbsSetup::code), size),
+            EventType::MISC => mask(spec.misc.iter().map(|misc| misc.0), size),
+            _ => vec![0; size],
+        };
+        write_mask(w, &format!("B: {event_type:02x}"), &codes)?;
+    }
+    for axis in &spec.abs_axes {
+        let info = axis.absinfo();
+        writeln!(
+            w,
+            "A: {:02x} {} {} {} {} {}",
+            axis.code(),
+            info.minimum(),
+            info.maximum(),
+            info.fuzz(),
+            info.flat(),
+            info.resolution()
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the capabilities of a virtual trackpad in the same format that
+/// `evtest` prints for a real device
+///
+/// This is generated from the same [`TrackpadSpec`] that the device is built
+/// from, so it can be diffed against a real trackpad's `evtest` output to spot
+/// differences between them.
+pub fn describe_target(spec: &TrackpadSpec, w: &mut impl Write) -> io::Result<()> {
+    write_capabilities(spec, w, "")
+}
+
+/// Writes the device name, ID, supported events and properties, with each line
+/// starting with `prefix`
+pub fn write_capabilities(spec: &TrackpadSpec, w: &mut impl Write, prefix: &str) -> io::Result<()> {
+    let id = &spec.input_id;
+    writeln!(w, "{prefix}Input device name: {:?}", spec.name)?;
+    writeln!(
+        w,
+        "{prefix}Input device ID: bus {:#04x} vendor {:#06x} product {:#06x} version {:#06x}",
+        id.bus_type().0,
+        id.vendor(),
+        id.product(),
+        id.version()
+    )?;
+    writeln!(w, "{prefix}Supported events:")?;
+    writeln!(w, "{prefix}  Event type 0 (EV_SYN)")?;
+    writeln!(w, "{prefix}    Event code 0 (SYN_REPORT)")?;
+    writeln!(w, "{prefix}  Event type 1 (EV_KEY)")?;
+    for key in &spec.keys {
+        let name = keys::name(key).unwrap_or_else(|| "?".to_owned());
+        writeln!(w, "{prefix}    Event code {} ({name})", key.code())?;
+    }
+    writeln!(w, "{prefix}  Event type 3 (EV_ABS)")?;
+    for axis in &spec.abs_axes {
+        let info = axis.absinfo();
+        writeln!(
+            w,
+            "{prefix}    Event code {} ({:?})",
+            axis.code(),
+            AbsoluteAxisType(axis.code())
+        )?;
+        writeln!(w, "{prefix}      Value   {:>10}", info.value())?;
+        writeln!(w, "{prefix}      Min     {:>10}", info.minimum())?;
+        writeln!(w, "{prefix}      Max     {:>10}", info.maximum())?;
+        writeln!(w, "{prefix}      Fuzz    {:>10}", info.fuzz())?;
+        writeln!(w, "{prefix}      Flat    {:>10}", info.flat())?;
+        writeln!(w, "{prefix}      Resolution {:>7}", info.resolution())?;
+    }
+    if spec.misc.iter().next().is_some() {
+        writeln!(w, "{prefix}  Event type 4 (EV_MSC)")?;
+        for misc in &spec.misc {
+            writeln!(w, "{prefix}    Event code {} ({misc:?})", misc.0)?;
+        }
+    }
+    writeln!(w, "{prefix}Properties:")?;
+    for prop in &spec.properties {
+        writeln!(
+            w,
+            "{prefix}  Property  type {} (INPUT_PROP_{prop:?})",
+            prop.0
+        )?;
+    }
+    Ok(())
+}
+
+fn mask(codes: impl IntoIterator<Item = u16>, size: usize) -> Vec<u8> {
+    let mut bytes = vec![0; size];
+    for code in codes {
+        let code = usize::from(code);
+        bytes[code / 8] |= 1 << (code % 8);
+    }
+    bytes
+}
+
+fn write_mask(w: &mut impl Write, prefix: &str, bytes: &[u8]) -> io::Result<()> {
+    for chunk in bytes.chunks(8) {
+        write!(w, "{prefix}")?;
+        for i in 0..8 {
+            write!(w, " {:02x}", chunk.get(i).copied().unwrap_or(0))?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Records emitted events in `evemu-record` format, so that they can be
+/// replayed with `evemu-play`
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    start: Option<libc::timeval>,
+}
+
+impl Recorder {
+    /// Starts a recording at `path` (or stdout if `-`), writing the device
+    /// description as the header
+    pub fn create(path: &Path, spec: &TrackpadSpec) -> io::Result<Self> {
+        let mut out = open_output(path)?;
+        describe(spec, &mut out)?;
+        out.flush()?;
+        Ok(Self { out, start: None })
+    }
+}
+
+impl trackpad::Recorder for Recorder {
+    fn record(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let Some(last) = events.last() else {
+            return Ok(());
+        };
+        let time = last.as_ref().time;
+        let syn = InputEvent::from(libc::input_event {
+            time,
+            type_: EventType::SYNCHRONIZATION.0,
+            code: Synchronization::SYN_REPORT.0,
+            value: 0,
+        });
+
+        for event in events.iter().chain([&syn]) {
+            let time = event.as_ref().time;
+            let start = *self.start.get_or_insert(time);
+            let micros = (time.tv_sec - start.tv_sec) * 1_000_000 + (time.tv_usec - start.tv_usec);
+            writeln!(
+                self.out,
+                "E: {}.{:06} {:04x} {:04x} {:04}",
+                micros / 1_000_000,
+                micros % 1_000_000,
+                event.event_type().0,
+                event.code(),
+                event.value()
+            )?;
+        }
+        self.out.flush()
+    }
+}
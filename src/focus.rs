@@ -0,0 +1,302 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use log::debug;
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use crate::config::Config;
+
+/// How long to wait for the compositor to report the focused window, so that
+/// a stuck compositor doesn't hold up the gesture loop
+const QUERY_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Magic string which starts every i3/Sway IPC message
+const SWAY_MAGIC: &[u8] = b"i3-ipc";
+
+/// i3/Sway IPC message type which requests the workspaces
+const SWAY_GET_WORKSPACES: u32 = 1;
+
+/// i3/Sway IPC message type which requests the outputs
+const SWAY_GET_OUTPUTS: u32 = 3;
+
+/// i3/Sway IPC message type which requests the layout tree
+const SWAY_GET_TREE: u32 = 4;
+
+/// Determines if a swipe may start with the window which is focused right
+/// now
+///
+/// If `apps` is empty, any window may be focused. Otherwise, the focused
+/// window's app ID (or X11 class) must be one of `apps`. If the focused window
+/// can't be found out, swipes are allowed as if `apps` was empty.
+pub async fn allows(apps: &[String]) -> bool {
+    if apps.is_empty() {
+        return true;
+    }
+    match focused_app().await {
+        Ok(Some(app)) if apps.contains(&app) => true,
+        Ok(Some(app)) => {
+            debug!("Not starting swipe because {app:?} is focused");
+            false
+        }
+        Ok(None) => {
+            debug!("Not starting swipe because no window is focused");
+            false
+        }
+        Err(err) => {
+            debug!("Failed to get focused window, allowing swipe: {err:#}");
+            true
+        }
+    }
+}
+
+/// Gets the monitor whose scale applies to a swipe starting now, if any
+/// monitors have their own scale
+///
+/// If the focused monitor can't be found out, the swipe uses no monitor's
+/// scale.
+pub async fn swipe_monitor(config: &Config) -> Option<String> {
+    if !config.has_monitor_scales() {
+        return None;
+    }
+    match focused_monitor().await {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            debug!("Failed to get focused monitor, using no monitor's scale: {err:#}");
+            None
+        }
+    }
+}
+
+/// Gets the app ID (or X11 class) of the focused window, or `None` if no
+/// window is focused
+///
+/// Only Sway and Hyprland are supported, detected from the environment
+/// variables which they set.
+pub async fn focused_app() -> Result<Option<String>> {
+    query(sway_focused_app, hyprland_focused_app).await
+}
+
+/// Gets the name of the focused monitor (e.g. `DP-1`), or `None` if no monitor
+/// is focused
+///
+/// Supports the same compositors as [`focused_app`].
+pub async fn focused_monitor() -> Result<Option<String>> {
+    query(sway_focused_monitor, hyprland_focused_monitor).await
+}
+
+/// Gets the name of the focused workspace, or `None` if no workspace is
+/// focused
+///
+/// Supports the same compositors as [`focused_app`].
+pub async fn focused_workspace() -> Result<Option<String>> {
+    query(sway_focused_workspace, hyprland_focused_workspace).await
+}
+
+/// Asks whichever supported compositor is running, using `sway` with the path
+/// of Sway's socket, or `hyprland` with Hyprland's instance signature
+async fn query<T, S, H>(
+    sway: impl FnOnce(PathBuf) -> S,
+    hyprland: impl FnOnce(OsString) -> H,
+) -> Result<T>
+where
+    S: Future<Output = Result<T>>,
+    H: Future<Output = Result<T>>,
+{
+    let query = async {
+        if let Some(socket) = env::var_os("SWAYSOCK") {
+            sway(PathBuf::from(socket)).await
+        } else if let Some(signature) = env::var_os("HYPRLAND_INSTANCE_SIGNATURE") {
+            hyprland(signature).await
+        } else {
+            bail!("unsupported compositor, only Sway and Hyprland are supported");
+        }
+    };
+    tokio::time::timeout(QUERY_TIMEOUT, query)
+        .await
+        .ok()
+        .with_context(|| "timed out waiting for the compositor")?
+}
+
+#[derive(Debug, Deserialize)]
+struct SwayNode {
+    #[serde(default)]
+    focused: bool,
+    app_id: Option<String>,
+    window_properties: Option<SwayWindowProperties>,
+    #[serde(default)]
+    nodes: Vec<SwayNode>,
+    #[serde(default)]
+    floating_nodes: Vec<SwayNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SwayWindowProperties {
+    class: Option<String>,
+}
+
+impl SwayNode {
+    fn find_focused(self) -> Option<Self> {
+        if self.focused {
+            return Some(self);
+        }
+        self.nodes
+            .into_iter()
+            .chain(self.floating_nodes)
+            .find_map(Self::find_focused)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SwayOutput {
+    name: String,
+    #[serde(default)]
+    focused: bool,
+}
+
+async fn sway_focused_app(socket: PathBuf) -> Result<Option<String>> {
+    let reply = sway_request(&socket, SWAY_GET_TREE).await?;
+    let tree = serde_json::from_slice::<SwayNode>(&reply)
+        .with_context(|| "failed to parse layout tree from Sway")?;
+    Ok(tree.find_focused().and_then(|node| {
+        node.app_id
+            .or_else(|| node.window_properties.and_then(|props| props.class))
+    }))
+}
+
+async fn sway_focused_monitor(socket: PathBuf) -> Result<Option<String>> {
+    let reply = sway_request(&socket, SWAY_GET_OUTPUTS).await?;
+    let outputs = serde_json::from_slice::<Vec<SwayOutput>>(&reply)
+        .with_context(|| "failed to parse outputs from Sway")?;
+    Ok(outputs
+        .into_iter()
+        .find(|output| output.focused)
+        .map(|output| output.name))
+}
+
+async fn sway_focused_workspace(socket: PathBuf) -> Result<Option<String>> {
+    let reply = sway_request(&socket, SWAY_GET_WORKSPACES).await?;
+    // workspaces have the same fields as outputs that we need
+    let workspaces = serde_json::from_slice::<Vec<SwayOutput>>(&reply)
+        .with_context(|| "failed to parse workspaces from Sway")?;
+    Ok(workspaces
+        .into_iter()
+        .find(|workspace| workspace.focused)
+        .map(|workspace| workspace.name))
+}
+
+/// Sends a message of `message_type` with no payload to Sway, returning the
+/// payload of its reply
+async fn sway_request(socket: &Path, message_type: u32) -> Result<Vec<u8>> {
+    let mut stream = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("failed to connect to Sway at {}", socket.display()))?;
+
+    let mut request = SWAY_MAGIC.to_vec();
+    request.extend(0u32.to_ne_bytes());
+    request.extend(message_type.to_ne_bytes());
+    stream
+        .write_all(&request)
+        .await
+        .with_context(|| "failed to send request to Sway")?;
+
+    let mut header = [0; SWAY_MAGIC.len() + 8];
+    stream
+        .read_exact(&mut header)
+        .await
+        .with_context(|| "failed to read reply header from Sway")?;
+    if &header[..SWAY_MAGIC.len()] != SWAY_MAGIC {
+        bail!("invalid reply from Sway");
+    }
+    let len = u32::from_ne_bytes(
+        header[SWAY_MAGIC.len()..SWAY_MAGIC.len() + 4]
+            .try_into()
+            .expect("slice should be 4 bytes long"),
+    );
+    let mut payload = vec![0; usize::try_from(len).unwrap_or(usize::MAX)];
+    stream
+        .read_exact(&mut payload)
+        .await
+        .with_context(|| "failed to read reply from Sway")?;
+    Ok(payload)
+}
+
+#[derive(Debug, Deserialize)]
+struct HyprlandWindow {
+    class: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyprlandWorkspace {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HyprlandMonitor {
+    name: String,
+    #[serde(default)]
+    focused: bool,
+}
+
+async fn hyprland_focused_app(signature: OsString) -> Result<Option<String>> {
+    let reply = hyprland_request(&signature, "j/activewindow").await?;
+    let window = serde_json::from_slice::<HyprlandWindow>(&reply)
+        .with_context(|| "failed to parse active window from Hyprland")?;
+    Ok(window.class.filter(|class| !class.is_empty()))
+}
+
+async fn hyprland_focused_monitor(signature: OsString) -> Result<Option<String>> {
+    let reply = hyprland_request(&signature, "j/monitors").await?;
+    let monitors = serde_json::from_slice::<Vec<HyprlandMonitor>>(&reply)
+        .with_context(|| "failed to parse monitors from Hyprland")?;
+    Ok(monitors
+        .into_iter()
+        .find(|monitor| monitor.focused)
+        .map(|monitor| monitor.name))
+}
+
+async fn hyprland_focused_workspace(signature: OsString) -> Result<Option<String>> {
+    let reply = hyprland_request(&signature, "j/activeworkspace").await?;
+    let workspace = serde_json::from_slice::<HyprlandWorkspace>(&reply)
+        .with_context(|| "failed to parse active workspace from Hyprland")?;
+    Ok(Some(workspace.name).filter(|name| !name.is_empty()))
+}
+
+/// Sends `command` to Hyprland, returning its reply
+async fn hyprland_request(signature: &OsStr, command: &str) -> Result<Vec<u8>> {
+    // newer versions put the socket under the runtime dir, older ones under
+    // `/tmp`
+    let candidates = env::var_os("XDG_RUNTIME_DIR")
+        .map(|runtime_dir| PathBuf::from(runtime_dir).join("hypr"))
+        .into_iter()
+        .chain([PathBuf::from("/tmp/hypr")])
+        .map(|dir| dir.join(signature).join(".socket.sock"));
+    let mut stream = None;
+    for socket in candidates {
+        if let Ok(connected) = UnixStream::connect(&socket).await {
+            stream = Some(connected);
+            break;
+        }
+    }
+    let mut stream = stream.with_context(|| "failed to connect to Hyprland")?;
+
+    stream
+        .write_all(command.as_bytes())
+        .await
+        .with_context(|| "failed to send request to Hyprland")?;
+    let mut reply = Vec::new();
+    stream
+        .read_to_end(&mut reply)
+        .await
+        .with_context(|| "failed to read reply from Hyprland")?;
+    Ok(reply)
+}
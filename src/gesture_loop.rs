@@ -0,0 +1,198 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use tokio::time::MissedTickBehavior;
+
+use crate::{
+    config::Config,
+    states::{Fingers, Touch, FRAME_INTERVAL},
+    trackpad::{self, Sink},
+};
+
+/// Direction that the fingers move in during a looped gesture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// Gets the direction which motion by `(x, y)` is mostly in, or `None` if
+    /// there is no motion
+    pub fn dominant(x: f32, y: f32) -> Option<Self> {
+        if x == 0.0 && y == 0.0 {
+            None
+        } else if x.abs() >= y.abs() {
+            Some(if x < 0.0 { Self::Left } else { Self::Right })
+        } else {
+            Some(if y < 0.0 { Self::Up } else { Self::Down })
+        }
+    }
+
+    fn unit(self) -> (i32, i32) {
+        match self {
+            Self::Up => (0, -1),
+            Self::Down => (0, 1),
+            Self::Left => (-1, 0),
+            Self::Right => (1, 0),
+        }
+    }
+}
+
+/// Gesture which is repeatedly performed on the virtual trackpad
+#[derive(Debug, Clone, Copy)]
+pub struct LoopGesture {
+    pub fingers: Fingers,
+    pub direction: Direction,
+    /// How far the fingers move, in trackpad units
+    pub distance: i32,
+    /// How long the fingers take to move `distance`
+    pub duration: Duration,
+    /// Time between the start of each gesture
+    pub interval: Duration,
+}
+
+/// Creates a virtual trackpad and performs `gesture` on it every
+/// `gesture.interval`, until Ctrl-C is pressed
+pub async fn run(config: &Config, gesture: LoopGesture) -> Result<()> {
+    info!("Creating virtual trackpad");
+    let (mut sink, _) = trackpad::create_trackpad(config).await?;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut interval = tokio::time::interval(gesture.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    info!(
+        "Swiping {:?} with {} fingers every {:?}, press Ctrl-C to stop",
+        gesture.direction,
+        gesture.fingers.count(),
+        gesture.interval
+    );
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            res = &mut ctrl_c => {
+                res.with_context(|| "failed to listen for Ctrl-C")?;
+                info!("Stopping gesture loop");
+                return Ok(());
+            }
+        }
+
+        // each gesture runs to completion, so that the fingers are never left
+        // touching the trackpad
+        perform(config, &mut sink, &gesture)
+            .await
+            .with_context(|| "failed to perform gesture")?;
+        debug!("Performed {:?} gesture", gesture.direction);
+    }
+}
+
+/// How long to give the compositor to react to a probe swipe before asking
+/// it what happened
+#[cfg(feature = "focus")]
+const ECHO_DELAY: Duration = Duration::from_millis(500);
+
+/// Creates a virtual trackpad and performs `gesture` once with each finger
+/// count in turn, so the user can see which ones their compositor responds to
+///
+/// `gesture.fingers` is ignored, and `gesture.interval` is the pause between
+/// each finger count. With the `focus` feature, whether each swipe switched
+/// workspace is asked from the compositor and printed, if it's supported.
+pub async fn probe_fingers(config: &Config, gesture: LoopGesture) -> Result<()> {
+    info!("Creating virtual trackpad");
+    let (mut sink, _) = trackpad::create_trackpad(config).await?;
+    #[cfg(feature = "focus")]
+    if let Err(err) = crate::focus::focused_workspace().await {
+        println!("Can't ask the compositor what each swipe did, so watch the screen: {err:#}");
+    }
+
+    for fingers in [Fingers::Two, Fingers::Three, Fingers::Four, Fingers::Five] {
+        println!(
+            "Swiping {:?} with {} fingers - {}",
+            gesture.direction,
+            fingers.count(),
+            expected_action(fingers)
+        );
+        // give the user time to read what's coming before it happens
+        tokio::time::sleep(gesture.interval).await;
+        #[cfg(feature = "focus")]
+        let before = crate::focus::focused_workspace().await;
+        perform(config, &mut sink, &LoopGesture { fingers, ..gesture })
+            .await
+            .with_context(|| format!("failed to perform {}-finger gesture", fingers.count()))?;
+        #[cfg(feature = "focus")]
+        echo_workspace(before).await;
+    }
+
+    println!(
+        "Done. Use the `-2`/`-3`/`-4`/`-5` options for the finger counts which did something \
+         useful."
+    );
+    Ok(())
+}
+
+/// Prints whether the focused workspace is different from `before`, once the
+/// compositor has had time to react to a swipe
+///
+/// Nothing is printed if the workspace couldn't be found out, in which case
+/// the user has to watch the screen.
+#[cfg(feature = "focus")]
+async fn echo_workspace(before: Result<Option<String>>) {
+    let Ok(before) = before else {
+        return;
+    };
+    tokio::time::sleep(ECHO_DELAY).await;
+    match crate::focus::focused_workspace().await {
+        Ok(after) if after != before => println!(
+            "  -> the compositor switched workspace, from {} to {}",
+            before.as_deref().unwrap_or("none"),
+            after.as_deref().unwrap_or("none")
+        ),
+        Ok(_) => println!("  -> the focused workspace didn't change"),
+        Err(err) => debug!("Failed to get focused workspace after swipe: {err:#}"),
+    }
+}
+
+fn expected_action(fingers: Fingers) -> &'static str {
+    match fingers {
+        Fingers::One => "this moves the cursor rather than performing a gesture",
+        Fingers::Two => {
+            "libinput treats this as scrolling, so the window under the cursor may scroll"
+        }
+        Fingers::Three => {
+            "many compositors switch workspace or navigate back/forward with this (e.g. GNOME)"
+        }
+        Fingers::Four => {
+            "some compositors switch workspace or open an overview with this (e.g. KDE Plasma)"
+        }
+        Fingers::Five => "few compositors use this by default, so nothing may happen",
+    }
+}
+
+/// Touches down, moves `gesture.distance` over `gesture.duration`, then lifts
+/// the fingers
+///
+/// `gesture.interval` is ignored.
+pub async fn perform(config: &Config, sink: &mut Sink, gesture: &LoopGesture) -> Result<()> {
+    let (unit_x, unit_y) = gesture.direction.unit();
+    let steps = (gesture.duration.as_millis() / FRAME_INTERVAL.as_millis()).max(1);
+    let steps = i32::try_from(steps).unwrap_or(i32::MAX);
+
+    let touch = Touch::down(config, sink, gesture.fingers)?;
+    let mut interval = tokio::time::interval(FRAME_INTERVAL);
+    // the first tick completes immediately, which gives the touch down its own
+    // frame before any movement
+    interval.tick().await;
+    for step in 1..=steps {
+        interval.tick().await;
+        let travelled =
+            i32::try_from(i64::from(gesture.distance) * i64::from(step) / i64::from(steps))
+                .expect("interpolated value should be between 0 and `distance`");
+        touch.emit_position(config, sink, unit_x * travelled, unit_y * travelled)?;
+    }
+    touch.lift(config, sink)
+}
@@ -0,0 +1,176 @@
+use std::{collections::BTreeMap, io, iter};
+
+use clap::Parser;
+use evdev::{
+    AbsoluteAxisType, AttributeSetRef, InputEvent, InputEventKind, Key, RelativeAxisType,
+    Synchronization,
+};
+
+use crate::{config::Config, source::Source, trackpad::Sink, Args};
+
+/// Builds the config which fukomaster would run with, given `args`
+pub fn config(args: &[&str]) -> Config {
+    let args = Args::try_parse_from(iter::once("fukomaster").chain(args.iter().copied()))
+        .expect("args should parse");
+    crate::configure(args).expect("args should be valid").0
+}
+
+/// Stand-in for a mouse, which can be made to fail to ungrab
+#[derive(Debug, Default)]
+pub struct TestSource {
+    pub grabbed: bool,
+    pub fail_ungrab: bool,
+}
+
+impl Source for TestSource {
+    fn name(&self) -> Option<&str> {
+        Some("test mouse")
+    }
+
+    fn grab(&mut self) -> io::Result<()> {
+        self.grabbed = true;
+        Ok(())
+    }
+
+    fn ungrab(&mut self) -> io::Result<()> {
+        if self.fail_ungrab {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        self.grabbed = false;
+        Ok(())
+    }
+
+    fn supported_relative_axes(&self) -> Option<&AttributeSetRef<RelativeAxisType>> {
+        None
+    }
+
+    fn supported_absolute_axes(&self) -> Option<&AttributeSetRef<AbsoluteAxisType>> {
+        None
+    }
+
+    fn get_abs_state(&self) -> io::Result<[libc::input_absinfo; libc::ABS_CNT]> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Finger touching the trackpad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contact {
+    /// Tracking ID of the finger, which the type A protocol doesn't have
+    pub tracking_id: Option<i32>,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// What a consumer of the virtual trackpad sees, rebuilt from the frames
+/// emitted to it
+#[derive(Debug, Default)]
+pub struct Trackpad {
+    slot: i32,
+    /// Finger in each slot, by slot
+    pub contacts: BTreeMap<i32, Contact>,
+    /// Codes of the keys which are pressed
+    pressed: Vec<u16>,
+    /// Every frame seen so far
+    pub frames: Vec<Vec<InputEvent>>,
+}
+
+impl Trackpad {
+    /// Applies the frames emitted to `sink` since it was last read
+    pub fn read(&mut self, sink: &mut Sink) {
+        for frame in sink.take_frames() {
+            self.read_frame(frame);
+        }
+    }
+
+    /// Applies a single frame
+    pub fn read_frame(&mut self, frame: Vec<InputEvent>) {
+        self.apply(&frame);
+        self.frames.push(frame);
+    }
+
+    fn apply(&mut self, frame: &[InputEvent]) {
+        // type A reports every finger in every frame, each ending with a
+        // `SYN_MT_REPORT`
+        let mut reported = None::<Vec<Contact>>;
+        let mut pending = None::<Contact>;
+        let new_contact = || Contact {
+            tracking_id: None,
+            x: 0,
+            y: 0,
+        };
+        for event in frame {
+            let value = event.value();
+            match event.kind() {
+                InputEventKind::Synchronization(Synchronization::SYN_MT_REPORT) => {
+                    reported.get_or_insert_with(Vec::new).extend(pending.take());
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_SLOT) => self.slot = value,
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TRACKING_ID) if value < 0 => {
+                    self.contacts.remove(&self.slot);
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_TRACKING_ID) => {
+                    self.contacts.insert(
+                        self.slot,
+                        Contact {
+                            tracking_id: Some(value),
+                            ..new_contact()
+                        },
+                    );
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
+                    pending.get_or_insert_with(new_contact).x = value;
+                    if let Some(contact) = self.contacts.get_mut(&self.slot) {
+                        contact.x = value;
+                    }
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
+                    pending.get_or_insert_with(new_contact).y = value;
+                    if let Some(contact) = self.contacts.get_mut(&self.slot) {
+                        contact.y = value;
+                    }
+                }
+                InputEventKind::Key(key) => {
+                    self.pressed.retain(|&code| code != key.code());
+                    if value != 0 {
+                        self.pressed.push(key.code());
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(reported) = reported {
+            self.contacts = (0..).zip(reported).collect();
+        }
+    }
+
+    /// Gets how many fingers are touching
+    pub fn fingers(&self) -> usize {
+        self.contacts.len()
+    }
+
+    /// Gets if `key` is pressed
+    pub fn pressed(&self, key: Key) -> bool {
+        self.pressed.contains(&key.code())
+    }
+
+    /// Gets the tracking ID of each finger, by slot
+    pub fn tracking_ids(&self) -> Vec<Option<i32>> {
+        self.contacts
+            .values()
+            .map(|contact| contact.tracking_id)
+            .collect()
+    }
+
+    /// Gets the mean position of the fingers, or `None` if none are touching
+    pub fn position(&self) -> Option<(i32, i32)> {
+        let len = i32::try_from(self.contacts.len())
+            .ok()
+            .filter(|&len| len > 0)?;
+        let (x, y) = self
+            .contacts
+            .values()
+            .fold((0, 0), |(x, y), contact| (x + contact.x, y + contact.y));
+        Some((x / len, y / len))
+    }
+}
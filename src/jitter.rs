@@ -0,0 +1,35 @@
+use log::info;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Random offsets added to emitted finger positions, imitating the noise in
+/// the positions that a real trackpad reports
+#[derive(Debug)]
+pub struct Jitter {
+    amount: i32,
+    rng: StdRng,
+}
+
+impl Jitter {
+    /// Creates jitter of up to `amount` trackpad units on each axis
+    ///
+    /// With the same `seed`, the same sequence of offsets is generated. If
+    /// there is no seed, a random one is picked and logged, so that the run
+    /// can be reproduced.
+    pub fn new(amount: u32, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(rand::random);
+        info!("Jittering positions by up to {amount} units with seed {seed}");
+        Self {
+            amount: i32::try_from(amount).unwrap_or(i32::MAX),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Picks the offset for the next position, uniformly between `-amount`
+    /// and `amount` on each axis
+    pub fn next_offset(&mut self) -> (i32, i32) {
+        (
+            self.rng.gen_range(-self.amount..=self.amount),
+            self.rng.gen_range(-self.amount..=self.amount),
+        )
+    }
+}
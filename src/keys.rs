@@ -0,0 +1,46 @@
+use std::str::FromStr;
+
+use evdev::Key;
+
+/// Highest key code, `KEY_MAX` from the kernel headers
+pub const KEY_MAX: u16 = 0x2ff;
+
+/// Gets the name of `key` from the kernel's input event codes, like
+/// `BTN_SIDE`, or `None` if it has no name, like many vendor-specific buttons
+///
+/// Codes with more than one name, like `BTN_MISC` and `BTN_0`, get the first
+/// one which evdev knows of.
+pub fn name(key: Key) -> Option<String> {
+    // evdev's `Debug` falls back to a description for codes it has no name
+    // for, which doesn't parse back to the key
+    let name = format!("{key:?}");
+    (Key::from_str(&name) == Ok(key)).then_some(name)
+}
+
+/// Describes `key` by its name and code, like `BTN_SIDE (key code 275)`
+pub fn describe(key: Key) -> String {
+    match name(key) {
+        Some(name) => format!("{name} (key code {})", key.code()),
+        None => format!("unnamed key (key code {})", key.code()),
+    }
+}
+
+/// Parses a key given either by its code, like `275`, or by its name, like
+/// `BTN_SIDE`, ignoring case
+pub fn parse_code(s: &str) -> Result<u16, String> {
+    if let Ok(code) = s.parse::<u16>() {
+        return Ok(code);
+    }
+    Key::from_str(&s.to_ascii_uppercase())
+        .map(Key::code)
+        .map_err(|_| format!("`{s}` is neither a key code nor the name of a key like `BTN_SIDE`"))
+}
+
+/// Prints the code and name of every key which has a name
+pub fn print_all() {
+    for key in (0..=KEY_MAX).map(Key::new) {
+        if let Some(name) = name(key) {
+            println!("{:>4}  {name}", key.code());
+        }
+    }
+}
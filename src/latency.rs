@@ -0,0 +1,59 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
+
+/// How many of the most recent samples are kept for the rolling statistics
+const MAX_SAMPLES: usize = 1024;
+
+/// Rolling record of the delay between a source device reporting motion and
+/// the matching frame being emitted to the virtual trackpad
+#[derive(Debug, Default)]
+pub struct Latency {
+    samples: VecDeque<Duration>,
+}
+
+/// Statistics over the recorded latency samples
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    pub samples: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+}
+
+impl Latency {
+    /// Records that motion which the source reported at `input_time` has just
+    /// been emitted, returning the delay
+    ///
+    /// If `input_time` is in the future, e.g. because the source device
+    /// timestamps with a different clock, the delay is counted as zero.
+    pub fn record(&mut self, input_time: SystemTime) -> Duration {
+        let delay = input_time.elapsed().unwrap_or_default();
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(delay);
+        delay
+    }
+
+    /// Computes statistics over the recent samples, or `None` if there are
+    /// none yet
+    pub fn summary(&self) -> Option<Summary> {
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let samples = sorted.len();
+        if samples == 0 {
+            return None;
+        }
+        let percentile = |p: usize| sorted[(samples * p / 100).min(samples - 1)];
+
+        let total = sorted.iter().sum::<Duration>();
+        Some(Summary {
+            samples,
+            mean: total / u32::try_from(samples).unwrap_or(u32::MAX),
+            p50: percentile(50),
+            p99: percentile(99),
+        })
+    }
+}
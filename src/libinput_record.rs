@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, MiscType, PropType};
+
+use crate::{
+    evemu, keys,
+    trackpad::{self, TrackpadSpec},
+};
+
+/// Version of the `libinput record` file format which is written
+const FORMAT_VERSION: u32 = 1;
+
+/// Records emitted events in the YAML format of `libinput record`, so that
+/// they can be replayed with `libinput replay`
+///
+/// See <https://wayland.freedesktop.org/libinput/doc/latest/tools.html>.
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    start: Option<libc::timeval>,
+    /// Time of the last frame, in microseconds since the start
+    last_frame: Option<i64>,
+}
+
+impl Recorder {
+    /// Starts a recording at `path` (or stdout if `-`), writing the device
+    /// description as the header
+    ///
+    /// `dev_node` is the virtual trackpad's event node, if it's known.
+    pub fn create(path: &Path, spec: &TrackpadSpec, dev_node: Option<&Path>) -> io::Result<Self> {
+        let mut out = evemu::open_output(path)?;
+        describe(spec, dev_node, &mut out)?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            start: None,
+            last_frame: None,
+        })
+    }
+}
+
+impl trackpad::Recorder for Recorder {
+    fn record(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let Some(last) = events.last() else {
+            return Ok(());
+        };
+        let time = last.as_ref().time;
+        let start = *self.start.get_or_insert(time);
+        let micros = |time: libc::timeval| {
+            (time.tv_sec - start.tv_sec) * 1_000_000 + (time.tv_usec - start.tv_usec)
+        };
+
+        writeln!(self.out, "  - evdev:")?;
+        for event in events {
+            let micros = micros(event.as_ref().time);
+            let name = match event.event_type() {
+                EventType::KEY => format!(
+                    "EV_KEY / {}",
+                    keys::name(Key::new(event.code())).unwrap_or_else(|| "?".to_owned())
+                ),
+                EventType::ABSOLUTE => format!("EV_ABS / {:?}", AbsoluteAxisType(event.code())),
+                EventType::MISC => format!("EV_MSC / {:?}", MiscType(event.code())),
+                EventType::SYNCHRONIZATION => "EV_SYN".to_owned(),
+                event_type => format!("{event_type:?}"),
+            };
+            writeln!(
+                self.out,
+                "    - [{:3}, {:6}, {:3}, {:3}, {:6}] # {name}",
+                micros / 1_000_000,
+                micros % 1_000_000,
+                event.event_type().0,
+                event.code(),
+                event.value()
+            )?;
+        }
+        let micros = micros(time);
+        let since_last = micros - self.last_frame.replace(micros).unwrap_or(micros);
+        writeln!(
+            self.out,
+            "    - [{:3}, {:6},   0,   0,      0] # ------------ SYN_REPORT (0) ---------- +{}ms",
+            micros / 1_000_000,
+            micros % 1_000_000,
+            since_last / 1000
+        )?;
+        self.out.flush()
+    }
+}
+
+/// Writes everything in the recording up to its events: the system it was
+/// recorded on, and the capabilities of the virtual trackpad
+fn describe(spec: &TrackpadSpec, dev_node: Option<&Path>, w: &mut impl Write) -> io::Result<()> {
+    let id = &spec.input_id;
+    writeln!(w, "# libinput record")?;
+    writeln!(w, "# recorded by fukomaster, not by libinput itself")?;
+    writeln!(w, "version: {FORMAT_VERSION}")?;
+    writeln!(w, "ndevices: 1")?;
+    writeln!(w, "libinput:")?;
+    writeln!(w, "  version: \"unknown\"")?;
+    writeln!(w, "  git: \"unknown\"")?;
+    writeln!(w, "system:")?;
+    writeln!(w, "  os: {:?}", os())?;
+    writeln!(
+        w,
+        "  kernel: {:?}",
+        read_trimmed("/proc/sys/kernel/osrelease")
+    )?;
+    writeln!(w, "  dmi: {:?}", read_trimmed("/sys/class/dmi/id/modalias"))?;
+    writeln!(w, "devices:")?;
+    match dev_node {
+        Some(dev_node) => writeln!(w, "- node: {}", dev_node.display())?,
+        None => writeln!(w, "- node: unknown")?,
+    }
+
+    writeln!(w, "  evdev:")?;
+    evemu::write_capabilities(spec, w, "    # ")?;
+    writeln!(w, "    name: {:?}", spec.name)?;
+    writeln!(
+        w,
+        "    id: [{}, {}, {}, {}]",
+        id.bus_type().0,
+        id.vendor(),
+        id.product(),
+        id.version()
+    )?;
+    let mut abs_axes = spec.abs_axes.iter().collect::<Vec<_>>();
+    abs_axes.sort_by_key(|axis| axis.code());
+    writeln!(w, "    codes:")?;
+    writeln!(w, "      0: [0] # EV_SYN")?;
+    writeln!(
+        w,
+        "      1: {} # EV_KEY",
+        list(spec.keys.iter().map(Key::code))
+    )?;
+    writeln!(
+        w,
+        "      3: {} # EV_ABS",
+        list(abs_axes.iter().map(|axis| axis.code()))
+    )?;
+    if spec.misc.iter().next().is_some() {
+        writeln!(
+            w,
+            "      4: {} # EV_MSC",
+            list(spec.misc.iter().map(|misc| misc.0))
+        )?;
+    }
+    writeln!(w, "    absinfo:")?;
+    for axis in abs_axes {
+        let info = axis.absinfo();
+        writeln!(
+            w,
+            "      {}: [{}, {}, {}, {}, {}]",
+            axis.code(),
+            info.minimum(),
+            info.maximum(),
+            info.fuzz(),
+            info.flat(),
+            info.resolution()
+        )?;
+    }
+    writeln!(
+        w,
+        "    properties: {}",
+        list(spec.properties.iter().map(|prop| prop.0))
+    )?;
+
+    // the properties which udev gives the device, which `libinput replay`
+    // checks the replayed device against
+    writeln!(w, "  udev:")?;
+    writeln!(w, "    properties:")?;
+    writeln!(w, "    - ID_INPUT=1")?;
+    if spec.properties.contains(PropType::POINTER) {
+        writeln!(w, "    - ID_INPUT_TOUCHPAD=1")?;
+    }
+    if spec.properties.contains(PropType::DIRECT) {
+        writeln!(w, "    - ID_INPUT_TOUCHSCREEN=1")?;
+    }
+    writeln!(w, "  events:")?;
+    Ok(())
+}
+
+fn list(items: impl IntoIterator<Item = u16>) -> String {
+    let items = items
+        .into_iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+fn read_trimmed(path: &str) -> String {
+    fs::read_to_string(path)
+        .map(|contents| contents.trim().to_owned())
+        .unwrap_or_default()
+}
+
+/// Gets the OS as `<id>:<version>`, like `libinput record` does
+fn os() -> String {
+    let os_release = read_trimmed("/etc/os-release");
+    let field = |name: &str| {
+        os_release
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+            .map_or("unknown", |value| value.trim_matches('"'))
+    };
+    format!("{}:{}", field("ID"), field("VERSION_ID"))
+}
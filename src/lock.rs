@@ -0,0 +1,69 @@
+use std::{
+    env,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+const LOCK_FILE_NAME: &str = "fukomaster.lock";
+
+/// Gets the default path of the lock file
+///
+/// This is under `$XDG_RUNTIME_DIR` if it is set, and `/run` otherwise.
+pub fn default_path() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map_or_else(|| PathBuf::from("/run"), PathBuf::from)
+        .join(LOCK_FILE_NAME)
+}
+
+/// Exclusive lock held by this process for as long as it is running
+///
+/// The lock is released by the kernel when the file is closed, which happens
+/// when this value is dropped or the process exits for any reason.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Acquires the lock file at `path`, failing if another process already holds
+/// it
+///
+/// On success, our PID is written into the lock file so that other instances
+/// can report who holds the lock.
+pub fn acquire(path: &Path) -> Result<InstanceLock> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("failed to open lock file {}", path.display()))?;
+
+    // SAFETY: `file` is an open file descriptor for the duration of the call
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::WouldBlock {
+            return Err(err).with_context(|| format!("failed to lock {}", path.display()));
+        }
+
+        let mut pid = String::new();
+        let _ = file.read_to_string(&mut pid);
+        match pid.trim() {
+            "" => bail!("another instance of fukomaster is already running (lock file {path:?})"),
+            pid => bail!(
+                "another instance of fukomaster is already running with PID {pid} (lock file \
+                 {path:?})"
+            ),
+        }
+    }
+
+    file.set_len(0)
+        .and_then(|()| file.rewind())
+        .and_then(|()| writeln!(file, "{}", std::process::id()))
+        .with_context(|| format!("failed to write PID to lock file {}", path.display()))?;
+
+    Ok(InstanceLock { _file: file })
+}
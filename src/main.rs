@@ -1,21 +1,88 @@
 #![doc = include_str!("../README.md")]
 
+mod accel;
+mod auto_tune;
+mod backlog;
+mod bench;
+mod bindings;
+mod blacklist;
+mod caps;
+mod config;
+mod control;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod dropped;
+mod dump;
+mod evemu;
+#[cfg(feature = "focus")]
+mod focus;
+mod gesture_loop;
+#[cfg(test)]
+mod harness;
+mod jitter;
+mod keys;
+mod latency;
+mod libinput_record;
+mod lock;
+mod mirror;
+#[cfg(feature = "notifications")]
+mod notifications;
+mod passthrough;
+mod pointer;
+mod preset;
+mod recording;
+mod remote;
+mod sched;
+#[cfg(feature = "lock-detect")]
+mod screen_lock;
+mod setup;
+mod source;
 mod states;
 mod swipe;
+mod swipe_command;
+mod template;
+mod trackpad;
+mod visualize;
+mod watch;
 
 use std::{
     fs,
-    path::{Path, PathBuf},
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
 
 use evdev::Key;
 use futures::never::Never;
 use log::{debug, info, warn};
-use notify::Watcher;
-use tokio::sync::mpsc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+};
+use tracing_subscriber::{filter::LevelFilter, fmt::format::FmtSpan, EnvFilter};
+
+use crate::{
+    accel::AccelProfile,
+    backlog::InputQueue,
+    bindings::Triggers,
+    caps::DeviceCaps,
+    config::{
+        AxisFilter, Config, DirectionGains, Drag, DragLock, ListenGestures, Physical, Stick,
+        Thresholds, Turbo, Warmup,
+    },
+    gesture_loop::{Direction, LoopGesture},
+    recording::Recording,
+    states::{Clock, Fingers, GrabTiming, LiftOrder, MotionSource, RetouchId, TrackingIds},
+    swipe_command::SwipeCommand,
+    template::Template,
+    trackpad::{Bus, DeviceType, MtProtocol, Origin, TrackpadSpec},
+    watch::WatchMode,
+};
 
 /// Simulate a trackpad with your physical mouse
 ///
@@ -27,7 +94,11 @@ use tokio::sync::mpsc;
 /// This tool works on `uinput` key codes. Use `wev` to test which button on
 /// your mouse you want to use for activation. For the MX Master 3S, the mouse
 /// gesture button has key code `277`.
+///
+/// Sending fukomaster `SIGUSR1` logs everything it is tracking, which is
+/// useful to include in bug reports.
 #[derive(Debug, Clone, clap::Parser)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Args {
     /// Input device files to read inputs from (e.g. `/dev/input/event1`)
     ///
@@ -36,39 +107,480 @@ pub struct Args {
     /// read.
     #[arg(short = 'i')]
     pub input_allow: Vec<PathBuf>,
-    /// Input device files to *never* read inputs from (e.g. `/dev/input/event1`)
+    /// Input device files to *never* read inputs from (e.g.
+    /// `/dev/input/event1`)
     ///
     /// Any devices given under this option will never be read for inputs, even
     /// if they appear in the `-i` list.
     #[arg(short = 'I')]
     pub input_deny: Vec<PathBuf>,
+    /// Only read inputs from devices which look like one of these kinds
+    ///
+    /// The kind of a device is guessed from the events it supports:
+    /// - `keyboard`: at least 20 keyboard keys, and not a pointer or touchpad
+    ///   (mice often have a few keys for their extra buttons)
+    /// - `pointer`: relative X and Y axes, like a mouse or trackball
+    /// - `touchpad`: multi-touch position axes and `BTN_TOOL_FINGER`
+    /// - `gamepad`: a gamepad's south face button
+    ///
+    /// These are combined with `-i` and `-I`, so a device must pass both the
+    /// path and capability filters. Unlike paths, these stay correct when
+    /// device files are renumbered across reboots.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub allow_caps: Vec<DeviceCaps>,
+    /// Never read inputs from devices which look like any of these kinds
+    ///
+    /// See `--allow-caps` for how kinds are guessed. This takes priority over
+    /// `--allow-caps`.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub deny_caps: Vec<DeviceCaps>,
+    /// Device file to read swipe trigger keys from (e.g. a foot pedal)
+    ///
+    /// If set, swipes only start when a trigger key is pressed on this
+    /// device.
+    #[arg(long)]
+    pub trigger_device: Option<PathBuf>,
+    /// Device file to read swipe motion from (e.g. a mouse)
+    ///
+    /// If set, swipes are moved only by this device's motion, no matter which
+    /// device started them, and this is the device which gets grabbed. Use
+    /// with `--trigger-device` to start swipes with one device and move them
+    /// with another.
+    #[arg(long)]
+    pub motion_device: Option<PathBuf>,
+    /// Which devices' motion moves a swipe in progress
+    ///
+    /// - `strict`: only the device which started the swipe
+    /// - `any`: any tracked device, for starting swipes with a key on one
+    ///   device (e.g. a keyboard) and moving them with another (e.g. a mouse)
+    ///   without having to know which device files they are
+    ///
+    /// With `any`, the device which started the swipe is still the one that
+    /// gets grabbed, so the other device's motion also moves the cursor.
+    /// Ignored if `--motion-device` is set, since then only that device moves
+    /// swipes.
+    #[arg(long, value_enum, default_value_t = MotionSource::Strict)]
+    pub motion_source: MotionSource,
+    /// Only start swipes while this file exists
+    ///
+    /// The file is checked every time a swipe would start, so another process
+    /// (e.g. a policy daemon on a shared machine) can enable and disable
+    /// gestures by creating and removing it.
+    #[arg(long)]
+    pub enable_file: Option<PathBuf>,
+    /// Only start swipes while a window of this app is focused (can be given
+    /// more than once)
+    ///
+    /// The app is the Wayland app ID, or the class of X11 windows. The focused
+    /// window is asked from the compositor every time a swipe would start,
+    /// which is only supported on Sway and Hyprland. If it can't be found out,
+    /// swipes start no matter which window is focused.
+    #[cfg(feature = "focus")]
+    #[arg(long, value_name = "APP")]
+    pub focus_app: Vec<String>,
     /// Key code which activates 2-finger swiping mode
-    #[arg(short = '2')]
+    ///
+    /// This and the other key options take either a key code or the key's
+    /// name from the kernel's input event codes, like `BTN_SIDE`, ignoring
+    /// case. `fukomaster keys` lists every name.
+    #[arg(short = '2', value_parser = keys::parse_code)]
     pub swipe_2: Option<u16>,
     /// Key code which activates 3-finger swiping mode
-    #[arg(short = '3', default_value = "277")]
+    ///
+    /// Defaults to 277 (the gesture button on many mice). Use 0 to leave 3
+    /// fingers unbound.
+    #[arg(short = '3', default_value = "277", value_parser = keys::parse_code)]
     pub swipe_3: Option<u16>,
     /// Key code which activates 4-finger swiping mode
-    #[arg(short = '4')]
+    #[arg(short = '4', value_parser = keys::parse_code)]
     pub swipe_4: Option<u16>,
     /// Key code which activates 5-finger swiping mode
-    #[arg(short = '5')]
+    #[arg(short = '5', value_parser = keys::parse_code)]
     pub swipe_5: Option<u16>,
+    /// TOML file to read the trigger key codes from, instead of `-2` to `-5`
+    ///
+    /// The file sets a key code for each finger count to bind, e.g.
+    /// `swipe_3 = 277`, and finger counts which it leaves out are unbound.
+    /// Changes to the file apply straight away. If a changed file can't be
+    /// read, binds no finger counts, or binds one key to several finger
+    /// counts, the previous bindings are kept and the error is logged.
+    #[arg(long)]
+    pub bindings_file: Option<PathBuf>,
+    /// Tap with the trigger's fingers when a trigger is pressed and released
+    /// quickly without moving
+    ///
+    /// Some compositors map multi-finger taps to actions, such as showing the
+    /// overview. With this option, pressing a trigger doesn't put the fingers
+    /// down straight away. If the trigger is released within `--tap-window-ms`
+    /// without the mouse moving, the fingers tap the trackpad. As soon as the
+    /// mouse moves, a swipe starts as normal, but that first motion isn't
+    /// grabbed, so it also moves the cursor slightly.
+    #[arg(long)]
+    pub enable_taps: bool,
+    /// Longest time between pressing and releasing a trigger that counts as a
+    /// tap, in milliseconds
+    #[arg(long, default_value_t = 200)]
+    pub tap_window_ms: u64,
+    /// Swipe with the nearest supported finger count when a swipe key's count
+    /// isn't supported by the compositor
+    ///
+    /// Supported counts come from `--supported-fingers`, or the detected
+    /// desktop's preset: GNOME and KDE Plasma recognize 3 and 4 finger swipes,
+    /// and Hyprland and Sway recognize 3 to 5. Two-finger swipes (scrolling)
+    /// are always supported.
+    #[arg(long)]
+    pub finger_fallback: bool,
+    /// Finger counts that the compositor recognizes swipes with, for
+    /// `--finger-fallback` (e.g. `2,3,4`)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u8).range(2..=5)
+    )]
+    pub supported_fingers: Vec<u8>,
+    /// Key code which cancels the current swipe
+    ///
+    /// Normally, releasing the trigger key lifts the fingers, and the
+    /// compositor commits to the gesture (e.g. switches workspace). Pressing
+    /// this key during a swipe instead slides the fingers back to where they
+    /// started before lifting them, so that compositors which follow the
+    /// gesture's progress snap back and do nothing. The return movement takes
+    /// `--boomerang-ms`.
+    #[arg(long, value_parser = keys::parse_code)]
+    pub cancel_key: Option<u16>,
+    /// Key code which starts a held drag
+    ///
+    /// A drag is a swipe with `--drag-fingers` which stays held after this
+    /// key is released, and only ends when `--drag-end-key` is pressed. This
+    /// lets something be dragged a long way over several strokes of the mouse.
+    ///
+    /// The compositor just sees one long swipe. To move windows with it, use a
+    /// drag gesture for this many fingers, such as libinput's three-finger
+    /// drag (libinput 1.27 and later) if the compositor exposes it. Without
+    /// one, the fingers are treated like any other swipe, and some compositors
+    /// may end the gesture early if the fingers stay still for a while.
+    #[arg(long, value_parser = keys::parse_code)]
+    pub drag_key: Option<u16>,
+    /// Key code which ends a held drag
+    ///
+    /// Defaults to `--drag-key`, so that pressing it again ends the drag.
+    #[arg(long, value_parser = keys::parse_code)]
+    pub drag_end_key: Option<u16>,
+    /// How many fingers are held down during a drag
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(2..=5))]
+    pub drag_fingers: u8,
+    /// Key code which performs a one-finger tap-and-drag while held
+    ///
+    /// Pressing it taps once, then touches down again and moves with the
+    /// mouse until it's released, which compositors treat as holding down the
+    /// left button, e.g. to drag files. The compositor must have tap-to-click
+    /// and tap-and-drag enabled for the virtual trackpad.
+    #[arg(long, value_parser = keys::parse_code)]
+    pub drag_lock_key: Option<u16>,
+    /// Time between the end of the `--drag-lock-key` tap and touching down
+    /// again, in milliseconds
+    ///
+    /// The tap lasts one frame. Together, they must fit within the
+    /// compositor's double-tap window, which is 180 ms in libinput, or the
+    /// second touch just moves the cursor.
+    #[arg(long, default_value_t = 50)]
+    pub drag_lock_gap_ms: u64,
+    /// Key code which speeds up the swipe in progress while held
+    ///
+    /// This allows covering large distances quickly, then releasing it for
+    /// precise movement, within the same swipe. Only motion while it's held
+    /// is sped up, so the fingers don't jump when it's pressed or released.
+    #[arg(long, value_parser = keys::parse_code)]
+    pub turbo_key: Option<u16>,
+    /// How much `--turbo-key` multiplies swipe motion by
+    #[arg(long, default_value_t = 3.0)]
+    pub turbo_factor: f32,
+    /// How much the first `--anchor-events` motion events of a swipe are
+    /// multiplied by
+    ///
+    /// Some compositors lock a gesture's direction from its first few
+    /// frames, so a swipe which starts with a slight wobble can be taken the
+    /// wrong way. Boosting the start makes its initial direction clear, after
+    /// which motion settles to normal. The default of 1 leaves motion as it
+    /// is.
+    #[arg(long, default_value_t = 1.0)]
+    pub anchor_boost: f32,
+    /// How many motion events at the start of a swipe `--anchor-boost`
+    /// applies to
+    #[arg(long, default_value_t = 3)]
+    pub anchor_events: u32,
+    /// How far, in source motion units, a swipe must move along an axis
+    /// before motion on that axis is applied
+    ///
+    /// This keeps a swipe from wobbling sideways as it starts. Motion within
+    /// the dead zone is dropped, and once the swipe leaves it, only the motion
+    /// past its edge is applied. Each axis leaves it separately.
+    #[arg(long, default_value_t = 0)]
+    pub dead_zone: u32,
+    /// Dead zone on the X axis, instead of `--dead-zone`
+    #[arg(long)]
+    pub dead_zone_x: Option<u32>,
+    /// Dead zone on the Y axis, instead of `--dead-zone`
+    #[arg(long)]
+    pub dead_zone_y: Option<u32>,
+    /// How much swipe motion is smoothed, from 0 (not at all) to 0.99
+    ///
+    /// Each motion event is blended with the ones before it, weighting them by
+    /// this much, which evens out noisy sensors at the cost of some lag. Any
+    /// motion still being smoothed out when the swipe ends is dropped.
+    #[arg(long, default_value_t = 0.0)]
+    pub smoothing: f32,
+    /// Smoothing on the X axis, instead of `--smoothing`
+    #[arg(long)]
+    pub smoothing_x: Option<f32>,
+    /// Smoothing on the Y axis, instead of `--smoothing`
+    #[arg(long)]
+    pub smoothing_y: Option<f32>,
+    /// Key code which turns mouse motion into scrolling while held
+    ///
+    /// Scrolling is emitted through a companion virtual pointer as
+    /// high-resolution scroll (`REL_WHEEL_HI_RES` and `REL_HWHEEL_HI_RES`),
+    /// which compositors use for smooth scrolling. Legacy wheel notches are
+    /// also sent for every 120 high-resolution units. The mouse's own scroll
+    /// wheel is emitted the same way while it's grabbed, whether it sends
+    /// high-resolution scroll, legacy notches or both.
+    #[arg(long, value_parser = keys::parse_code)]
+    pub scroll_key: Option<u16>,
+    /// How much `--scroll-key` scrolls per unit of mouse motion, in
+    /// high-resolution scroll units (120 per wheel notch)
+    #[arg(long, default_value_t = 1.0)]
+    pub scroll_factor: f32,
+    /// Emit two-finger swipes as scrolling instead of touches
+    ///
+    /// For apps which treat scroll wheel events differently from trackpad
+    /// scroll, or which don't recognize two fingers on the virtual trackpad
+    /// as scrolling at all. While a two-finger trigger is held, motion
+    /// scrolls through the same companion virtual pointer as `--scroll-key`,
+    /// by `--scroll-factor`, and lifting the trigger stops scrolling. Toggled
+    /// swipes, like `--drag-key`, still touch the trackpad.
+    ///
+    /// evdev can't mark where a scroll begins and ends, so compositors see
+    /// high-resolution wheel scrolling rather than a finger scroll with
+    /// phases. The scroll begins with its first event and ends when events
+    /// stop: the kernel drops relative events with a value of zero, so there
+    /// is no stop event, and libinput only reports scroll phases for finger
+    /// and continuous scroll sources, never for wheels. Apps which wait for a
+    /// finger scroll's stop to start kinetic scrolling won't fling after it.
+    #[arg(long)]
+    pub scroll_two_finger: bool,
     /// Resolution of the virtual trackpad
     ///
     /// A larger resolution means you have to move your mouse further to have
     /// the trackpad move the same distance.
     ///
     /// The value is used directly as the resolution of the virtual `uinput`
-    /// device.
-    #[arg(short, long, default_value_t = 12)]
-    pub resolution: u16,
+    /// device. By default, this is picked to suit the desktop environment
+    /// detected from `XDG_CURRENT_DESKTOP`, or 12 if it isn't recognized.
+    #[arg(short, long)]
+    pub resolution: Option<u16>,
+    /// Distance, in millimetres, that libinput needs the fingers to move
+    /// before it recognizes a gesture
+    ///
+    /// When a swipe finishes, its movement is compared against this and
+    /// `--threshold-speed-mm-s`, and whether each was met is logged at the
+    /// debug level. This diagnoses swipes which were too short or slow to
+    /// register. The defaults are approximate, and vary between libinput
+    /// versions and compositors.
+    #[arg(long, default_value_t = 1.5)]
+    pub threshold_distance_mm: f32,
+    /// Speed at release, in millimetres per second, above which compositors
+    /// commit a swipe that is too short to commit otherwise
+    ///
+    /// See `--threshold-distance-mm`.
+    #[arg(long, default_value_t = 100.0)]
+    pub threshold_speed_mm_s: f32,
+    /// Real trackpad to copy the axis ranges and resolution from (e.g.
+    /// `/dev/input/event5`)
+    ///
+    /// The virtual trackpad is created with the same position axes as this
+    /// device, so that swipes feel the same as on the real trackpad. This
+    /// overrides `--resolution`. If the device can't be read, the defaults
+    /// are used instead.
+    #[arg(long)]
+    pub match_device: Option<PathBuf>,
+    /// File descriptor of an already-created `uinput` device to emit events
+    /// through, instead of creating a new virtual trackpad
+    ///
+    /// This lets a privileged helper own `/dev/uinput` and hand the device off
+    /// to fukomaster. The helper creates the device with the capabilities from
+    /// `fukomaster describe-target` (run with the same options), then starts
+    /// fukomaster with the file descriptor inherited. fukomaster takes
+    /// ownership of it, so the device is destroyed when fukomaster exits.
+    /// `--cursor-lock` and `--scroll-key` still create their own pointer, which
+    /// needs access to `/dev/uinput`.
+    #[arg(long)]
+    pub uinput_fd: Option<i32>,
+    /// Kind of input device that the virtual trackpad presents itself as
+    ///
+    /// A trackpad has relative coordinates: fingers touch down in the middle
+    /// of its axes, and only how far they move matters. A touchscreen has
+    /// coordinates mapped onto the screen by the compositor: its axes cover
+    /// `--screen-width` by `--screen-height` pixels, fingers touch down in the
+    /// middle of the screen, and mouse motion (after multipliers) moves them
+    /// that many pixels across it, stopping at the edges.
+    #[arg(long, value_enum, default_value_t = DeviceType::Trackpad)]
+    pub device_type: DeviceType,
+    /// Where (0, 0) is on the virtual trackpad's position axes
+    ///
+    /// Defaults to `center` for a trackpad and `top-left` for a touchscreen.
+    /// Fingers touch down in the middle of the axes and move the same way
+    /// with either; only whether positions can be negative changes. Use
+    /// `top-left` if swipes up or to the left go the wrong way, are mirrored,
+    /// or jump, which happens with consumers that mishandle negative
+    /// positions. Use `center` if swipes on a touchscreen land offset towards
+    /// the bottom-right, which happens with consumers that expect its axes to
+    /// be centered. Swipes which go the wrong way with either origin need
+    /// `--invert-x`/`--invert-y` instead. Has no effect with
+    /// `--match-device`, whose axes are copied as they are.
+    #[arg(long, value_enum)]
+    pub origin: Option<Origin>,
+    /// Fuzz of the virtual trackpad's position axes, in trackpad units
+    ///
+    /// The kernel smooths out changes in a position smaller than twice the
+    /// fuzz, and drops changes smaller than half of it, to filter out noise.
+    /// libinput may take the fuzz over as its own hysteresis instead. Swipes
+    /// move in steps which are already free of noise, so this is only worth
+    /// raising to see how a consumer copes with a noisy real trackpad.
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..))]
+    pub abs_fuzz: i32,
+    /// Flat of the virtual trackpad's position axes, in trackpad units
+    ///
+    /// Positions this close to the middle of an axis are treated as the
+    /// middle by consumers which use it as a deadzone, which is mostly
+    /// joystick drivers; libinput ignores it for trackpads and touchscreens.
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..))]
+    pub abs_flat: i32,
+    /// Bus that the virtual trackpad appears to be connected through
+    ///
+    /// Some compositor and libinput quirks only apply to devices on a certain
+    /// bus, so this can make the virtual trackpad match a real one.
+    #[arg(long, value_enum, default_value_t = Bus::Usb)]
+    pub bus_type: Bus,
+    /// Multi-touch protocol that the virtual trackpad reports fingers with
+    ///
+    /// Type `b` gives each finger a slot and tracking ID, and only reports
+    /// what changed, which is what every modern consumer expects. Type `a`
+    /// reports every finger's position in every frame with `SYN_MT_REPORT`
+    /// between them, and has no slots or tracking IDs, for very old kernels
+    /// and consumers which only understand that. Can't be used with
+    /// `--passthrough-device`.
+    #[arg(long, value_enum, default_value_t = MtProtocol::B)]
+    pub mt_protocol: MtProtocol,
+    /// Report the pressure of each finger (`ABS_MT_PRESSURE`)
+    ///
+    /// Fingers are at full pressure whenever they touch, unless
+    /// `--pressure-ramp-ms` is set. Only useful for compositors which use
+    /// pressure, e.g. to tell a resting palm from a finger.
+    #[arg(long)]
+    pub pressure: bool,
+    /// How long the pressure of each finger takes to ramp up from zero when a
+    /// swipe starts, and back down before the fingers lift, in milliseconds
+    ///
+    /// A real finger's pressure builds as it presses on a trackpad, and a
+    /// consumer may ignore a finger until its pressure passes a threshold, so
+    /// motion while the pressure is ramping up may be partly lost. Swipes
+    /// which end with a flick from `--simulate-physical` lift one finger at a
+    /// time instead of ramping down.
+    #[arg(long, value_name = "MS", default_value_t = 0, requires = "pressure")]
+    pub pressure_ramp_ms: u64,
+    /// Width in pixels of the screen that a `--device-type touchscreen` covers
+    #[arg(long, default_value_t = 1920)]
+    pub screen_width: u16,
+    /// Height in pixels of the screen that a `--device-type touchscreen` covers
+    #[arg(long, default_value_t = 1080)]
+    pub screen_height: u16,
     /// Swipe speed multiplier on the X axis
-    #[arg(short, long, default_value_t = 1.0)]
+    ///
+    /// Negative values invert the axis, and 0 stops the fingers moving along
+    /// it at all.
+    #[arg(short, long, default_value_t = 1.0, allow_negative_numbers = true)]
     pub x_mult: f32,
     /// Swipe speed multiplier on the Y axis
-    #[arg(short, long, default_value_t = 1.0)]
+    ///
+    /// Negative values invert the axis, and 0 stops the fingers moving along
+    /// it at all.
+    #[arg(short, long, default_value_t = 1.0, allow_negative_numbers = true)]
     pub y_mult: f32,
+    /// Invert swipes on the X axis, on top of `--x-mult` and the config file
+    #[arg(long)]
+    pub invert_x: bool,
+    /// Invert swipes on the Y axis, on top of `--y-mult` and the config file
+    #[arg(long)]
+    pub invert_y: bool,
+    /// Swipe speed multiplier for motion which moves the fingers up
+    ///
+    /// The direction gains are applied on top of `--y-mult` and `--x-mult`,
+    /// to each bit of motion as it arrives depending on which way it moves the
+    /// fingers, so that changing direction partway through a swipe only
+    /// affects the motion after it. Useful when a compositor needs the
+    /// fingers to travel further for a gesture one way than the other.
+    #[arg(long, value_name = "GAIN", default_value_t = 1.0)]
+    pub gain_up: f32,
+    /// Swipe speed multiplier for motion which moves the fingers down, like
+    /// `--gain-up`
+    #[arg(long, value_name = "GAIN", default_value_t = 1.0)]
+    pub gain_down: f32,
+    /// Swipe speed multiplier for motion which moves the fingers left, like
+    /// `--gain-up`
+    #[arg(long, value_name = "GAIN", default_value_t = 1.0)]
+    pub gain_left: f32,
+    /// Swipe speed multiplier for motion which moves the fingers right, like
+    /// `--gain-up`
+    #[arg(long, value_name = "GAIN", default_value_t = 1.0)]
+    pub gain_right: f32,
+    /// Config file to read per-finger multipliers from
+    ///
+    /// This is a TOML file with a table for each finger count, whose values
+    /// replace `--x-mult` and `--y-mult` when swiping with that many fingers,
+    /// e.g. `x_mult = 1.5` under `[fingers.3]`.
+    ///
+    /// With the `focus` feature, a table for a monitor (e.g. `scale = 0.5`
+    /// under `[monitors.DP-1]`) scales both multipliers of swipes which start
+    /// while that monitor is focused, for setups with monitors of different
+    /// sizes. Sway and Hyprland are supported, and swipes use the usual
+    /// multipliers if the focused monitor can't be found out.
+    ///
+    /// This can be given more than once, and a file can also list others to
+    /// load before it with `include = ["base.toml"]`, relative to its own
+    /// directory. Files are merged in order, with values in later files
+    /// replacing earlier ones, and a file's own values replacing those of its
+    /// includes.
+    ///
+    /// The files are reloaded when fukomaster receives `SIGHUP`, and the new
+    /// values apply straight away, even to a swipe in progress.
+    #[arg(long)]
+    pub config: Vec<PathBuf>,
+    /// Swipe speed multiplier when the mouse is moving slowly
+    ///
+    /// The gain applied to mouse movement blends from `--gain-low` when the
+    /// mouse is barely moving to `--gain-high` at `--transition-speed`, giving
+    /// precise control at low speeds and fast coverage at high speeds. This is
+    /// applied on top of `--x-mult` and `--y-mult`.
+    #[arg(long, default_value_t = 1.0)]
+    pub gain_low: f32,
+    /// Swipe speed multiplier when the mouse is moving at or above
+    /// `--transition-speed`
+    #[arg(long, default_value_t = 1.0)]
+    pub gain_high: f32,
+    /// Mouse speed, in mouse units per second, at which `--gain-high` is fully
+    /// applied, which must be above 0
+    #[arg(long, default_value_t = 2000.0)]
+    pub transition_speed: f32,
+    /// Accelerate swipes the same way as the cursor, using the desktop's
+    /// mouse acceleration settings
+    ///
+    /// This replaces `--gain-low` and `--gain-high` with libinput's `flat` or
+    /// `adaptive` acceleration profile, at the configured mouse speed. The
+    /// settings are currently only read from GNOME, and must be readable by
+    /// the user running fukomaster. If they can't be read, the gain settings
+    /// are used as normal.
+    #[arg(long)]
+    pub libinput_accel: bool,
     /// Disables grabbing the mouse cursor in `evdev` when swiping
     ///
     /// If grabbing is disabled, the mouse cursor will move with the virtual
@@ -76,6 +588,637 @@ pub struct Args {
     /// which also attempt to grab the mouse.
     #[arg(long)]
     pub no_grab: bool,
+    /// Device to always grab when swiping, even with `--no-grab`
+    ///
+    /// This can be either a device file (e.g. `/dev/input/event1`) or a device
+    /// name (e.g. `Logitech MX Master 3S`).
+    #[arg(long)]
+    pub grab_device: Vec<String>,
+    /// Device to never grab when swiping
+    ///
+    /// This can be either a device file (e.g. `/dev/input/event1`) or a device
+    /// name (e.g. `Logitech MX Master 3S`). Useful for devices which only
+    /// provide the trigger key, such as a keyboard, where grabbing would
+    /// swallow other key presses. Takes priority over `--grab-device`.
+    #[arg(long)]
+    pub skip_grab_device: Vec<String>,
+    /// When a swipe's device is grabbed
+    ///
+    /// - `immediate`: the default, as soon as the trigger is pressed
+    /// - `on-motion`: once the device first moves after the trigger is pressed,
+    ///   so that pressing and releasing the trigger without moving reaches
+    ///   applications as a normal click. The first motion also moves the
+    ///   cursor, since it arrives before the grab (unless `--cursor-lock`
+    ///   cancels it out).
+    ///
+    /// Devices which aren't grabbed at all, e.g. with `--no-grab`, are
+    /// unaffected.
+    #[arg(long, value_enum, default_value_t = GrabTiming::Immediate)]
+    pub grab_timing: GrabTiming,
+    /// Keep the cursor still while swiping with a device that isn't grabbed
+    ///
+    /// When a device isn't grabbed, the compositor sees its motion too, so the
+    /// cursor moves along with the swipe. With this option, a companion
+    /// virtual pointer is created, which moves the cursor by the opposite
+    /// amount to cancel this out. Grabbed devices already don't move the
+    /// cursor, so nothing is emitted for them.
+    ///
+    /// The cancelling motion is accelerated like any other pointer motion, so
+    /// this works best with a flat pointer acceleration profile. Pointer
+    /// constraints (e.g. a game locking the pointer) apply to both devices
+    /// equally, so they're unaffected.
+    #[arg(long)]
+    pub cursor_lock: bool,
+    /// Keep other keys and buttons of a grabbed device working while swiping
+    ///
+    /// A grabbed device's events all go to fukomaster, so pressing its other
+    /// buttons or keys mid-swipe normally does nothing. With this option, they
+    /// are re-emitted through a companion virtual device, along with scroll
+    /// wheel motion. The trigger and cancel keys are not passed through.
+    #[arg(long)]
+    pub passthrough: bool,
+    /// Real trackpad to mirror onto the virtual trackpad, instead of
+    /// simulating gestures with a mouse
+    ///
+    /// The real trackpad is grabbed, and its touches are re-emitted with their
+    /// motion scaled by `--x-mult` and `--y-mult`, which makes a small or slow
+    /// trackpad more sensitive. Trigger keys are not used in this mode.
+    #[arg(long)]
+    pub passthrough_device: Option<PathBuf>,
+    /// How many reads from the swiping device can fail in a row before the
+    /// swipe is stopped
+    ///
+    /// A single failed read is usually transient, so the swipe carries on.
+    /// If the device keeps failing, the fingers are lifted rather than being
+    /// left down on the virtual trackpad. Set to 0 to never stop.
+    #[arg(long, default_value_t = 10)]
+    pub max_read_errors: u32,
+    /// How motion which a device sends faster than it can be handled is dealt
+    /// with
+    ///
+    /// - `drop-oldest`: the default, the oldest queued motion events are added
+    ///   together, so the fingers skip ahead to where the device has moved to
+    ///   and no motion is lost
+    /// - `drop-newest`: the newest queued motion events are dropped, losing
+    ///   their motion
+    /// - `block`: every event is handled in turn, however far behind that falls
+    ///
+    /// Key presses and other events are never dropped or moved past motion.
+    #[arg(long, value_enum, default_value_t = InputQueue::DropOldest)]
+    pub input_queue: InputQueue,
+    /// How many queued motion events in a row are handled one by one before
+    /// `--input-queue` applies
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    pub input_queue_depth: u16,
+    /// Maximum number of events written to the virtual trackpad at once, or 0
+    /// for no limit
+    ///
+    /// A frame with many fingers can hold a lot of events. If the kernel
+    /// limits how much can be written to a `uinput` device at once, larger
+    /// frames are split across several writes. The `SYN_REPORT` is only sent
+    /// at the end, so consumers still see a single frame.
+    #[arg(long, default_value_t = 0)]
+    pub max_batch_events: usize,
+    /// Clock used to timestamp events emitted to the virtual trackpad
+    ///
+    /// All events within a single frame share one timestamp taken from this
+    /// clock.
+    #[arg(long, value_enum, default_value_t = Clock::Monotonic)]
+    pub clock: Clock,
+    /// How tracking IDs are assigned to the virtual fingers
+    ///
+    /// Fingers always use slots 0 to N-1. By default their tracking IDs match
+    /// their slots, but some tools which consume recorded gestures expect the
+    /// kernel's convention of a fresh, increasing tracking ID for every touch.
+    #[arg(long, value_enum, default_value_t = TrackingIds::Slot)]
+    pub tracking_ids: TrackingIds,
+    /// Order of the events which lift the fingers at the end of a gesture
+    ///
+    /// Try changing this if the compositor behaves oddly when a swipe ends:
+    /// - `all-at-once`: the default, which suits libinput
+    /// - `reverse`: lifts the last slot first, for consumers which expect slot
+    ///   0 (the first finger down) to be the last one up
+    /// - `btn-touch-last`: also releases `BTN_TOUCH`, after all tracking IDs
+    ///   are cleared, for when a gesture seems to carry on after the fingers
+    ///   are lifted, as if a finger is still down
+    ///
+    /// Swipes which end with a flick from `--simulate-physical` always lift
+    /// one finger at a time.
+    #[arg(long, value_enum, default_value_t = LiftOrder::AllAtOnce)]
+    pub lift_order: LiftOrder,
+    /// Distance, in trackpad units, that the fingers can move from where they
+    /// touched down before they are put back down there
+    ///
+    /// This allows endless swipes and scrolling on a virtual trackpad with
+    /// limited axes, such as with `--match-device` or `--device-type
+    /// touchscreen`. The fingers are briefly lifted and put back down in the
+    /// middle of the axes, keeping the same tracking IDs and `BTN_TOOL_*`
+    /// state so that the compositor can continue the same gesture, unless
+    /// `--retouch-id new` is used.
+    #[arg(long)]
+    pub recenter_distance: Option<u32>,
+    /// Whether fingers put back down by `--recenter-distance` keep their
+    /// tracking IDs
+    ///
+    /// - `reuse`: the default, which keeps the same gesture going, for endless
+    ///   swipes and scrolling
+    /// - `new`: lifts the fingers completely and puts them down with new
+    ///   tracking IDs, so the compositor ends the gesture and starts another,
+    ///   e.g. to switch workspace once per recenter while the mouse keeps
+    ///   moving
+    ///
+    /// With `--tracking-ids slot`, the new IDs are the same numbers as before,
+    /// but the fingers still lift in between, so it's a new gesture all the
+    /// same. A swipe which continues held fingers never retouches them.
+    #[arg(long, value_enum, default_value_t = RetouchId::Reuse)]
+    pub retouch_id: RetouchId,
+    /// Scale swipe distance so that mouse travel grows logarithmically with
+    /// it, with the slope of the curve doubling every `BASE` trackpad units
+    ///
+    /// Small motions move the fingers as much as without this, so they stay
+    /// precise, but the further the fingers are from where they touched down,
+    /// the faster they move, so that large swipes don't need large mouse
+    /// travel. The curve only depends on position, so moving back retraces it
+    /// exactly. Without this, motion is scaled linearly.
+    #[arg(long, value_name = "BASE")]
+    pub log_scale: Option<f32>,
+    /// Bend swipes into an arc which turns this many degrees for every 1000
+    /// trackpad units that the fingers travel
+    ///
+    /// Some gesture recognizers respond to curved rather than straight finger
+    /// paths. Positive values bend clockwise, and negative values
+    /// counterclockwise. The fingers travel the same distance as without
+    /// this, and all move along the same arc. Without this, straight mouse
+    /// motion moves the fingers in a straight line.
+    #[arg(long, value_name = "DEGREES", allow_negative_numbers = true)]
+    pub curvature: Option<f32>,
+    /// Split moves which would jump the fingers further than this, in
+    /// trackpad units, into smaller steps spread out over a frame
+    ///
+    /// A mouse with a low polling rate, or a fast flick, can move the fingers
+    /// a long way in one event, which looks jerky. The fingers always end up
+    /// where they would have without this.
+    #[arg(long, value_name = "MAX_STEP", value_parser = clap::value_parser!(u32).range(1..))]
+    pub interpolate: Option<u32>,
+    /// Move the fingers this far on the X axis, in trackpad units, straight
+    /// after they touch down for a swipe
+    ///
+    /// This is an experimental workaround for compositors which misdetect the
+    /// direction of a swipe because they treat it as starting slightly off.
+    /// Most setups don't need it.
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub start_offset_x: i32,
+    /// Move the fingers this far on the Y axis, in trackpad units, straight
+    /// after they touch down for a swipe
+    ///
+    /// See `--start-offset-x`.
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    pub start_offset_y: i32,
+    /// Move the fingers back to where they started before lifting them
+    ///
+    /// Some compositors cancel a gesture rather than committing it if the
+    /// fingers are lifted far from where they touched down. With this option,
+    /// releasing the trigger first slides the fingers back to the start
+    /// position over `--boomerang-ms`, then lifts them.
+    #[arg(long)]
+    pub boomerang: bool,
+    /// Make fast swipes end like a flick on a real trackpad
+    ///
+    /// If the mouse is still moving quickly when the trigger is released, the
+    /// fingers carry on moving and slow down over a few frames, then lift off
+    /// one at a time instead of all together. Slow swipes end as normal. Has
+    /// no effect with `--boomerang`.
+    #[arg(long)]
+    pub simulate_physical: bool,
+    /// How easily a release counts as a flick with `--simulate-physical`
+    ///
+    /// The mouse speed at release is multiplied by this before comparing it to
+    /// the flick threshold, so larger values turn slower movements into flicks
+    /// as well.
+    #[arg(long, default_value_t = 1.0)]
+    pub flick_sensitivity: f32,
+    /// How long the `--boomerang` and `--cancel-key` return movements take, in
+    /// milliseconds
+    #[arg(long, default_value_t = 100)]
+    pub boomerang_ms: u64,
+    /// How long the fingers rest at their final position before lifting when
+    /// a swipe ends, in milliseconds
+    ///
+    /// Works around compositors which only commit a gesture once its end
+    /// position has been held briefly. While resting, a frame with only a
+    /// timestamp (`MSC_TIMESTAMP`) is emitted every frame interval, or with
+    /// `--mt-protocol a`, a frame reporting the fingers where they are, so the
+    /// compositor keeps receiving frames with the fingers still. Since the
+    /// fingers stop while resting, swipes never end as flicks with
+    /// `--simulate-physical` when this is set.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub hold_end_ms: u64,
+    /// Lift every finger again shortly after a gesture ends
+    ///
+    /// Works around compositors occasionally acting as if a finger is still
+    /// down after a swipe. After `--post-stop-clear-ms`, every slot is lifted
+    /// and `BTN_TOUCH` is released again, which has no effect if nothing was
+    /// stuck.
+    #[arg(long)]
+    pub post_stop_clear: bool,
+    /// How long after a gesture ends to lift the fingers again with
+    /// `--post-stop-clear`, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 50)]
+    pub post_stop_clear_ms: u64,
+    /// Merge swipes in the same direction which start within this many
+    /// milliseconds of each other into one gesture, or 0 to never merge
+    ///
+    /// Compositors may ignore a second swipe straight after the first, e.g.
+    /// when switching two workspaces in a row. With this option, the fingers
+    /// stay down for this long after the trigger is released, and a swipe
+    /// started in that time with the same finger count carries on moving
+    /// them. If it moves the other way, the fingers are lifted and touch down
+    /// again instead. The gesture only ends once the fingers are lifted, so a
+    /// swipe's action is delayed by this long. With `--hold-end-ms`, the
+    /// fingers rest before they're held. Has no effect with `--boomerang` or
+    /// `--simulate-physical`.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub merge_window_ms: u64,
+    /// How long to wait after the fingers touch down before moving them, in
+    /// milliseconds
+    ///
+    /// Some compositors treat fingers which move straight after touching down
+    /// as a multi-finger tap rather than a swipe. Motion during this delay is
+    /// held back, then applied all at once with the first motion after it.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub emit_delay: u64,
+    /// Lock file used to prevent multiple instances running at once
+    ///
+    /// Defaults to `fukomaster.lock` under `$XDG_RUNTIME_DIR` if it is set, or
+    /// under `/run` otherwise. If another instance already holds this lock,
+    /// this instance refuses to start.
+    #[arg(long)]
+    pub lock_file: Option<PathBuf>,
+    /// How many times to retry listing devices under `/dev/input` at startup
+    ///
+    /// When started early in boot, `/dev/input` may not be ready yet. Retries
+    /// are made with an increasing delay between them.
+    #[arg(long, default_value_t = 5)]
+    pub startup_retries: u32,
+    /// Run with this real-time priority (`SCHED_FIFO`), from 1 to 99
+    ///
+    /// Keeps other processes from preempting fukomaster while it's handling
+    /// input, for more consistent gesture latency. Needs `CAP_SYS_NICE` or a
+    /// high enough `RLIMIT_RTPRIO`; without them, fukomaster warns and runs
+    /// with its usual priority.
+    #[arg(long, value_name = "PRIORITY", value_parser = clap::value_parser!(u8).range(1..=99))]
+    pub rt_priority: Option<u8>,
+    /// Only run on these CPU cores, separated by commas (e.g. `2,3`)
+    ///
+    /// If this can't be applied, fukomaster warns and runs on any core.
+    #[arg(long, value_name = "CPUS", value_delimiter = ',', value_parser = sched::parse_cpu)]
+    pub cpu_affinity: Vec<usize>,
+    /// How long to wait for more device changes before acting on one, in
+    /// milliseconds, or 0 to act on each change immediately
+    ///
+    /// Devices being reset may be removed and created again in quick
+    /// succession. Changes to the same device within this window are merged,
+    /// so it's only untracked or tracked again once.
+    #[arg(long, default_value_t = 0)]
+    pub device_debounce_ms: u64,
+    /// How to detect devices being added and removed
+    ///
+    /// inotify can fail to be set up, or not deliver events, in some sandboxed
+    /// or containerized environments. `poll` works anywhere, but only notices
+    /// changes every `--poll-interval-ms`.
+    #[arg(long, value_enum, default_value_t = WatchMode::Auto)]
+    pub watch_mode: WatchMode,
+    /// How often to check for device changes with `--watch-mode poll`, in
+    /// milliseconds
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub poll_interval_ms: u64,
+    /// Pause gestures while the screen is locked
+    ///
+    /// The lock state is read from logind's `LockedHint` for the session in
+    /// `$XDG_SESSION_ID`, or the session fukomaster is running in. While
+    /// locked, any swipe or scroll in progress is stopped, which releases its
+    /// grab, and no new ones are started.
+    #[cfg(feature = "lock-detect")]
+    #[arg(long)]
+    pub pause_when_locked: bool,
+    /// Serve a control interface on the D-Bus session bus
+    ///
+    /// `io.github.aecsocket.Fukomaster` exposes methods at
+    /// `/io/github/aecsocket/Fukomaster` to start, stop and cancel swipes,
+    /// pause and resume gestures, set multipliers, enable and disable finger
+    /// counts, and query the status. If the session bus is unavailable,
+    /// fukomaster runs without it.
+    #[cfg(feature = "dbus")]
+    #[arg(long)]
+    pub dbus: bool,
+    /// Show desktop notifications for these kinds of events, separated by
+    /// commas
+    ///
+    /// Notifications go through the freedesktop notification interface on the
+    /// session bus. At most one is shown per second, each replacing the last,
+    /// so quick gestures don't flood the screen. If there is no notification
+    /// daemon, nothing is shown.
+    #[cfg(feature = "notifications")]
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "EVENTS")]
+    pub notify: Vec<notifications::NotifyOn>,
+    /// Accept gestures from remote clients over TCP on this address
+    ///
+    /// Clients send one command per line, and must first send `auth <token>`
+    /// with the token from `--listen-token-file`. After that,
+    /// `swipe <fingers> <up|down|left|right> [distance] [ms]` performs a whole
+    /// swipe, and `status` reports what fukomaster is doing. Each command is
+    /// answered with a line starting with `ok` or `error`.
+    #[arg(long, value_name = "ADDR", requires = "listen_token_file")]
+    pub listen_gestures: Option<SocketAddr>,
+    /// File containing the token which `--listen-gestures` clients must send
+    #[arg(long, value_name = "PATH", requires = "listen_gestures")]
+    pub listen_token_file: Option<PathBuf>,
+    /// Record all events emitted to the virtual trackpad to this file, in
+    /// `evemu-record` format (`-` for stdout)
+    ///
+    /// The recording starts with the virtual trackpad's description, so it
+    /// can be replayed with `evemu-play` for bug reports.
+    #[arg(long)]
+    pub evemu_record: Option<PathBuf>,
+    /// Record all events emitted to the virtual trackpad to this file, in
+    /// `libinput record` format (`-` for stdout)
+    ///
+    /// The recording describes the system and the virtual trackpad, so it can
+    /// be replayed with `libinput replay` and attached to libinput bug
+    /// reports.
+    #[arg(long)]
+    pub libinput_record: Option<PathBuf>,
+    /// Record all events emitted to the virtual trackpad to this file, in
+    /// fukomaster's own format (`-` for stdout)
+    ///
+    /// The recording includes the version of fukomaster, the virtual
+    /// trackpad's capabilities and the options which shaped it, so it can be
+    /// replayed with `fukomaster replay` by later versions too, even if their
+    /// defaults change.
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<PathBuf>,
+    /// Write the path of the virtual fingers to this file as they move, as
+    /// CSV (`-` for stdout)
+    ///
+    /// Each row is one frame emitted to the virtual trackpad: `time,event,x,y`,
+    /// where `event` is `down`, `move` or `up` and `x`/`y` is the fingers'
+    /// position relative to where they touch down, in trackpad units, with Y
+    /// increasing downwards. Rows are flushed as they're written, so a
+    /// plotting tool following the file shows the path that the compositor
+    /// receives live, to compare against how the mouse moved.
+    #[arg(long, value_name = "PATH")]
+    pub visualize: Option<PathBuf>,
+    /// Measure the delay between mouse motion and the virtual trackpad moving
+    ///
+    /// Each delay is logged at the debug level, using the kernel's timestamp
+    /// of the mouse event. When a swipe finishes, the mean, median and 99th
+    /// percentile over the most recent motion are logged.
+    #[arg(long)]
+    pub measure_latency: bool,
+    /// Experimental: raise the swipe speed multipliers while running if swipes
+    /// are often too short to be recognized as gestures
+    ///
+    /// Whether a swipe is too short uses the same check as
+    /// `--threshold-distance-mm`. If 3 of the last 5 swipes with a finger
+    /// count were too short, its multipliers are raised by a quarter. Once 5
+    /// swipes in a row are long enough, that finger count has converged and
+    /// isn't adjusted again. Every adjustment is logged, so the final
+    /// multipliers can be put in the config file, after which this option
+    /// should be dropped. Reloading the config file replaces the tuned
+    /// multipliers. Can't be used with `--boomerang`, which always ends swipes
+    /// where they started.
+    #[arg(long)]
+    pub auto_tune: bool,
+    /// Run a command when a swipe ends, given as
+    /// `<fingers>-<direction>=<command>` (e.g. `3-up=wmctrl -s 1`)
+    ///
+    /// The command runs through `sh -c` when a swipe with that many fingers
+    /// ends having moved at least `--threshold-distance-mm`. Its direction is
+    /// whichever of up, down, left or right the fingers moved furthest in,
+    /// from where they touched down to where they lift, after multipliers. A
+    /// swipe which changes direction partway through counts by where it ended
+    /// up. Cancelled swipes don't run commands. Can be given more than once.
+    #[arg(long, value_name = "FINGERS-DIRECTION=COMMAND")]
+    pub swipe_cmd: Vec<SwipeCommand>,
+    /// Only run `--swipe-cmd` commands for swipes, without emitting anything
+    /// through the virtual trackpad
+    ///
+    /// Useful for compositors without gesture support, or to bind finger
+    /// counts and directions which the compositor doesn't use.
+    #[arg(long, requires = "swipe_cmd")]
+    pub swipe_cmd_only: bool,
+    /// Shortest time between two `--swipe-cmd` commands running, in
+    /// milliseconds
+    ///
+    /// A command for a swipe which ends sooner than this after the last
+    /// command ran is skipped, so that quick repeated swipes don't flood the
+    /// system with commands.
+    #[arg(long, value_name = "MS", default_value_t = 250)]
+    pub swipe_cmd_debounce_ms: u64,
+    /// Add random noise of up to this many trackpad units to each emitted
+    /// finger position, on each axis
+    ///
+    /// Real trackpads report slightly noisy positions, so this is useful for
+    /// testing that gesture recognizers cope with them.
+    #[arg(long, default_value_t = 0)]
+    pub jitter: u32,
+    /// Seed for `--jitter`, so that the same noise is generated every run
+    ///
+    /// Without this, a random seed is picked and logged.
+    #[arg(long)]
+    pub jitter_seed: Option<u64>,
+    /// Log with `tracing-subscriber` instead of the default logger
+    ///
+    /// Each swipe is logged as a span, which reports how long it took when
+    /// it closes (at INFO). A summary of each swipe is logged at DEBUG, and
+    /// every finger movement at TRACE. Filter with `RUST_LOG` as usual.
+    #[arg(long)]
+    pub tracing: bool,
+    /// Wait until another process has opened the virtual trackpad before
+    /// starting any gestures
+    ///
+    /// A compositor can take a moment to open a new input device, and
+    /// gestures sent before then are lost. This checks which processes have
+    /// the virtual trackpad's dev node open (through `/proc`), which only sees
+    /// processes running as the same user unless run as root. Gives up and
+    /// carries on after `--wait-for-consumer-ms`.
+    #[arg(long)]
+    pub wait_for_consumer: bool,
+    /// Longest time to wait with `--wait-for-consumer`, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 5000)]
+    pub wait_for_consumer_ms: u64,
+    /// Perform a tiny swipe on the virtual trackpad right after creating it
+    ///
+    /// Some compositors only finish setting up gesture handling for a new
+    /// trackpad once it has sent its first touch, which can make the first
+    /// real swipe feel laggy or get dropped. This touches down, moves by
+    /// `--warmup-distance` and lifts straight away, to prime the compositor.
+    #[arg(long)]
+    pub warmup: bool,
+    /// How many fingers the `--warmup` gesture uses
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(2..=5))]
+    pub warmup_fingers: u8,
+    /// How far the fingers move in the `--warmup` gesture, in trackpad units
+    #[arg(long, default_value_t = 1)]
+    pub warmup_distance: i32,
+    /// Emit a frame with only a timestamp (`MSC_TIMESTAMP`) to the virtual
+    /// trackpad whenever it has been idle for this many seconds
+    ///
+    /// Some compositors stop reading from an input device which has been idle
+    /// for a long time, after which swipes are ignored. The frame has no
+    /// touches in it, so it is never seen as part of a gesture. It is only
+    /// sent while no fingers are down.
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub idle_keepalive: Option<u64>,
+    /// Move the fingers with a gamepad's right analog stick while swiping
+    ///
+    /// Gamepad buttons can be used as triggers like any other key code (e.g.
+    /// `-3 304` for the south face button). With this option, if the device
+    /// that started the swipe has a right analog stick (`ABS_RX`/`ABS_RY`),
+    /// pushing the stick moves the fingers continuously, faster the further it
+    /// is pushed.
+    #[arg(long)]
+    pub stick: bool,
+    /// How far the `--stick` must be pushed before the fingers move, from 0.0
+    /// to 1.0
+    #[arg(long, default_value_t = 0.15)]
+    pub stick_deadzone: f32,
+    /// How fast the fingers move with the `--stick` fully pushed, in trackpad
+    /// units per second
+    ///
+    /// This is applied before `--x-mult` and `--y-mult`.
+    #[arg(long, default_value_t = 2000.0)]
+    pub stick_speed: f32,
+    /// Action to perform instead of simulating the trackpad
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Alternative actions to simulating the trackpad
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum Command {
+    /// Print the virtual trackpad's capabilities in `evemu-describe` format
+    ///
+    /// The output can be used with `evemu-device` to create an identical
+    /// device, without running fukomaster.
+    EvemuDescribe {
+        /// File to write the description to (`-` for stdout)
+        #[arg(default_value = "-")]
+        output: PathBuf,
+    },
+    /// Print the virtual trackpad's capabilities in the same format as
+    /// `evtest`
+    ///
+    /// This describes exactly what the virtual trackpad would be created with
+    /// using the other options given, so it can be diffed against the output
+    /// of `evtest` for a real trackpad.
+    DescribeTarget {
+        /// File to write the description to (`-` for stdout)
+        #[arg(default_value = "-")]
+        output: PathBuf,
+    },
+    /// Find out which device and key code to use, by pressing the button you
+    /// want to swipe with
+    ///
+    /// All input devices are listed, then the first button pressed on any of
+    /// them is detected, and the flags needed to use it are printed.
+    Setup {
+        /// How many fingers the detected button should swipe with
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(2..=5))]
+        fingers: u8,
+        /// How long to wait for a button press, in seconds
+        #[arg(long, default_value_t = 30)]
+        timeout_secs: u64,
+    },
+    /// Repeatedly perform a swipe on the virtual trackpad, without any
+    /// physical input
+    ///
+    /// Useful for watching a compositor's gesture animations while tweaking
+    /// them. Runs until Ctrl-C is pressed.
+    Loop {
+        /// How many fingers to swipe with
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(2..=5))]
+        fingers: u8,
+        /// Direction to swipe in
+        #[arg(long, value_enum, default_value_t = Direction::Left)]
+        direction: Direction,
+        /// How far to swipe, in trackpad units
+        #[arg(long, default_value_t = 1000)]
+        distance: i32,
+        /// How long each swipe takes, in milliseconds
+        #[arg(long, default_value_t = 250)]
+        swipe_ms: u64,
+        /// Time between the start of each swipe, in milliseconds
+        #[arg(long, default_value_t = 2000, value_parser = clap::value_parser!(u64).range(1..))]
+        interval_ms: u64,
+    },
+    /// Perform a swipe with 2, 3, 4 and then 5 fingers, to find out which
+    /// ones the compositor responds to
+    ///
+    /// Before each swipe, what to expect is printed, followed by a pause. With
+    /// the `focus` feature on Sway or Hyprland, whether each swipe switched
+    /// workspace is also printed after it.
+    ProbeFingers {
+        /// Direction to swipe in
+        #[arg(long, value_enum, default_value_t = Direction::Left)]
+        direction: Direction,
+        /// How far to swipe, in trackpad units
+        #[arg(long, default_value_t = 1000)]
+        distance: i32,
+        /// How long each swipe takes, in milliseconds
+        #[arg(long, default_value_t = 250)]
+        swipe_ms: u64,
+        /// Pause before each swipe, in milliseconds
+        #[arg(long, default_value_t = 2000)]
+        pause_ms: u64,
+    },
+    /// Play a gesture template on the virtual trackpad
+    ///
+    /// Templates are `<name>.gesture` files in the templates directory. Each
+    /// line is a step: `fingers <2-5>` first, then any number of
+    /// `move <dx> <dy> [ms]` and `wait <ms>`. A later `fingers <2-5>` changes
+    /// the finger count without lifting, for compound gestures. Lines starting
+    /// with `#` are ignored.
+    Play {
+        /// Name of the template to play
+        name: String,
+        /// Directory to load templates from
+        ///
+        /// Defaults to `fukomaster/templates` under `$XDG_CONFIG_HOME`, or
+        /// under `~/.config` if it isn't set.
+        #[arg(long)]
+        templates_dir: Option<PathBuf>,
+    },
+    /// Measure how fast the virtual trackpad can be sent finger motion
+    ///
+    /// Fingers are moved back and forth as fast as possible for the given
+    /// time, then the frames and events emitted per second are printed, with
+    /// percentiles of how long each frame took to emit and how many failed.
+    /// Slow or failing frames mean the kernel or a consumer is falling behind.
+    Bench {
+        /// How many fingers to move
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(2..=5))]
+        fingers: u8,
+        /// How long to emit for, in seconds
+        #[arg(long, default_value_t = 5)]
+        secs: u64,
+    },
+    /// Replay a recording made with `--record` on a new virtual trackpad
+    ///
+    /// The trackpad is created with the capabilities from the recording, so
+    /// options which change the trackpad have no effect. Recordings from
+    /// newer versions of fukomaster which use a format this version can't
+    /// read are refused.
+    Replay {
+        /// Recording to replay
+        path: PathBuf,
+    },
+    /// List the code and name of every key and button
+    ///
+    /// Any of the names can be given instead of a key code to the key options
+    /// and in bindings files. Codes which aren't listed, like many
+    /// vendor-specific buttons, have no name and can only be given by code.
+    Keys,
 }
 
 const DEV_INPUT: &str = "/dev/input";
@@ -87,30 +1230,510 @@ enum NotifyEvent {
 }
 
 #[tokio::main]
-async fn main() -> Result<Never> {
-    init_logging();
-
+async fn main() -> Result<()> {
     // arg parsing
 
+    let args = Args::parse();
+    init_logging(args.tracing);
+    let (
+        config,
+        Launch {
+            command,
+            lock_file,
+            startup_retries,
+            rt_priority,
+            cpu_affinity,
+        },
+    ) = configure(args)?;
+
+    sched::apply(rt_priority, &cpu_affinity);
+
+    match command {
+        Some(Command::EvemuDescribe { output }) => {
+            let mut out = evemu::open_output(&output)
+                .with_context(|| format!("failed to open {}", output.display()))?;
+            evemu::describe(&TrackpadSpec::new(&config), &mut out)
+                .and_then(|()| out.flush())
+                .with_context(|| "failed to write device description")
+        }
+        Some(Command::DescribeTarget { output }) => {
+            let mut out = evemu::open_output(&output)
+                .with_context(|| format!("failed to open {}", output.display()))?;
+            evemu::describe_target(&TrackpadSpec::new(&config), &mut out)
+                .and_then(|()| out.flush())
+                .with_context(|| "failed to write device description")
+        }
+        Some(Command::Setup {
+            fingers,
+            timeout_secs,
+        }) => {
+            let fingers = Fingers::from_count(fingers)
+                .expect("`--fingers` should be validated to be between 2 and 5");
+            let paths = enumerate_devices_with_retry(startup_retries).await?;
+            setup::run(&config, paths, fingers, Duration::from_secs(timeout_secs)).await
+        }
+        Some(Command::Loop {
+            fingers,
+            direction,
+            distance,
+            swipe_ms,
+            interval_ms,
+        }) => {
+            let gesture = LoopGesture {
+                fingers: Fingers::from_count(fingers)
+                    .expect("`--fingers` should be validated to be between 2 and 5"),
+                direction,
+                distance,
+                duration: Duration::from_millis(swipe_ms),
+                interval: Duration::from_millis(interval_ms),
+            };
+            gesture_loop::run(&config, gesture).await
+        }
+        Some(Command::ProbeFingers {
+            direction,
+            distance,
+            swipe_ms,
+            pause_ms,
+        }) => {
+            let gesture = LoopGesture {
+                fingers: Fingers::Two,
+                direction,
+                distance,
+                duration: Duration::from_millis(swipe_ms),
+                interval: Duration::from_millis(pause_ms),
+            };
+            gesture_loop::probe_fingers(&config, gesture).await
+        }
+        Some(Command::Play {
+            name,
+            templates_dir,
+        }) => {
+            let templates_dir = templates_dir.unwrap_or_else(template::default_dir);
+            let template = Template::load(&templates_dir, &name)?;
+            template::play(&config, &name, &template).await
+        }
+        Some(Command::Bench { fingers, secs }) => {
+            let fingers = Fingers::from_count(fingers)
+                .expect("`--fingers` should be validated to be between 2 and 5");
+            bench::run(&config, fingers, Duration::from_secs(secs)).await
+        }
+        Some(Command::Replay { path }) => {
+            let recording = Recording::load(&path)?;
+            recording::replay(&recording).await
+        }
+        Some(Command::Keys) => {
+            keys::print_all();
+            Ok(())
+        }
+        None => {
+            log_triggers(&config);
+            let never = run(&config, lock_file, startup_retries).await?;
+            match never {}
+        }
+    }
+}
+
+/// What to do with the config once it's built, which isn't part of it
+struct Launch {
+    command: Option<Command>,
+    lock_file: Option<PathBuf>,
+    startup_retries: u32,
+    rt_priority: Option<u8>,
+    cpu_affinity: Vec<usize>,
+}
+
+/// Checks the parsed arguments, and builds the config from them
+#[allow(clippy::too_many_lines)]
+fn configure(args: Args) -> Result<(Config, Launch)> {
     let Args {
-        input_allow,
+        mut input_allow,
         input_deny,
+        allow_caps,
+        deny_caps,
+        trigger_device,
+        motion_device,
+        motion_source,
+        enable_file,
+        #[cfg(feature = "focus")]
+        focus_app,
         swipe_2,
         swipe_3,
         swipe_4,
         swipe_5,
+        bindings_file,
+        finger_fallback,
+        supported_fingers,
+        enable_taps,
+        tap_window_ms,
+        cancel_key,
+        drag_key,
+        drag_end_key,
+        drag_fingers,
+        drag_lock_key,
+        drag_lock_gap_ms,
+        turbo_key,
+        turbo_factor,
+        anchor_boost,
+        anchor_events,
+        dead_zone,
+        dead_zone_x,
+        dead_zone_y,
+        smoothing,
+        smoothing_x,
+        smoothing_y,
+        scroll_key,
+        scroll_factor,
+        scroll_two_finger,
         resolution,
+        threshold_distance_mm,
+        threshold_speed_mm_s,
+        match_device,
+        uinput_fd,
+        device_type,
+        origin,
+        abs_fuzz,
+        abs_flat,
+        bus_type,
+        mt_protocol,
+        pressure,
+        pressure_ramp_ms,
+        screen_width,
+        screen_height,
         x_mult,
         y_mult,
+        invert_x,
+        invert_y,
+        gain_up,
+        gain_down,
+        gain_left,
+        gain_right,
+        config: config_files,
+        gain_low,
+        gain_high,
+        transition_speed,
+        libinput_accel,
         no_grab,
-    } = Args::parse();
+        grab_device,
+        skip_grab_device,
+        grab_timing,
+        cursor_lock,
+        passthrough,
+        passthrough_device,
+        max_read_errors,
+        input_queue,
+        input_queue_depth,
+        max_batch_events,
+        clock,
+        tracking_ids,
+        lift_order,
+        recenter_distance,
+        retouch_id,
+        log_scale,
+        curvature,
+        interpolate,
+        start_offset_x,
+        start_offset_y,
+        boomerang,
+        simulate_physical,
+        flick_sensitivity,
+        boomerang_ms,
+        hold_end_ms,
+        post_stop_clear,
+        post_stop_clear_ms,
+        merge_window_ms,
+        emit_delay,
+        lock_file,
+        startup_retries,
+        rt_priority,
+        cpu_affinity,
+        device_debounce_ms,
+        watch_mode,
+        poll_interval_ms,
+        #[cfg(feature = "lock-detect")]
+        pause_when_locked,
+        #[cfg(feature = "dbus")]
+        dbus,
+        #[cfg(feature = "notifications")]
+        notify,
+        listen_gestures,
+        listen_token_file,
+        evemu_record,
+        libinput_record,
+        record,
+        visualize,
+        measure_latency,
+        auto_tune,
+        swipe_cmd,
+        swipe_cmd_only,
+        swipe_cmd_debounce_ms,
+        jitter,
+        jitter_seed,
+        wait_for_consumer,
+        wait_for_consumer_ms,
+        warmup,
+        warmup_fingers,
+        warmup_distance,
+        idle_keepalive,
+        stick,
+        stick_deadzone,
+        stick_speed,
+        tracing: _,
+        command,
+    } = args;
 
-    let swipe_2 = swipe_2.map(Key::new);
-    let swipe_3 = swipe_3.map(Key::new);
-    let swipe_4 = swipe_4.map(Key::new);
-    let swipe_5 = swipe_5.map(Key::new);
+    config::check_multiplier("--x-mult", x_mult)?;
+    config::check_multiplier("--y-mult", y_mult)?;
+    for (name, gain) in [
+        ("--gain-up", gain_up),
+        ("--gain-down", gain_down),
+        ("--gain-left", gain_left),
+        ("--gain-right", gain_right),
+    ] {
+        config::check_multiplier(name, gain)?;
+    }
+    config::check_multiplier("--turbo-factor", turbo_factor)?;
+    config::check_multiplier("--anchor-boost", anchor_boost)?;
+    config::check_multiplier("--scroll-factor", scroll_factor)?;
+    config::check_gains(gain_low, gain_high, transition_speed)?;
+    let multipliers = config::load_multipliers(&config_files)?;
+    let triggers = match &bindings_file {
+        Some(path) => {
+            let triggers = bindings::load(path)?;
+            info!("Loaded bindings from {}", path.display());
+            triggers
+        }
+        None => Triggers::from_codes([swipe_2, swipe_3, swipe_4, swipe_5]),
+    };
 
-    let grab = !no_grab;
+    if log_scale.is_some_and(|base| !(base.is_finite() && base > 0.0)) {
+        bail!("`--log-scale` must be a finite number greater than 0");
+    }
+    if curvature.is_some_and(|degrees| !degrees.is_finite()) {
+        bail!("`--curvature` must be a finite number");
+    }
+    if auto_tune && boomerang {
+        bail!("`--auto-tune` can't be used with `--boomerang`");
+    }
+    if mt_protocol == MtProtocol::A && passthrough_device.is_some() {
+        // the real trackpad's slots are forwarded as they are
+        bail!("`--mt-protocol a` can't be used with `--passthrough-device`");
+    }
+
+    for device in [&trigger_device, &motion_device, &passthrough_device]
+        .into_iter()
+        .flatten()
+    {
+        if !device.exists() {
+            bail!("device {} does not exist", device.display());
+        }
+        // an allow list would otherwise silently ignore these devices
+        if !input_allow.is_empty() && !input_allow.contains(device) {
+            input_allow.push(device.clone());
+        }
+    }
+
+    let accel = if libinput_accel {
+        match AccelProfile::read_gnome() {
+            Ok(accel) => {
+                info!("Using mouse acceleration settings: {accel:?}");
+                Some(accel)
+            }
+            Err(err) => {
+                warn!("Failed to read mouse acceleration settings, using gain instead: {err:#}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let preset = preset::detect();
+    if let Some(preset) = preset {
+        info!(
+            "Detected {} desktop, using its preset defaults",
+            preset.name
+        );
+    } else {
+        info!("Desktop environment not recognized, using neutral defaults");
+    }
+
+    let supported_fingers = if !finger_fallback {
+        None
+    } else if !supported_fingers.is_empty() {
+        Some(supported_fingers)
+    } else if let Some(preset) = preset {
+        Some(preset.fingers.to_vec())
+    } else {
+        warn!("Supported finger counts of this desktop are unknown, so fingers won't fall back");
+        None
+    };
+
+    let config = Config {
+        input_allow,
+        input_deny,
+        allow_caps,
+        deny_caps,
+        trigger_device,
+        motion_device,
+        motion_source,
+        enable_file,
+        #[cfg(feature = "focus")]
+        focus_apps: focus_app,
+        triggers: Arc::new(RwLock::new(triggers)),
+        bindings_file,
+        supported_fingers: supported_fingers
+            .map(|counts| counts.into_iter().filter_map(Fingers::from_count).collect()),
+        fallen_back: Arc::default(),
+        tap_window: enable_taps.then(|| Duration::from_millis(tap_window_ms)),
+        cancel_key: cancel_key.map(Key::new),
+        drag: drag_key.map(|key| Drag {
+            key: Key::new(key),
+            end_key: Key::new(drag_end_key.unwrap_or(key)),
+            fingers: Fingers::from_count(drag_fingers)
+                .expect("`--drag-fingers` should be validated to be between 2 and 5"),
+        }),
+        drag_lock: drag_lock_key.map(|key| DragLock {
+            key: Key::new(key),
+            gap: Duration::from_millis(drag_lock_gap_ms),
+        }),
+        turbo: turbo_key.map(|key| Turbo {
+            key: Key::new(key),
+            factor: turbo_factor,
+        }),
+        anchor_boost,
+        anchor_events,
+        filter_x: AxisFilter {
+            dead_zone: dead_zone_x.unwrap_or(dead_zone),
+            smoothing: smoothing_x.unwrap_or(smoothing).clamp(0.0, 0.99),
+        },
+        filter_y: AxisFilter {
+            dead_zone: dead_zone_y.unwrap_or(dead_zone),
+            smoothing: smoothing_y.unwrap_or(smoothing).clamp(0.0, 0.99),
+        },
+        cancel_duration: Duration::from_millis(boomerang_ms),
+        hold_end: Duration::from_millis(hold_end_ms),
+        scroll_key: scroll_key.map(Key::new),
+        scroll_factor,
+        scroll_two_finger,
+        thresholds: Thresholds {
+            distance_mm: threshold_distance_mm,
+            speed_mm_s: threshold_speed_mm_s,
+        },
+        resolution: resolution.unwrap_or_else(|| {
+            preset.map_or(preset::DEFAULT_RESOLUTION, |preset| preset.resolution)
+        }),
+        match_device,
+        uinput_fd,
+        device_type,
+        origin,
+        abs_fuzz,
+        abs_flat,
+        bus: bus_type,
+        mt_protocol,
+        pressure_ramp: pressure.then(|| Duration::from_millis(pressure_ramp_ms)),
+        screen_size: (screen_width, screen_height),
+        x_mult,
+        y_mult,
+        invert_x,
+        invert_y,
+        direction_gains: DirectionGains {
+            up: gain_up,
+            down: gain_down,
+            left: gain_left,
+            right: gain_right,
+        },
+        files: config_files,
+        multipliers: Arc::new(RwLock::new(multipliers)),
+        gain_low,
+        gain_high,
+        transition_speed,
+        accel,
+        grab: !no_grab,
+        grab_devices: grab_device,
+        skip_grab_devices: skip_grab_device,
+        grab_timing,
+        cursor_lock,
+        passthrough,
+        passthrough_device,
+        max_read_errors,
+        input_queue,
+        input_queue_depth: usize::from(input_queue_depth),
+        max_batch_events,
+        clock,
+        tracking_ids,
+        lift_order,
+        recenter_distance,
+        retouch_id,
+        log_scale,
+        curvature: curvature.map(|degrees| degrees.to_radians() / 1000.0),
+        interpolate,
+        start_offset: (start_offset_x, start_offset_y),
+        boomerang: boomerang.then(|| Duration::from_millis(boomerang_ms)),
+        emit_delay: Duration::from_millis(emit_delay),
+        post_stop_clear: post_stop_clear.then(|| Duration::from_millis(post_stop_clear_ms)),
+        merge_window: (merge_window_ms > 0).then(|| Duration::from_millis(merge_window_ms)),
+        #[cfg(feature = "lock-detect")]
+        pause_when_locked,
+        #[cfg(feature = "dbus")]
+        dbus,
+        #[cfg(feature = "notifications")]
+        notify,
+        listen_gestures: listen_gestures
+            .zip(listen_token_file)
+            .map(|(addr, token_file)| ListenGestures { addr, token_file }),
+        device_debounce: (device_debounce_ms > 0)
+            .then(|| Duration::from_millis(device_debounce_ms)),
+        watch_mode,
+        poll_interval: Duration::from_millis(poll_interval_ms),
+        physical: simulate_physical.then_some(Physical {
+            flick_sensitivity: flick_sensitivity.max(0.0),
+        }),
+        evemu_record,
+        libinput_record,
+        record,
+        visualize,
+        measure_latency,
+        auto_tune,
+        swipe_cmd,
+        swipe_cmd_only,
+        swipe_cmd_debounce: Duration::from_millis(swipe_cmd_debounce_ms),
+        jitter,
+        jitter_seed,
+        wait_for_consumer: wait_for_consumer.then(|| Duration::from_millis(wait_for_consumer_ms)),
+        warmup: warmup.then(|| Warmup {
+            fingers: Fingers::from_count(warmup_fingers)
+                .expect("`--warmup-fingers` should be validated to be between 2 and 5"),
+            distance: warmup_distance,
+        }),
+        idle_keepalive: idle_keepalive.map(Duration::from_secs),
+        stick: stick.then_some(Stick {
+            deadzone: stick_deadzone.clamp(0.0, 0.99),
+            speed: stick_speed,
+        }),
+    };
+
+    Ok((
+        config,
+        Launch {
+            command,
+            lock_file,
+            startup_retries,
+            rt_priority,
+            cpu_affinity,
+        },
+    ))
+}
+
+async fn run(config: &Config, lock_file: Option<PathBuf>, startup_retries: u32) -> Result<Never> {
+    // running two instances at once means two virtual trackpads fighting over
+    // the same grabbed devices, so make sure we're the only one
+    let lock_file = lock_file.unwrap_or_else(lock::default_path);
+    let _lock = lock::acquire(&lock_file)?;
+    debug!("Acquired lock file {lock_file:?}");
+
+    if let Some(source_path) = &config.passthrough_device {
+        return mirror::run(config, source_path).await;
+    }
 
     // setup
 
@@ -120,69 +1743,175 @@ async fn main() -> Result<Never> {
     // note that paths in NotifyEvent may not actually point to a device;
     // it's the consumer's job to figure out if a path is actually for a device
     // that we can use
-    for result in fs::read_dir(DEV_INPUT)
-        .with_context(|| format!("failed to list files under {DEV_INPUT:?}"))?
-    {
-        let entry = result.with_context(|| format!("failed to read file under {DEV_INPUT:?}"))?;
+    let known = enumerate_devices_with_retry(startup_retries).await?;
+    for path in &known {
         send_notifs
-            .send(NotifyEvent::Created(entry.path()))
+            .send(NotifyEvent::Created(path.clone()))
             .expect("channel should be open");
     }
 
     // then set up a watcher to watch for device changes
-    let mut dev_watcher = notify::recommended_watcher(move |res| match res {
-        Ok(notify::Event {
-            kind: notify::EventKind::Create(_),
-            paths,
-            ..
-        }) => {
-            for path in paths {
-                debug!("{path:?} created");
-                let _ = send_notifs.send(NotifyEvent::Created(path));
+    let _dev_watcher =
+        watch::watch_devices(config.watch_mode, config.poll_interval, known, &send_notifs)?;
+    drop(send_notifs);
+
+    let _bindings_watcher = config
+        .bindings_file
+        .as_deref()
+        .map(|path| bindings::watch(path, config.triggers.clone()))
+        .transpose()?;
+
+    if !config.files.is_empty() {
+        let mut hangups =
+            signal(SignalKind::hangup()).with_context(|| "failed to listen for SIGHUP")?;
+        let config_files = config.files.clone();
+        let multipliers = config.multipliers.clone();
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                match config::load_multipliers(&config_files) {
+                    Ok(new) => {
+                        *multipliers.write().unwrap_or_else(PoisonError::into_inner) = new;
+                        info!("Reloaded {config_files:?}");
+                    }
+                    Err(err) => {
+                        warn!("Failed to reload config files, keeping previous values: {err:#}");
+                    }
+                }
             }
+        });
+    }
+
+    // the sender is kept alive for as long as we run, even if nothing sends
+    // lock changes, so that the receiver never closes
+    let (send_locks, mut recv_locks) = mpsc::unbounded_channel::<bool>();
+    #[cfg(feature = "lock-detect")]
+    if config.pause_when_locked {
+        screen_lock::watch(send_locks.clone())
+            .await
+            .with_context(|| "failed to watch screen lock state")?;
+    }
+
+    #[cfg(feature = "focus")]
+    if !config.focus_apps.is_empty() {
+        // checked once up front, since failures when starting swipes are only
+        // logged at the debug level
+        if let Err(err) = focus::focused_app().await {
+            warn!("Failed to get focused window, so swipes will start in any app: {err:#}");
         }
-        Ok(notify::Event {
-            kind: notify::EventKind::Remove(_),
-            paths,
-            ..
-        }) => {
-            for path in paths {
-                debug!("{path:?} removed");
-                let _ = send_notifs.send(NotifyEvent::Removed(path));
-            }
+    }
+    #[cfg(feature = "focus")]
+    if config.has_monitor_scales() {
+        if let Err(err) = focus::focused_monitor().await {
+            warn!("Failed to get focused monitor, so monitor scales won't apply: {err:#}");
         }
-        Ok(_) => {}
-        Err(err) => {
-            warn!(
-                "Error while watching {DEV_INPUT:?}: {:#}",
-                anyhow::Error::new(err)
-            );
+    }
+    #[cfg(not(feature = "focus"))]
+    if config.has_monitor_scales() {
+        warn!("Monitor scales in the config file need the `focus` feature, so they won't apply");
+    }
+
+    // same for control requests, which may not have any controllers
+    let (send_commands, mut recv_commands) = mpsc::unbounded_channel::<control::Command>();
+    #[cfg(feature = "dbus")]
+    let _dbus = if config.dbus {
+        match dbus::serve(send_commands.clone()).await {
+            Ok(connection) => {
+                info!("Serving control interface on the session bus");
+                Some(connection)
+            }
+            Err(err) => {
+                warn!("Failed to serve control interface, continuing without it: {err:#}");
+                None
+            }
         }
-    })
-    .with_context(|| format!("failed to create {DEV_INPUT:?} watcher"))?;
+    } else {
+        None
+    };
 
-    dev_watcher
-        .watch(Path::new(DEV_INPUT), notify::RecursiveMode::NonRecursive)
-        .with_context(|| format!("failed to start watching {DEV_INPUT:?}"))?;
-    info!("Watching {DEV_INPUT:?} for device changes");
+    if let Some(listen) = &config.listen_gestures {
+        remote::listen(listen.addr, &listen.token_file, send_commands.clone())
+            .await
+            .with_context(|| "failed to listen for remote gestures")?;
+    }
 
-    swipe::simulate(
+    let never = swipe::simulate(
         &mut recv_notifs,
-        &input_allow,
-        &input_deny,
-        swipe_2,
-        swipe_3,
-        swipe_4,
-        swipe_5,
-        resolution,
-        x_mult,
-        y_mult,
-        grab,
+        &mut recv_locks,
+        &mut recv_commands,
+        config,
     )
-    .await
+    .await;
+    drop(send_locks);
+    drop(send_commands);
+    never
 }
 
-fn init_logging() {
+fn enumerate_devices() -> Result<Vec<PathBuf>> {
+    fs::read_dir(DEV_INPUT)
+        .with_context(|| format!("failed to list files under {DEV_INPUT:?}"))?
+        .map(|result| {
+            result
+                .map(|entry| entry.path())
+                .with_context(|| format!("failed to read file under {DEV_INPUT:?}"))
+        })
+        .collect()
+}
+
+/// Enumerates devices, retrying with backoff if this fails
+///
+/// Early in boot, `/dev/input` may not be fully set up yet, so a failure here
+/// is not necessarily permanent.
+async fn enumerate_devices_with_retry(retries: u32) -> Result<Vec<PathBuf>> {
+    const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+    const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match enumerate_devices() {
+            Ok(paths) => return Ok(paths),
+            Err(err) if attempt < retries => {
+                attempt += 1;
+                warn!(
+                    "Failed to enumerate devices, retrying in {backoff:?} (attempt {attempt}/{retries}): {err:#}"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Logs which key starts each finger count, warning if no key starts any
+/// swipe at all
+fn log_triggers(config: &Config) {
+    let triggers = config.triggers();
+    info!("Triggers:");
+    triggers.log();
+    if triggers.iter().all(|(_, key)| key.is_none())
+        && config.drag.is_none()
+        && config.drag_lock.is_none()
+    {
+        warn!(
+            "No trigger keys are set, so no swipes can be started from input devices; set one \
+             with `-2` to `-5` or `--bindings-file`"
+        );
+    }
+}
+
+fn init_logging(tracing: bool) {
+    if tracing {
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy();
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_span_events(FmtSpan::CLOSE)
+            .init();
+        return;
+    }
+
     let mut builder = pretty_env_logger::formatted_timed_builder();
     builder.filter_level(log::LevelFilter::Info);
     builder.parse_default_env();
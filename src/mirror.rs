@@ -0,0 +1,100 @@
+use std::{mem, path::Path};
+
+use anyhow::{Context, Result};
+use evdev::{AbsoluteAxisType, Device, InputEvent, InputEventKind, Synchronization};
+use futures::never::Never;
+use log::info;
+
+use crate::{
+    config::Config,
+    trackpad::{self, PositionAxis},
+};
+
+/// Re-emits the touches of a real trackpad on the virtual trackpad, with
+/// their motion scaled by `--x-mult` and `--y-mult`, and flipped by
+/// `--invert-x` and `--invert-y`
+///
+/// Positions are scaled around the middle of the source's position axes, so
+/// every touch moves further than it does on the real trackpad, and fingers
+/// are spread further apart. Everything else, like finger counts and tracking
+/// IDs, is passed through as it is.
+pub async fn run(config: &Config, source_path: &Path) -> Result<Never> {
+    let (source_x, source_y) = trackpad::read_position_axes(source_path)
+        .with_context(|| format!("failed to read position axes of {}", source_path.display()))?;
+    let mut source = Device::open(source_path)
+        .with_context(|| format!("failed to open {}", source_path.display()))?;
+
+    info!("Creating virtual trackpad");
+    let (mut sink, _) = trackpad::create_trackpad(config).await?;
+
+    // otherwise the compositor would see every touch twice
+    source
+        .grab()
+        .with_context(|| "failed to grab source trackpad")?;
+    let mut events = source
+        .into_event_stream()
+        .with_context(|| "failed to convert source trackpad into event stream")?;
+    info!("Mirroring {source_path:?}");
+
+    let (sign_x, sign_y) = config.invert_signs();
+    let x = ScaledAxis::new(config, source_x, config.x_mult * sign_x);
+    let y = ScaledAxis::new(config, source_y, config.y_mult * sign_y);
+    let mut frame = Vec::<InputEvent>::new();
+    loop {
+        let event = events
+            .next_event()
+            .await
+            .with_context(|| "failed to read from source trackpad")?;
+        match event.kind() {
+            InputEventKind::Synchronization(Synchronization::SYN_REPORT) => {
+                if !frame.is_empty() {
+                    sink.emit(&mem::take(&mut frame))
+                        .with_context(|| "failed to emit mirrored frame")?;
+                }
+            }
+            InputEventKind::Synchronization(_) => {}
+            InputEventKind::AbsAxis(
+                AbsoluteAxisType::ABS_X | AbsoluteAxisType::ABS_MT_POSITION_X,
+            ) => {
+                let value = sink.absolute_position(x.scale(event.value()), 0).0;
+                frame.push(InputEvent::new(event.event_type(), event.code(), value));
+            }
+            InputEventKind::AbsAxis(
+                AbsoluteAxisType::ABS_Y | AbsoluteAxisType::ABS_MT_POSITION_Y,
+            ) => {
+                let value = sink.absolute_position(0, y.scale(event.value())).1;
+                frame.push(InputEvent::new(event.event_type(), event.code(), value));
+            }
+            _ => frame.push(event),
+        }
+    }
+}
+
+/// Converts positions on one of the source's position axes into positions
+/// relative to the virtual trackpad's origin
+struct ScaledAxis {
+    center: i32,
+    scale: f32,
+}
+
+impl ScaledAxis {
+    fn new(config: &Config, source: PositionAxis, mult: f32) -> Self {
+        // motion should cover the same physical distance on both trackpads
+        // before the multiplier is applied
+        #[allow(clippy::cast_precision_loss)]
+        let resolution = if source.resolution > 0 {
+            f32::from(config.resolution) / source.resolution as f32
+        } else {
+            1.0
+        };
+        Self {
+            center: source.center(),
+            scale: resolution * mult,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn scale(&self, value: i32) -> i32 {
+        ((i64::from(value) - i64::from(self.center)) as f32 * self.scale) as i32
+    }
+}
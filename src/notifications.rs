@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use log::debug;
+use tokio::sync::mpsc;
+use zbus::{proxy, zvariant::Value, Connection};
+
+/// Shortest time between two notifications being shown, so that quick
+/// gestures don't flood the notification daemon
+const RATE_LIMIT: Duration = Duration::from_secs(1);
+
+/// How long each notification stays on screen, in milliseconds
+const EXPIRE_TIMEOUT_MS: i32 = 2000;
+
+/// Kind of event which shows a desktop notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NotifyOn {
+    /// Swipes starting and ending
+    Gestures,
+    /// Problems which stop a gesture, like its device being disconnected
+    Errors,
+}
+
+#[proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+}
+
+/// Shows desktop notifications through the freedesktop notification
+/// interface, without holding up the caller
+///
+/// At most one notification is shown per [`RATE_LIMIT`], and each one replaces
+/// the last, so only the latest is kept while notifications are held back. If
+/// there is no session bus or notification daemon, notifications are dropped.
+#[derive(Debug)]
+pub struct Notifier {
+    on: Vec<NotifyOn>,
+    send: mpsc::UnboundedSender<(NotifyOn, String)>,
+}
+
+impl Notifier {
+    /// Starts showing notifications for the kinds of events in `on`
+    pub fn start(on: Vec<NotifyOn>) -> Self {
+        let (send, recv) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let proxy = match connect().await {
+                Ok(proxy) => proxy,
+                Err(err) => {
+                    debug!("Not showing notifications: {err:#}");
+                    return;
+                }
+            };
+            show_all(&proxy, recv).await;
+        });
+        Self { on, send }
+    }
+
+    /// Shows `message` as a notification, if notifications are shown for
+    /// `kind`
+    pub fn notify(&self, kind: NotifyOn, message: String) {
+        if self.on.contains(&kind) {
+            // the receiver is gone if there's nowhere to show notifications
+            let _ = self.send.send((kind, message));
+        }
+    }
+}
+
+async fn connect() -> Result<NotificationsProxy<'static>> {
+    let connection = Connection::session()
+        .await
+        .with_context(|| "failed to connect to the session bus")?;
+    NotificationsProxy::new(&connection)
+        .await
+        .with_context(|| "failed to create notifications proxy")
+}
+
+async fn show_all(
+    proxy: &NotificationsProxy<'_>,
+    mut recv: mpsc::UnboundedReceiver<(NotifyOn, String)>,
+) {
+    let mut id = 0;
+    let mut next_at = Instant::now();
+    let mut pending = None::<(NotifyOn, String)>;
+    loop {
+        tokio::select! {
+            message = recv.recv() => {
+                let Some((kind, message)) = message else {
+                    return;
+                };
+                // an error says more than a gesture which comes right after it
+                if !matches!((&pending, kind), (Some((NotifyOn::Errors, _)), NotifyOn::Gestures)) {
+                    pending = Some((kind, message));
+                }
+            }
+            () = tokio::time::sleep_until(next_at.into()), if pending.is_some() => {
+                let Some((_, message)) = pending.take() else {
+                    continue;
+                };
+                match proxy
+                    .notify(
+                        "fukomaster",
+                        id,
+                        "input-touchpad",
+                        &message,
+                        "",
+                        &[],
+                        HashMap::new(),
+                        EXPIRE_TIMEOUT_MS,
+                    )
+                    .await
+                {
+                    Ok(new_id) => id = new_id,
+                    Err(err) => debug!("Failed to show notification: {err:#}"),
+                }
+                next_at = Instant::now() + RATE_LIMIT;
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use evdev::{
+    uinput::{VirtualDevice, VirtualDeviceBuilder},
+    AttributeSet, InputEvent, InputEventKind, Key, RelativeAxisType,
+};
+
+use crate::keys;
+
+pub const VIRTUAL_DEVICE_NAME: &str = "fukomaster passthrough";
+
+/// Virtual device which re-emits events from a grabbed source device that
+/// aren't part of the swipe, so that they still reach applications
+pub struct Passthrough {
+    device: VirtualDevice,
+}
+
+impl Passthrough {
+    pub fn build() -> Result<Self> {
+        // we don't know which keys the grabbed devices have ahead of time, so
+        // every key and button is supported
+        let keys = (1..=keys::KEY_MAX)
+            .map(Key::new)
+            .collect::<AttributeSet<_>>();
+
+        // libinput only treats buttons as pointer buttons if the device can
+        // also move, even though we never send motion
+        let mut rel_axes = AttributeSet::<RelativeAxisType>::new();
+        rel_axes.insert(RelativeAxisType::REL_X);
+        rel_axes.insert(RelativeAxisType::REL_Y);
+        rel_axes.insert(RelativeAxisType::REL_WHEEL);
+        rel_axes.insert(RelativeAxisType::REL_HWHEEL);
+        rel_axes.insert(RelativeAxisType::REL_WHEEL_HI_RES);
+        rel_axes.insert(RelativeAxisType::REL_HWHEEL_HI_RES);
+
+        let device = VirtualDeviceBuilder::new()?
+            .name(VIRTUAL_DEVICE_NAME)
+            .with_keys(&keys)?
+            .with_relative_axes(&rel_axes)?
+            .build()
+            .with_context(|| "failed to create passthrough device")?;
+        Ok(Self { device })
+    }
+
+    pub fn device_mut(&mut self) -> &mut VirtualDevice {
+        &mut self.device
+    }
+
+    /// Determines if `event` from a grabbed device should be re-emitted
+    ///
+    /// Keys, buttons and scroll wheels are forwarded. Motion is left out,
+    /// since that is what moves the fingers.
+    pub fn forwards(event: &InputEvent) -> bool {
+        match event.kind() {
+            InputEventKind::Key(_) => true,
+            InputEventKind::RelAxis(axis) => matches!(
+                axis,
+                RelativeAxisType::REL_WHEEL
+                    | RelativeAxisType::REL_HWHEEL
+                    | RelativeAxisType::REL_WHEEL_HI_RES
+                    | RelativeAxisType::REL_HWHEEL_HI_RES
+            ),
+            _ => false,
+        }
+    }
+
+    /// Re-emits a single event, followed by a `SYN_REPORT`
+    pub fn forward(&mut self, event: InputEvent) -> Result<()> {
+        self.device.emit(&[event])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use evdev::{
+    uinput::{VirtualDevice, VirtualDeviceBuilder},
+    AttributeSet, EventType, InputEvent, Key, RelativeAxisType,
+};
+
+pub const VIRTUAL_DEVICE_NAME: &str = "fukomaster virtual pointer";
+
+/// High-resolution scroll units in one notch of a legacy scroll wheel
+pub const HI_RES_PER_NOTCH: i32 = 120;
+
+/// Virtual relative pointer used to cancel out cursor motion while swiping,
+/// and to scroll
+pub struct Pointer {
+    device: VirtualDevice,
+    /// High-resolution scroll which hasn't added up to a full legacy notch yet
+    wheel_remainder: i32,
+    hwheel_remainder: i32,
+}
+
+impl Pointer {
+    pub fn build() -> Result<Self> {
+        // libinput only treats a device as a pointer if it has a button, even
+        // though we never press it
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_LEFT);
+
+        let mut rel_axes = AttributeSet::<RelativeAxisType>::new();
+        rel_axes.insert(RelativeAxisType::REL_X);
+        rel_axes.insert(RelativeAxisType::REL_Y);
+        rel_axes.insert(RelativeAxisType::REL_WHEEL);
+        rel_axes.insert(RelativeAxisType::REL_HWHEEL);
+        rel_axes.insert(RelativeAxisType::REL_WHEEL_HI_RES);
+        rel_axes.insert(RelativeAxisType::REL_HWHEEL_HI_RES);
+
+        let device = VirtualDeviceBuilder::new()?
+            .name(VIRTUAL_DEVICE_NAME)
+            .with_keys(&keys)?
+            .with_relative_axes(&rel_axes)?
+            .build()
+            .with_context(|| "failed to create virtual pointer")?;
+        Ok(Self {
+            device,
+            wheel_remainder: 0,
+            hwheel_remainder: 0,
+        })
+    }
+
+    pub fn device_mut(&mut self) -> &mut VirtualDevice {
+        &mut self.device
+    }
+
+    /// Moves the cursor by the opposite of `(dx, dy)`
+    pub fn cancel_motion(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let events = [
+            rel_event(RelativeAxisType::REL_X, -dx),
+            rel_event(RelativeAxisType::REL_Y, -dy),
+        ];
+        self.device.emit(&events)?;
+        Ok(())
+    }
+
+    /// Scrolls by an amount in high-resolution units, where
+    /// [`HI_RES_PER_NOTCH`] units is one notch of a scroll wheel
+    ///
+    /// Like real high-resolution mice, the legacy `REL_WHEEL`/`REL_HWHEEL`
+    /// axes are also sent every time a full notch has been scrolled, for
+    /// consumers which don't understand the high-resolution axes.
+    pub fn scroll(&mut self, horizontal: i32, vertical: i32) -> Result<()> {
+        fn notches(remainder: &mut i32, amount: i32) -> i32 {
+            *remainder += amount;
+            let notches = *remainder / HI_RES_PER_NOTCH;
+            *remainder -= notches * HI_RES_PER_NOTCH;
+            notches
+        }
+
+        let wheel = notches(&mut self.wheel_remainder, vertical);
+        let hwheel = notches(&mut self.hwheel_remainder, horizontal);
+
+        let mut events = Vec::with_capacity(4);
+        if vertical != 0 {
+            events.push(rel_event(RelativeAxisType::REL_WHEEL_HI_RES, vertical));
+        }
+        if horizontal != 0 {
+            events.push(rel_event(RelativeAxisType::REL_HWHEEL_HI_RES, horizontal));
+        }
+        if wheel != 0 {
+            events.push(rel_event(RelativeAxisType::REL_WHEEL, wheel));
+        }
+        if hwheel != 0 {
+            events.push(rel_event(RelativeAxisType::REL_HWHEEL, hwheel));
+        }
+        if !events.is_empty() {
+            self.device.emit(&events)?;
+        }
+        Ok(())
+    }
+
+    /// Forgets any partially scrolled notch, so that the next scroll starts
+    /// from a clean slate
+    pub fn reset_scroll(&mut self) {
+        self.wheel_remainder = 0;
+        self.hwheel_remainder = 0;
+    }
+}
+
+fn rel_event(axis: RelativeAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::RELATIVE, axis.0, value)
+}
@@ -0,0 +1,89 @@
+use std::env;
+
+/// Default settings which suit a particular desktop environment
+#[derive(Debug, Clone, Copy)]
+pub struct Preset {
+    pub name: &'static str,
+    pub resolution: u16,
+    /// Finger counts that the compositor recognizes swipes with by default
+    ///
+    /// Two-finger swipes are scrolling, which every compositor supports.
+    pub fingers: &'static [u8],
+}
+
+/// Resolution used when the desktop environment isn't recognized
+pub const DEFAULT_RESOLUTION: u16 = 12;
+
+/// Known desktop environments, and the names they report in
+/// `XDG_CURRENT_DESKTOP`
+///
+/// The defaults were originally tuned on GNOME. The other presets are picked
+/// so that a swipe of the same mouse distance moves a similar distance
+/// through the workspace switcher.
+const PRESETS: [(&str, Preset); 4] = [
+    (
+        "GNOME",
+        Preset {
+            name: "GNOME",
+            resolution: 12,
+            fingers: &[2, 3, 4],
+        },
+    ),
+    (
+        "KDE",
+        Preset {
+            name: "KDE Plasma",
+            resolution: 8,
+            fingers: &[2, 3, 4],
+        },
+    ),
+    (
+        "Hyprland",
+        Preset {
+            name: "Hyprland",
+            resolution: 16,
+            fingers: &[2, 3, 4, 5],
+        },
+    ),
+    (
+        "sway",
+        Preset {
+            name: "Sway",
+            resolution: 12,
+            fingers: &[2, 3, 4, 5],
+        },
+    ),
+];
+
+/// Detects the desktop environment that we're running under from the
+/// environment variables it sets, and gets its preset
+///
+/// `XDG_CURRENT_DESKTOP` may list several names separated by `:` (e.g.
+/// `ubuntu:GNOME`), which are checked in order. Note that `sudo` clears most
+/// of the environment by default, so this may need `sudo -E` or
+/// `--preserve-env=XDG_CURRENT_DESKTOP` to work.
+pub fn detect() -> Option<Preset> {
+    let desktops = env::var("XDG_CURRENT_DESKTOP")
+        .or_else(|_| env::var("XDG_SESSION_DESKTOP"))
+        .unwrap_or_default();
+    let by_desktop = desktops.split(':').find_map(|desktop| {
+        PRESETS
+            .iter()
+            .find(|(id, _)| id.eq_ignore_ascii_case(desktop))
+            .map(|(_, preset)| *preset)
+    });
+    if by_desktop.is_some() {
+        return by_desktop;
+    }
+
+    // some compositors don't set `XDG_CURRENT_DESKTOP` unless started through
+    // a session manager, but always set their own variables
+    [
+        ("HYPRLAND_INSTANCE_SIGNATURE", "Hyprland"),
+        ("SWAYSOCK", "sway"),
+    ]
+    .into_iter()
+    .find(|(var, _)| env::var_os(var).is_some())
+    .and_then(|(_, id)| PRESETS.iter().find(|(preset_id, _)| *preset_id == id))
+    .map(|(_, preset)| *preset)
+}
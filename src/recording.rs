@@ -0,0 +1,443 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, BusType, EventType, InputEvent, InputId, Key,
+    MiscType, PropType, UinputAbsSetup,
+};
+use log::{debug, info};
+
+use crate::{
+    config::Config,
+    evemu,
+    trackpad::{self, TrackpadSpec},
+};
+
+/// Version of the recording format which is written, and the newest which can
+/// be read
+///
+/// Header lines which a reader doesn't know are skipped, so new ones can be
+/// added without changing the version. Any other change which older readers
+/// can't handle bumps it, and readers adapt every older version they know of.
+const FORMAT_VERSION: u32 = 1;
+
+/// Version of fukomaster, which is written to recordings for bug reports
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Records emitted events in fukomaster's own format, for `fukomaster replay`
+///
+/// A recording starts with a header describing the virtual trackpad, then
+/// lists every emitted frame:
+///
+/// ```text
+/// # fukomaster recording
+/// format 1
+/// fukomaster 0.2.1
+/// id 3 4660 22136 273
+/// property 0
+/// key 325 330 333 334 335 328
+/// misc 5
+/// abs 53 -2147483648 2147483647 0 0 12
+/// config device-type Trackpad
+/// events
+/// frame 0.000000
+/// 3 47 0
+/// 3 57 0
+/// ```
+///
+/// The trackpad is replayed with the capabilities in the header, not the ones
+/// that the current options and defaults would give, so a recording replays
+/// the same after they change. `config` lines record the options which shaped
+/// the trackpad, for reading along with a bug report.
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    start: Option<libc::timeval>,
+}
+
+impl Recorder {
+    /// Starts a recording at `path` (or stdout if `-`), writing the trackpad's
+    /// capabilities and the options which shaped it as the header
+    pub fn create(path: &Path, config: &Config, spec: &TrackpadSpec) -> io::Result<Self> {
+        let mut out = evemu::open_output(path)?;
+        write_header(config, spec, &mut out)?;
+        out.flush()?;
+        Ok(Self { out, start: None })
+    }
+}
+
+impl trackpad::Recorder for Recorder {
+    fn record(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let Some(last) = events.last() else {
+            return Ok(());
+        };
+        let time = last.as_ref().time;
+        let start = *self.start.get_or_insert(time);
+        let micros = (time.tv_sec - start.tv_sec) * 1_000_000 + (time.tv_usec - start.tv_usec);
+        writeln!(
+            self.out,
+            "frame {}.{:06}",
+            micros / 1_000_000,
+            micros % 1_000_000
+        )?;
+        for event in events {
+            writeln!(
+                self.out,
+                "{} {} {}",
+                event.event_type().0,
+                event.code(),
+                event.value()
+            )?;
+        }
+        self.out.flush()
+    }
+}
+
+fn write_header(config: &Config, spec: &TrackpadSpec, w: &mut impl Write) -> io::Result<()> {
+    let id = &spec.input_id;
+    writeln!(w, "# fukomaster recording")?;
+    writeln!(w, "format {FORMAT_VERSION}")?;
+    writeln!(w, "fukomaster {CRATE_VERSION}")?;
+    writeln!(
+        w,
+        "id {} {} {} {}",
+        id.bus_type().0,
+        id.vendor(),
+        id.product(),
+        id.version()
+    )?;
+    writeln!(
+        w,
+        "property {}",
+        list(spec.properties.iter().map(|prop| prop.0))
+    )?;
+    writeln!(w, "key {}", list(spec.keys.iter().map(Key::code)))?;
+    writeln!(w, "misc {}", list(spec.misc.iter().map(|misc| misc.0)))?;
+    for axis in &spec.abs_axes {
+        let info = axis.absinfo();
+        writeln!(
+            w,
+            "abs {} {} {} {} {} {}",
+            axis.code(),
+            info.minimum(),
+            info.maximum(),
+            info.fuzz(),
+            info.flat(),
+            info.resolution()
+        )?;
+    }
+
+    let options: [(&str, String); 12] = [
+        ("device-type", format!("{:?}", config.device_type)),
+        ("mt-protocol", format!("{:?}", config.mt_protocol)),
+        ("bus", format!("{:?}", config.bus)),
+        ("resolution", config.resolution.to_string()),
+        ("origin", format!("{:?}", config.origin)),
+        ("screen-size", format!("{:?}", config.screen_size)),
+        ("clock", format!("{:?}", config.clock)),
+        ("tracking-ids", format!("{:?}", config.tracking_ids)),
+        ("retouch-id", format!("{:?}", config.retouch_id)),
+        ("lift-order", format!("{:?}", config.lift_order)),
+        ("pressure-ramp", format!("{:?}", config.pressure_ramp)),
+        ("match-device", format!("{:?}", config.match_device)),
+    ];
+    for (name, value) in options {
+        writeln!(w, "config {name} {value}")?;
+    }
+    writeln!(w, "events")?;
+    Ok(())
+}
+
+fn list(codes: impl Iterator<Item = u16>) -> String {
+    codes
+        .map(|code| code.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Frames and trackpad capabilities read back from a recording
+pub struct Recording {
+    /// Version of fukomaster which made the recording
+    pub recorded_by: Option<String>,
+    pub spec: TrackpadSpec,
+    /// Options which shaped the trackpad, as `(name, value)`
+    pub config: Vec<(String, String)>,
+    /// Each frame's events, with its time since the first frame
+    pub frames: Vec<(Duration, Vec<InputEvent>)>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read recording {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("invalid recording {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let format = match lines.next() {
+            Some((_, line)) => line
+                .strip_prefix("format ")
+                .and_then(|format| format.trim().parse::<u32>().ok()),
+            None => None,
+        }
+        .ok_or_else(|| anyhow!("not a fukomaster recording, expected `format` first"))?;
+        match format {
+            FORMAT_VERSION => {}
+            newer if newer > FORMAT_VERSION => bail!(
+                "recording has format {newer}, but fukomaster {CRATE_VERSION} only reads up to \
+                 format {FORMAT_VERSION}; replay it with a newer version"
+            ),
+            older => bail!("recording has format {older}, which is no longer supported"),
+        }
+
+        let mut recording = Self {
+            recorded_by: None,
+            spec: TrackpadSpec {
+                name: trackpad::VIRTUAL_DEVICE_NAME,
+                input_id: InputId::new(BusType::BUS_USB, 0, 0, 0),
+                properties: AttributeSet::new(),
+                keys: AttributeSet::new(),
+                abs_axes: Vec::new(),
+                misc: AttributeSet::new(),
+                origin: (0, 0),
+                bounds: None,
+            },
+            config: Vec::new(),
+            frames: Vec::new(),
+        };
+        let mut in_events = false;
+        for (line_no, line) in lines {
+            let res = if in_events {
+                recording.parse_event_line(line)
+            } else if line == "events" {
+                in_events = true;
+                Ok(())
+            } else {
+                recording.parse_header_line(line)
+            };
+            res.with_context(|| format!("line {line_no}"))?;
+        }
+        if !in_events {
+            bail!("recording has no `events` line");
+        }
+        Ok(recording)
+    }
+
+    fn parse_header_line(&mut self, line: &str) -> Result<()> {
+        let (directive, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let numbers = || {
+            rest.split_whitespace()
+                .map(|n| {
+                    n.parse::<i32>()
+                        .with_context(|| format!("invalid number {n:?}"))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let code = |n: i32| u16::try_from(n).with_context(|| format!("invalid code {n}"));
+        match directive {
+            "fukomaster" => self.recorded_by = Some(rest.to_owned()),
+            "id" => {
+                let [bus, vendor, product, version] = numbers()?[..] else {
+                    bail!("expected `id <bus> <vendor> <product> <version>`");
+                };
+                self.spec.input_id = InputId::new(
+                    BusType(code(bus)?),
+                    code(vendor)?,
+                    code(product)?,
+                    code(version)?,
+                );
+            }
+            "property" => {
+                for prop in numbers()? {
+                    self.spec.properties.insert(PropType(code(prop)?));
+                }
+            }
+            "key" => {
+                for key in numbers()? {
+                    self.spec.keys.insert(Key::new(code(key)?));
+                }
+            }
+            "misc" => {
+                for misc in numbers()? {
+                    self.spec.misc.insert(MiscType(code(misc)?));
+                }
+            }
+            "abs" => {
+                let [axis, min, max, fuzz, flat, resolution] = numbers()?[..] else {
+                    bail!("expected `abs <code> <min> <max> <fuzz> <flat> <resolution>`");
+                };
+                self.spec.abs_axes.push(UinputAbsSetup::new(
+                    AbsoluteAxisType(code(axis)?),
+                    AbsInfo::new(0, min, max, fuzz, flat, resolution),
+                ));
+            }
+            "config" => {
+                let (name, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                self.config.push((name.to_owned(), value.to_owned()));
+            }
+            _ => debug!("Skipping unknown recording header line `{line}`"),
+        }
+        Ok(())
+    }
+
+    fn parse_event_line(&mut self, line: &str) -> Result<()> {
+        if let Some(time) = line.strip_prefix("frame ") {
+            let secs = time
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                .with_context(|| format!("invalid frame time {time:?}"))?;
+            self.frames
+                .push((Duration::from_secs_f64(secs), Vec::new()));
+            return Ok(());
+        }
+
+        let Some((_, events)) = self.frames.last_mut() else {
+            bail!("event before the first `frame`");
+        };
+        let mut parts = line.split_whitespace();
+        let (Some(event_type), Some(code), Some(value), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("expected `<type> <code> <value>`");
+        };
+        events.push(InputEvent::new(
+            EventType(event_type.parse().with_context(|| "invalid event type")?),
+            code.parse().with_context(|| "invalid event code")?,
+            value.parse().with_context(|| "invalid event value")?,
+        ));
+        Ok(())
+    }
+}
+
+/// Creates a virtual trackpad with the recording's capabilities, and emits its
+/// frames with their original timing
+pub async fn replay(recording: &Recording) -> Result<()> {
+    if let Some(recorded_by) = &recording.recorded_by {
+        info!("Replaying recording made by fukomaster {recorded_by}");
+    }
+    for (name, value) in &recording.config {
+        debug!("  {name} = {value}");
+    }
+
+    let mut device = recording
+        .spec
+        .build()
+        .with_context(|| "failed to create virtual trackpad")?;
+    // give the compositor time to recognize the device, as when simulating
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    info!("Created virtual trackpad");
+
+    let start = Instant::now();
+    for (offset, events) in &recording.frames {
+        tokio::time::sleep_until((start + *offset).into()).await;
+        device
+            .emit(events)
+            .with_context(|| "failed to emit frame")?;
+    }
+    info!("Replayed {} frames", recording.frames.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness;
+
+    #[test]
+    fn parses_example() {
+        let recording = Recording::parse(
+            "# fukomaster recording
+            format 1
+            fukomaster 0.2.1
+            id 3 4660 22136 273
+            property 0
+            key 325 330 333 334 335 328
+            misc 5
+            abs 53 -2147483648 2147483647 0 0 12
+            config device-type Trackpad
+            events
+            frame 0.000000
+            3 47 0
+            3 57 0
+            frame 0.008000
+            1 330 0",
+        )
+        .unwrap();
+        assert_eq!(recording.recorded_by.as_deref(), Some("0.2.1"));
+        assert_eq!(recording.spec.input_id.product(), 22136);
+        assert_eq!(recording.spec.keys.iter().count(), 6);
+        assert!(recording.spec.misc.contains(MiscType::MSC_TIMESTAMP));
+        assert_eq!(recording.spec.abs_axes.len(), 1);
+        assert_eq!(
+            recording.config,
+            [("device-type".to_owned(), "Trackpad".to_owned())]
+        );
+        let times = recording
+            .frames
+            .iter()
+            .map(|(time, events)| (time.as_millis(), events.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(times, [(0, 2), (8, 1)]);
+        assert_eq!(recording.frames[1].1[0].code(), Key::BTN_TOUCH.code());
+    }
+
+    #[test]
+    fn header_reads_back() {
+        let config = harness::config(&[]);
+        let spec = TrackpadSpec::new(&config);
+        let mut header = Vec::new();
+        write_header(&config, &spec, &mut header).unwrap();
+
+        let recording = Recording::parse(&String::from_utf8(header).unwrap()).unwrap();
+        assert_eq!(recording.recorded_by.as_deref(), Some(CRATE_VERSION));
+        assert_eq!(
+            format!("{:?}", recording.spec.input_id),
+            format!("{:?}", spec.input_id)
+        );
+        assert!(recording.spec.keys.iter().eq(spec.keys.iter()));
+        assert!(recording.spec.misc.iter().eq(spec.misc.iter()));
+        let codes =
+            |axes: &[UinputAbsSetup]| axes.iter().map(UinputAbsSetup::code).collect::<Vec<_>>();
+        assert_eq!(codes(&recording.spec.abs_axes), codes(&spec.abs_axes));
+        assert!(recording.frames.is_empty());
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        for contents in [
+            "",
+            "events",
+            "format one\nevents",
+            "format 0\nevents",
+            "format 2\nevents",
+        ] {
+            assert!(Recording::parse(contents).is_err(), "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_events() {
+        for contents in [
+            "format 1",
+            "format 1\nevents\n3 47 0",
+            "format 1\nevents\nframe -1",
+            "format 1\nevents\nframe 0\n3 47",
+            "format 1\nevents\nframe 0\n3 47 zero",
+            "format 1\nid 1 2 3\nevents",
+        ] {
+            assert!(Recording::parse(contents).is_err(), "{contents:?}");
+        }
+    }
+}
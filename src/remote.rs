@@ -0,0 +1,282 @@
+use std::{fs, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::mpsc,
+};
+
+use crate::{
+    control::{self, Command, Request},
+    gesture_loop::{Direction, LoopGesture},
+    states::Fingers,
+};
+
+/// Longest line accepted from a client, including the line ending
+const MAX_LINE_LEN: u64 = 256;
+
+/// How long a client has to authenticate after connecting
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How far a `swipe` moves if the client doesn't say, in trackpad units
+const DEFAULT_DISTANCE: i32 = 1000;
+
+/// How long a `swipe` takes if the client doesn't say
+const DEFAULT_DURATION: Duration = Duration::from_millis(250);
+
+/// Longest a `swipe` may take, since no other gestures can happen meanwhile
+const MAX_DURATION: Duration = Duration::from_secs(5);
+
+/// Starts accepting remote gesture clients on `addr`, forwarding their
+/// requests to `commands`
+///
+/// Clients send one command per line, and get one line back for each, either
+/// `ok <state>` or `error <message>`:
+///
+/// ```text
+/// auth <token>
+/// swipe <fingers> <up|down|left|right> [distance] [ms]
+/// status
+/// ```
+///
+/// `auth` with the contents of `token_file` must come first, otherwise the
+/// client is disconnected. A client which sends a line that is too long or
+/// isn't UTF-8 is also disconnected, since the rest of its stream can't be
+/// trusted to line up with commands.
+pub async fn listen(
+    addr: SocketAddr,
+    token_file: &Path,
+    commands: mpsc::UnboundedSender<Command>,
+) -> Result<()> {
+    let token = fs::read_to_string(token_file)
+        .with_context(|| format!("failed to read token file {}", token_file.display()))?;
+    let token = token.trim();
+    if token.is_empty() {
+        bail!("token file {} is empty", token_file.display());
+    }
+    let token = Arc::<str>::from(token);
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to listen on {addr}"))?;
+    info!("Listening for remote gestures on {addr}");
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    warn!("Failed to accept remote gesture client: {err}");
+                    continue;
+                }
+            };
+            debug!("Remote gesture client {peer} connected");
+            let token = token.clone();
+            let commands = commands.clone();
+            tokio::spawn(async move {
+                match serve_client(stream, &token, &commands).await {
+                    Ok(()) => debug!("Remote gesture client {peer} disconnected"),
+                    Err(err) => debug!("Remote gesture client {peer} disconnected: {err:#}"),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn serve_client(
+    stream: TcpStream,
+    token: &str,
+    commands: &mpsc::UnboundedSender<Command>,
+) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut read = BufReader::new(read);
+
+    let auth = tokio::time::timeout(AUTH_TIMEOUT, read_line(&mut read, &mut write))
+        .await
+        .ok()
+        .with_context(|| "timed out waiting for `auth`")??;
+    let Some(auth) = auth else {
+        return Ok(());
+    };
+    let given = auth.strip_prefix("auth ").unwrap_or_default();
+    if !token_matches(given, token) {
+        reply(&mut write, Err(anyhow!("not authenticated"))).await?;
+        bail!("failed to authenticate");
+    }
+    reply(&mut write, Ok("authenticated".to_owned())).await?;
+
+    while let Some(line) = read_line(&mut read, &mut write).await? {
+        let result = match parse_request(&line) {
+            Ok(request) => control::send(commands, request)
+                .await
+                .map(|status| status.state.to_owned()),
+            Err(err) => Err(err),
+        };
+        reply(&mut write, result).await?;
+    }
+    Ok(())
+}
+
+/// Reads the next line without its line ending, or `None` if the client has
+/// disconnected
+///
+/// A line which the client disconnects partway through is dropped.
+async fn read_line(
+    read: &mut BufReader<OwnedReadHalf>,
+    write: &mut OwnedWriteHalf,
+) -> Result<Option<String>> {
+    let mut buf = Vec::new();
+    let len = (&mut *read)
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut buf)
+        .await
+        .with_context(|| "failed to read from client")?;
+    if buf.last() != Some(&b'\n') {
+        if u64::try_from(len).unwrap_or(u64::MAX) < MAX_LINE_LEN {
+            return Ok(None);
+        }
+        reply(write, Err(anyhow!("line is too long"))).await?;
+        bail!("line is longer than {MAX_LINE_LEN} bytes");
+    }
+    buf.pop();
+    if buf.last() == Some(&b'\r') {
+        buf.pop();
+    }
+    let Ok(line) = String::from_utf8(buf) else {
+        reply(write, Err(anyhow!("line is not UTF-8"))).await?;
+        bail!("line is not UTF-8");
+    };
+    Ok(Some(line))
+}
+
+async fn reply(write: &mut OwnedWriteHalf, result: Result<String>) -> Result<()> {
+    let line = match result {
+        Ok(message) => format!("ok {message}\n"),
+        // a multi-line error would be read as several replies
+        Err(err) => format!("error {}\n", format!("{err:#}").replace('\n', " ")),
+    };
+    write
+        .write_all(line.as_bytes())
+        .await
+        .with_context(|| "failed to write to client")
+}
+
+/// Compares `given` to `token`, taking the same time wherever they differ
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn parse_request(line: &str) -> Result<Request> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        ["status"] => Ok(Request::Status),
+        ["swipe", fingers, direction, rest @ ..] if rest.len() <= 2 => {
+            let fingers = fingers
+                .parse::<u8>()
+                .ok()
+                .and_then(Fingers::from_count)
+                .ok_or_else(|| anyhow!("finger count must be 2 to 5"))?;
+            let direction = Direction::from_str(direction, true)
+                .map_err(|_| anyhow!("direction must be up, down, left or right"))?;
+            let distance = match rest.first() {
+                Some(distance) => distance
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|distance| *distance > 0)
+                    .ok_or_else(|| anyhow!("distance must be a positive whole number"))?,
+                None => DEFAULT_DISTANCE,
+            };
+            let duration = match rest.get(1) {
+                Some(ms) => ms
+                    .parse()
+                    .ok()
+                    .map(Duration::from_millis)
+                    .filter(|duration| *duration <= MAX_DURATION)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "duration must be a whole number of milliseconds, up to {}",
+                            MAX_DURATION.as_millis()
+                        )
+                    })?,
+                None => DEFAULT_DURATION,
+            };
+            Ok(Request::Perform {
+                gesture: LoopGesture {
+                    fingers,
+                    direction,
+                    distance,
+                    duration,
+                    interval: Duration::ZERO,
+                },
+            })
+        }
+        ["swipe", ..] => bail!("usage: swipe <fingers> <up|down|left|right> [distance] [ms]"),
+        [] => bail!("empty command"),
+        [command, ..] => bail!("unknown command `{command}`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gesture(line: &str) -> LoopGesture {
+        match parse_request(line).unwrap() {
+            Request::Perform { gesture } => gesture,
+            request => panic!("{line:?} should be a swipe, not {request:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_status() {
+        assert!(matches!(parse_request(" status "), Ok(Request::Status)));
+    }
+
+    #[test]
+    fn parses_swipe_with_defaults() {
+        let gesture = gesture("swipe 3 LEFT");
+        assert_eq!(gesture.fingers, Fingers::Three);
+        assert_eq!(gesture.direction, Direction::Left);
+        assert_eq!(gesture.distance, DEFAULT_DISTANCE);
+        assert_eq!(gesture.duration, DEFAULT_DURATION);
+    }
+
+    #[test]
+    fn parses_swipe_with_distance_and_duration() {
+        let gesture = gesture("swipe 4 up 500 100");
+        assert_eq!(gesture.fingers, Fingers::Four);
+        assert_eq!(gesture.direction, Direction::Up);
+        assert_eq!(gesture.distance, 500);
+        assert_eq!(gesture.duration, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        for line in [
+            "",
+            "jump",
+            "swipe",
+            "swipe 3",
+            "swipe 1 left",
+            "swipe 3 sideways",
+            "swipe 3 left 0",
+            "swipe 3 left -100",
+            "swipe 3 left 100 5001",
+            "swipe 3 left 100 100 100",
+        ] {
+            assert!(parse_request(line).is_err(), "{line:?}");
+        }
+    }
+}
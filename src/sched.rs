@@ -0,0 +1,111 @@
+use std::{fs, io, mem};
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+
+/// Applies a real-time priority and CPU affinity to every thread of the
+/// process, if they were asked for
+///
+/// Threads started afterwards inherit them from the thread which starts them.
+/// Failures are logged rather than returned, since gestures still work
+/// without these, only with less consistent latency.
+pub fn apply(rt_priority: Option<u8>, cpus: &[usize]) {
+    if rt_priority.is_none() && cpus.is_empty() {
+        return;
+    }
+    let threads = match threads() {
+        Ok(threads) => threads,
+        Err(err) => {
+            warn!("Failed to list threads, not changing scheduling: {err:#}");
+            return;
+        }
+    };
+
+    if let Some(priority) = rt_priority {
+        match for_each_thread(&threads, |tid| set_fifo(tid, priority)) {
+            Ok(()) => info!("Set real-time priority {priority} (SCHED_FIFO)"),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => warn!(
+                "Not allowed to set real-time priority, continuing without it; this needs \
+                 `CAP_SYS_NICE` or a high enough `RLIMIT_RTPRIO`"
+            ),
+            Err(err) => warn!(
+                "Failed to set real-time priority, continuing without it: {:#}",
+                anyhow::Error::new(err)
+            ),
+        }
+    }
+
+    if !cpus.is_empty() {
+        match for_each_thread(&threads, |tid| set_affinity(tid, cpus)) {
+            Ok(()) => info!("Pinned to CPUs {cpus:?}"),
+            Err(err) => warn!(
+                "Failed to pin to CPUs {cpus:?}, continuing on any CPU: {:#}",
+                anyhow::Error::new(err)
+            ),
+        }
+    }
+}
+
+/// Parses the index of a CPU core which a thread can be pinned to
+pub fn parse_cpu(s: &str) -> Result<usize> {
+    let max = usize::try_from(libc::CPU_SETSIZE).unwrap_or(usize::MAX);
+    s.parse()
+        .ok()
+        .filter(|cpu| *cpu < max)
+        .ok_or_else(|| anyhow!("CPU must be a whole number below {max}"))
+}
+
+/// Gets the IDs of every thread of the process
+fn threads() -> Result<Vec<libc::pid_t>> {
+    let entries =
+        fs::read_dir("/proc/self/task").with_context(|| "failed to read /proc/self/task")?;
+    let mut threads = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| "failed to read /proc/self/task entry")?;
+        if let Some(tid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            threads.push(tid);
+        }
+    }
+    Ok(threads)
+}
+
+fn for_each_thread(
+    threads: &[libc::pid_t],
+    f: impl Fn(libc::pid_t) -> io::Result<()>,
+) -> io::Result<()> {
+    threads.iter().try_for_each(|tid| match f(*tid) {
+        // the thread exited after we listed it
+        Err(err) if err.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+        res => res,
+    })
+}
+
+fn set_fifo(tid: libc::pid_t, priority: u8) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: i32::from(priority),
+    };
+    // SAFETY: `param` is a valid `sched_param` for the duration of the call
+    if unsafe { libc::sched_setscheduler(tid, libc::SCHED_FIFO, &param) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn set_affinity(tid: libc::pid_t, cpus: &[usize]) -> io::Result<()> {
+    // SAFETY: `cpu_set_t` is a plain bitmask, which is empty when zeroed
+    let mut set = unsafe { mem::zeroed::<libc::cpu_set_t>() };
+    for &cpu in cpus {
+        // SAFETY: `cpu` is checked to fit in the set when parsing arguments
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    // SAFETY: `set` is a valid `cpu_set_t` of the given size for the duration
+    // of the call
+    if unsafe { libc::sched_setaffinity(tid, mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::env;
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use log::{debug, warn};
+use tokio::sync::mpsc;
+use zbus::{proxy, zvariant::OwnedObjectPath, Connection};
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn get_session(&self, session_id: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+trait Session {
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+/// Starts watching whether the current session's screen is locked, using
+/// logind's `LockedHint`
+///
+/// The session is `$XDG_SESSION_ID` if it is set, and otherwise the session
+/// that this process belongs to. The lock state is sent to `send_locks` once
+/// at the start, then whenever it changes.
+pub async fn watch(send_locks: mpsc::UnboundedSender<bool>) -> Result<()> {
+    let connection = Connection::system()
+        .await
+        .with_context(|| "failed to connect to the system bus")?;
+    let manager = ManagerProxy::new(&connection)
+        .await
+        .with_context(|| "failed to create logind manager proxy")?;
+    let session_id = env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".to_owned());
+    let session_path = manager
+        .get_session(&session_id)
+        .await
+        .with_context(|| format!("failed to get logind session {session_id:?}"))?;
+    debug!("Watching lock state of logind session {session_path}");
+
+    let session = SessionProxy::builder(&connection)
+        .path(session_path)?
+        .build()
+        .await
+        .with_context(|| "failed to create logind session proxy")?;
+    let locked = session
+        .locked_hint()
+        .await
+        .with_context(|| "failed to read `LockedHint`")?;
+    let _ = send_locks.send(locked);
+
+    let mut changes = session.receive_locked_hint_changed().await;
+    tokio::spawn(async move {
+        while let Some(change) = changes.next().await {
+            match change.get().await {
+                Ok(locked) => {
+                    if send_locks.send(locked).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to read changed `LockedHint`: {:#}",
+                        anyhow::Error::new(err)
+                    );
+                }
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::{path::PathBuf, time::Duration};
+
+use ahash::AHashMap;
+use anyhow::{anyhow, bail, Result};
+use evdev::{Device, EventStream, InputEventKind, Key};
+use futures::{stream::FuturesUnordered, StreamExt};
+use log::{debug, warn};
+
+use crate::{config::Config, keys, states::Fingers, swipe};
+
+/// How long to keep listening after the first key press, to catch the user
+/// pressing more than one key
+const EXTRA_PRESS_WINDOW: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KeyPress {
+    path: PathBuf,
+    name: Option<String>,
+    key: Key,
+}
+
+/// Walks the user through picking the device and key to swipe with, and prints
+/// the flags to pass to fukomaster
+///
+/// All devices in `paths` which pass the allow and deny filters are listened to
+/// until a single key is pressed, or `timeout` elapses.
+pub async fn run(
+    config: &Config,
+    paths: Vec<PathBuf>,
+    fingers: Fingers,
+    timeout: Duration,
+) -> Result<()> {
+    let mut devices = AHashMap::<PathBuf, EventStream>::new();
+    println!("Input devices:");
+    for path in paths {
+        match swipe::add_device(path.clone(), &[], config, &mut devices) {
+            Ok(Ok(device)) => {
+                println!(
+                    "  {}: {}",
+                    path.display(),
+                    device.name().unwrap_or("(unnamed)")
+                );
+                if let Some(buttons) = buttons(device) {
+                    println!("    buttons: {buttons}");
+                }
+            }
+            Ok(Err(err)) => {
+                debug!("Will not listen to {path:?}: {err:#}");
+            }
+            Err(err) => {
+                warn!("Failed to open device {path:?}: {err:#}");
+            }
+        }
+    }
+
+    if devices.is_empty() {
+        bail!(
+            "no input devices could be opened - make sure you have permission to read devices \
+             under /dev/input"
+        );
+    }
+
+    println!();
+    println!(
+        "Press the button you want to use for {}-finger swipes (waiting {}s)...",
+        fingers.count(),
+        timeout.as_secs()
+    );
+
+    let first = tokio::time::timeout(timeout, next_key_press(&mut devices))
+        .await
+        .map_err(|_| anyhow!("no button was pressed within {}s", timeout.as_secs()))??;
+    let mut presses = vec![first];
+    while let Ok(press) =
+        tokio::time::timeout(EXTRA_PRESS_WINDOW, next_key_press(&mut devices)).await
+    {
+        let press = press?;
+        if !presses.contains(&press) {
+            presses.push(press);
+        }
+    }
+
+    println!();
+    if let [press] = presses.as_slice() {
+        println!(
+            "Detected {} on {} ({})",
+            keys::describe(press.key),
+            press.name.as_deref().unwrap_or("(unnamed)"),
+            press.path.display()
+        );
+        println!();
+        println!("Recommended flags:");
+        println!(
+            "  fukomaster -i {} -{} {}",
+            press.path.display(),
+            fingers.count(),
+            press.key.code()
+        );
+        Ok(())
+    } else {
+        println!("Detected more than one button:");
+        for press in &presses {
+            println!(
+                "  {} on {} ({})",
+                keys::describe(press.key),
+                press.name.as_deref().unwrap_or("(unnamed)"),
+                press.path.display()
+            );
+        }
+        bail!("more than one button was pressed - run setup again and press only one button")
+    }
+}
+
+/// Lists the buttons which `device` has, by name where they have one, leaving
+/// out keyboard keys
+fn buttons(device: &Device) -> Option<String> {
+    let buttons = device
+        .supported_keys()?
+        .iter()
+        .filter_map(|key| match keys::name(key) {
+            Some(name) if name.starts_with("BTN_") => Some(name),
+            Some(_) => None,
+            None => Some(key.code().to_string()),
+        })
+        .collect::<Vec<_>>();
+    (!buttons.is_empty()).then(|| buttons.join(", "))
+}
+
+async fn next_key_press(devices: &mut AHashMap<PathBuf, EventStream>) -> Result<KeyPress> {
+    loop {
+        let mut input_events = devices
+            .iter_mut()
+            .map(|(path, events)| async move {
+                let res = events.next_event().await;
+                (path.clone(), events.device().name().map(str::to_owned), res)
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let Some((path, name, input)) = input_events.next().await else {
+            bail!("no input devices left to listen to");
+        };
+        drop(input_events);
+
+        match input {
+            Ok(input) => {
+                if let InputEventKind::Key(key) = input.kind() {
+                    if input.value() == 1 {
+                        return Ok(KeyPress { path, name, key });
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to read events from {path:?}, no longer listening to it: {:#}",
+                    anyhow::Error::new(err)
+                );
+                devices.remove(&path);
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::io;
+
+use evdev::{AbsoluteAxisType, AttributeSetRef, Device, RelativeAxisType};
+
+/// Device which a swipe or scroll takes its motion from
+///
+/// This is what a gesture needs from the device besides its events, which
+/// are read separately. It's implemented by [`Device`], and by stand-ins in
+/// tests, which can't open real devices.
+pub trait Source {
+    /// Gets the name which the device reports, if it has one
+    fn name(&self) -> Option<&str>;
+
+    /// Grabs the device, so that only we see its events
+    fn grab(&mut self) -> io::Result<()>;
+
+    /// Releases a grab of the device
+    fn ungrab(&mut self) -> io::Result<()>;
+
+    /// Gets the relative axes which the device supports, if it has any
+    fn supported_relative_axes(&self) -> Option<&AttributeSetRef<RelativeAxisType>>;
+
+    /// Gets the absolute axes which the device supports, if it has any
+    fn supported_absolute_axes(&self) -> Option<&AttributeSetRef<AbsoluteAxisType>>;
+
+    /// Reads the current state of every absolute axis
+    fn get_abs_state(&self) -> io::Result<[libc::input_absinfo; libc::ABS_CNT]>;
+}
+
+impl Source for Device {
+    fn name(&self) -> Option<&str> {
+        self.name()
+    }
+
+    fn grab(&mut self) -> io::Result<()> {
+        self.grab()
+    }
+
+    fn ungrab(&mut self) -> io::Result<()> {
+        self.ungrab()
+    }
+
+    fn supported_relative_axes(&self) -> Option<&AttributeSetRef<RelativeAxisType>> {
+        self.supported_relative_axes()
+    }
+
+    fn supported_absolute_axes(&self) -> Option<&AttributeSetRef<AbsoluteAxisType>> {
+        self.supported_absolute_axes()
+    }
+
+    fn get_abs_state(&self) -> io::Result<[libc::input_absinfo; libc::ABS_CNT]> {
+        self.get_abs_state()
+    }
+}
@@ -1,10 +1,48 @@
-use std::path::PathBuf;
+use std::{
+    f32::consts::LN_2,
+    fmt, iter, mem,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
 
 use anyhow::{Context, Result};
-use evdev::{uinput::VirtualDevice, AbsoluteAxisType, Device, EventType, InputEvent, Key};
+use evdev::{AbsoluteAxisType, EventType, InputEvent, Key, RelativeAxisType, Synchronization};
 
-#[derive(Debug, Clone, Copy)]
+use tracing::{debug, info_span, trace, warn, Span};
+
+use crate::{
+    config::{self, Config},
+    gesture_loop::Direction,
+    pointer::HI_RES_PER_NOTCH,
+    source::Source,
+    trackpad::{DeviceType, MtProtocol, Sink, MAX_PRESSURE},
+};
+
+/// Approximate interval between frames emitted by a real trackpad
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(8);
+
+/// Longest interval between source motion events that is used for estimating
+/// speed, so that the first motion after a pause isn't treated as very slow
+const MAX_FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Speed, in units per second after gain, above which a release counts as a
+/// flick at a flick sensitivity of 1
+const FLICK_SPEED: f32 = 1500.0;
+
+/// Fraction of momentum kept from one frame to the next after a flick
+const FLICK_FRICTION: f32 = 0.85;
+
+/// Most frames of momentum emitted after a flick
+const MAX_FLICK_FRAMES: u32 = 30;
+
+/// Most moves that a single large jump is split into with `--interpolate`
+const MAX_INTERPOLATION_STEPS: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Fingers {
+    /// A single finger, which is only used for tap-and-drag, since a single
+    /// finger moves the cursor rather than performing a gesture
+    One,
     Two,
     Three,
     Four,
@@ -12,8 +50,21 @@ pub enum Fingers {
 }
 
 impl Fingers {
+    /// Gets the finger count for a gesture with `count` fingers, which must be
+    /// from 2 to 5
+    pub fn from_count(count: u8) -> Option<Self> {
+        match count {
+            2 => Some(Self::Two),
+            3 => Some(Self::Three),
+            4 => Some(Self::Four),
+            5 => Some(Self::Five),
+            _ => None,
+        }
+    }
+
     pub fn count(self) -> u8 {
         match self {
+            Self::One => 1,
             Self::Two => 2,
             Self::Three => 3,
             Self::Four => 4,
@@ -23,6 +74,7 @@ impl Fingers {
 
     pub fn btn_tool(self) -> Key {
         match self {
+            Self::One => Key::BTN_TOOL_FINGER,
             Self::Two => Key::BTN_TOOL_DOUBLETAP,
             Self::Three => Key::BTN_TOOL_TRIPLETAP,
             Self::Four => Key::BTN_TOOL_QUADTAP,
@@ -31,14 +83,262 @@ impl Fingers {
     }
 }
 
-fn abs_event(axis_type: AbsoluteAxisType, value: i32) -> InputEvent {
-    InputEvent::new_now(EventType::ABSOLUTE, axis_type.0, value)
+/// Gets the `BTN_TOOL_*` key for any number of fingers from 1 to 5
+fn btn_tool_for_count(count: u8) -> Key {
+    Fingers::from_count(count)
+        .unwrap_or(Fingers::One)
+        .btn_tool()
+}
+
+/// Clock used to timestamp events emitted to the virtual trackpad
+///
+/// Real trackpads report their events on the monotonic clock, and some
+/// consumers are sensitive to timestamps jumping backwards (which the realtime
+/// clock may do).
+///
+/// Every event within a single frame shares the same timestamp, which is
+/// captured once when the frame is built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Clock {
+    /// `CLOCK_MONOTONIC`
+    #[default]
+    Monotonic,
+    /// `CLOCK_REALTIME`
+    Realtime,
+}
+
+impl Clock {
+    pub fn now(self) -> libc::timeval {
+        let clock_id = match self {
+            Self::Monotonic => libc::CLOCK_MONOTONIC,
+            Self::Realtime => libc::CLOCK_REALTIME,
+        };
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `ts` is a valid, writable `timespec`
+        unsafe { libc::clock_gettime(clock_id, &mut ts) };
+        libc::timeval {
+            tv_sec: ts.tv_sec,
+            tv_usec: ts.tv_nsec / 1000,
+        }
+    }
+}
+
+/// How tracking IDs are assigned to fingers when they touch down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TrackingIds {
+    /// Each finger's tracking ID is its slot number, so the same IDs are
+    /// reused for every swipe
+    #[default]
+    Slot,
+    /// Each finger gets a new tracking ID from an increasing counter, the same
+    /// way that the kernel assigns them for real trackpads
+    Sequential,
+}
+
+/// Which tracking IDs fingers have when they're put back down mid-gesture
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RetouchId {
+    /// The fingers keep their tracking IDs and the touch keys stay pressed,
+    /// so the compositor sees the same gesture continue
+    #[default]
+    Reuse,
+    /// The fingers lift completely and touch down with new tracking IDs, so
+    /// the compositor sees the gesture end and a new one start
+    New,
+}
+
+/// Order of the events which lift the fingers at the end of a gesture
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LiftOrder {
+    /// Every slot is lifted from the first to the last, and `BTN_TOUCH` and
+    /// the `BTN_TOOL_*` key are released, all in one frame
+    #[default]
+    AllAtOnce,
+    /// Like `all-at-once`, but the slots are lifted from the last to the
+    /// first
+    Reverse,
+    /// Every slot is lifted in one frame, then `BTN_TOUCH` is released in a
+    /// frame of its own, once no tracking IDs are left
+    BtnTouchLast,
+}
+
+/// Which devices' relative motion moves a swipe in progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MotionSource {
+    /// Only the device which started the swipe
+    #[default]
+    Strict,
+    /// Any tracked device
+    Any,
+}
+
+/// When a swipe's source device is grabbed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum GrabTiming {
+    /// As soon as the trigger is pressed
+    #[default]
+    Immediate,
+    /// Once the source device first moves after the trigger is pressed
+    OnMotion,
+}
+
+/// Scales a distance along a curve which starts with a slope of 1, and whose
+/// slope doubles every `base` units, so that the distance travelled to reach
+/// a position grows logarithmically with it
+///
+/// The curve is odd and strictly increasing, so it keeps the direction of
+/// motion and moving back retraces it.
+fn log_scale(distance: f32, base: f32) -> f32 {
+    let scaled = base / LN_2 * ((distance.abs() / base).exp2() - 1.0);
+    scaled.copysign(distance)
+}
+
+/// Bends a straight move by `(x, y)` onto an arc which turns by `curvature`
+/// radians per unit travelled, keeping the distance from the start
+fn curve(x: f32, y: f32, curvature: f32) -> (f32, f32) {
+    // the chord of an arc points halfway between the directions at its ends
+    let (sin, cos) = (x.hypot(y) * curvature / 2.0).sin_cos();
+    (x * cos - y * sin, x * sin + y * cos)
+}
+
+/// Waits for the post-stop clear delay, then clears all touches, if enabled
+async fn clear_after_stop(config: &Config, sink: &mut Sink) -> Result<()> {
+    if let Some(delay) = config.post_stop_clear {
+        tokio::time::sleep(delay).await;
+        clear_touches(config, sink).with_context(|| "failed to clear touches")?;
+    }
+    Ok(())
+}
+
+/// Lifts every slot and releases every touch key, whether or not they are
+/// down
+fn clear_touches(config: &Config, sink: &mut Sink) -> Result<()> {
+    let time = config.clock.now();
+    let mut events = lift_contacts(config, time, 0..i32::from(Fingers::Five.count()));
+    if config.device_type == DeviceType::Trackpad {
+        events.extend(
+            (1..=Fingers::Five.count()).map(|count| key_event(time, btn_tool_for_count(count), 0)),
+        );
+    }
+    events.push(key_event(time, Key::BTN_TOUCH, 0));
+    sink.emit(&events)
+}
+
+fn event(time: libc::timeval, event_type: EventType, code: u16, value: i32) -> InputEvent {
+    InputEvent::from(libc::input_event {
+        time,
+        type_: event_type.0,
+        code,
+        value,
+    })
+}
+
+fn abs_event(time: libc::timeval, axis_type: AbsoluteAxisType, value: i32) -> InputEvent {
+    event(time, EventType::ABSOLUTE, axis_type.0, value)
+}
+
+/// Makes the events which report one finger at `(x, y)`, also giving it
+/// `tracking_id` and `pressure` if set
+///
+/// With the type A protocol, every touching finger must be reported in each
+/// frame.
+fn contact(
+    config: &Config,
+    time: libc::timeval,
+    finger: i32,
+    tracking_id: Option<i32>,
+    (x, y): (i32, i32),
+    pressure: Option<i32>,
+) -> Vec<InputEvent> {
+    let position = [
+        abs_event(time, AbsoluteAxisType::ABS_MT_POSITION_X, x),
+        abs_event(time, AbsoluteAxisType::ABS_MT_POSITION_Y, y),
+    ]
+    .into_iter()
+    .chain(pressure.map(|pressure| abs_event(time, AbsoluteAxisType::ABS_MT_PRESSURE, pressure)));
+    match config.mt_protocol {
+        MtProtocol::A => position
+            .chain([event(
+                time,
+                EventType::SYNCHRONIZATION,
+                Synchronization::SYN_MT_REPORT.0,
+                0,
+            )])
+            .collect(),
+        MtProtocol::B => iter::once(abs_event(time, AbsoluteAxisType::ABS_MT_SLOT, finger))
+            .chain(tracking_id.map(|id| abs_event(time, AbsoluteAxisType::ABS_MT_TRACKING_ID, id)))
+            .chain(position)
+            .collect(),
+    }
+}
+
+/// Makes the events which lift `fingers`
+///
+/// With the type A protocol, this can only lift every finger at once, by
+/// reporting none.
+fn lift_contacts(
+    config: &Config,
+    time: libc::timeval,
+    fingers: impl IntoIterator<Item = i32>,
+) -> Vec<InputEvent> {
+    match config.mt_protocol {
+        MtProtocol::A => vec![event(
+            time,
+            EventType::SYNCHRONIZATION,
+            Synchronization::SYN_MT_REPORT.0,
+            0,
+        )],
+        MtProtocol::B => fingers
+            .into_iter()
+            .flat_map(|finger| {
+                [
+                    abs_event(time, AbsoluteAxisType::ABS_MT_SLOT, finger),
+                    abs_event(time, AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
+                ]
+            })
+            .collect(),
+    }
+}
+
+fn key_event(time: libc::timeval, key: Key, value: i32) -> InputEvent {
+    event(time, EventType::KEY, key.0, value)
 }
 
+// there is only ever one state, so boxing the swipe would save nothing
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum State {
     Normal(Normal),
     Swiping(Swiping),
+    Scrolling(Scrolling),
+}
+
+impl State {
+    /// Gets when the fingers held after the last swipe should be lifted, if
+    /// there are any
+    pub fn held_until(&self, config: &Config) -> Option<Instant> {
+        match self {
+            Self::Normal(normal) => normal.held_until(config),
+            _ => None,
+        }
+    }
+
+    /// Gets when a keepalive frame should be emitted to the virtual trackpad,
+    /// if `--idle-keepalive` is used and no fingers are down
+    ///
+    /// With `--swipe-cmd-only`, nothing is emitted, so the trackpad is never
+    /// kept alive.
+    pub fn keepalive_at(&self, config: &Config, sink: &Sink) -> Option<Instant> {
+        match self {
+            Self::Normal(normal) if normal.held.is_none() && !config.swipe_cmd_only => {
+                Some(sink.last_emitted() + config.idle_keepalive?)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl Default for State {
@@ -48,7 +348,35 @@ impl Default for State {
 }
 
 #[derive(Debug)]
-pub struct Normal(());
+pub struct Normal {
+    /// Trigger press which hasn't been followed by motion yet, and may turn
+    /// out to be a tap
+    pub pending_tap: Option<PendingTap>,
+    /// Fingers left down after a swipe stopped, which the next swipe
+    /// continues if it starts within the merge window
+    held: Option<Held>,
+}
+
+/// Fingers which stay touching after a swipe stops, so that a quick second
+/// swipe in the same direction is part of the same gesture
+#[derive(Debug)]
+struct Held {
+    touch: Touch,
+    /// Direction which the fingers had mostly moved in when the swipe stopped
+    direction: Direction,
+    stopped_at: Instant,
+}
+
+/// Trigger press which is held back until it's clear whether it is a tap or
+/// the start of a swipe
+#[derive(Debug)]
+pub struct PendingTap {
+    pub trigger: Key,
+    /// Device which the trigger was pressed on
+    pub trigger_path: PathBuf,
+    pub fingers: Fingers,
+    pub pressed_at: Instant,
+}
 
 impl From<Normal> for State {
     fn from(value: Normal) -> Self {
@@ -58,25 +386,152 @@ impl From<Normal> for State {
 
 impl Normal {
     pub fn new() -> Self {
-        Self(())
+        Self {
+            pending_tap: None,
+            held: None,
+        }
     }
 
-    #[allow(clippy::unused_self)]
-    pub fn start_swiping(
-        self,
+    /// Gets when the fingers held after the last swipe should be lifted, if
+    /// there are any
+    fn held_until(&self, config: &Config) -> Option<Instant> {
+        let held = self.held.as_ref()?;
+        Some(held.stopped_at + config.merge_window?)
+    }
+
+    /// Lifts the fingers held after the last swipe, if there are any
+    pub async fn release_held(&mut self, config: &Config, sink: &mut Sink) -> Result<()> {
+        let Some(held) = self.held.take() else {
+            return Ok(());
+        };
+        trace!("Lifting fingers held after swipe");
+        held.touch.release(config, sink).await
+    }
+
+    /// Taps the trackpad with `fingers`, by touching down and lifting them a
+    /// frame later without moving
+    pub async fn tap(&mut self, config: &Config, sink: &mut Sink, fingers: Fingers) -> Result<()> {
+        self.release_held(config, sink).await?;
+        let fingers = config.supported_fingers_for(fingers);
+        let touch = Touch::down(config, sink, fingers)?;
+        tokio::time::sleep(FRAME_INTERVAL).await;
+        touch.lift(config, sink)
+    }
+
+    /// Starts a swipe, which continues the gesture of the fingers held after
+    /// the last swipe if they are still down with the same finger count
+    ///
+    /// A continued swipe turns back into a new gesture if its first motion
+    /// isn't in the same direction as the last swipe.
+    pub async fn start_swiping(
+        mut self,
+        config: &Config,
         source_path: PathBuf,
-        source: &mut Device,
-        sink: &mut VirtualDevice,
+        source: &mut impl Source,
+        sink: &mut Sink,
         trigger: Key,
         fingers: Fingers,
-        grab: bool,
     ) -> Result<Swiping> {
-        if grab {
+        let fingers = config.supported_fingers_for(fingers);
+        let should_grab = config.should_grab(&source_path, source.name());
+        let grab_pending = should_grab && config.grab_timing == GrabTiming::OnMotion;
+        let grabbed = should_grab && !grab_pending;
+        if grabbed {
             source
                 .grab()
                 .with_context(|| "failed to grab source device")?;
         }
 
+        let span = info_span!(
+            "swipe",
+            fingers = fingers.count(),
+            source = %source_path.display(),
+            grabbed,
+            updates = tracing::field::Empty,
+        );
+        let stick = config.stick.as_ref().and_then(|_| Stick::read(source));
+        let frames_at_start = sink.frames();
+        let (touch, merge_direction) = match self.held.take() {
+            Some(held) if held.touch.fingers == fingers => {
+                trace!(parent: &span, direction = ?held.direction, "continuing held fingers");
+                (held.touch, Some(held.direction))
+            }
+            held => {
+                if let Some(held) = held {
+                    held.touch.release(config, sink).await?;
+                }
+                let touch = Touch::down_for_swipe(config, sink, fingers)?;
+                trace!(parent: &span, "fingers down");
+                (touch, None)
+            }
+        };
+        Ok(Swiping {
+            trigger_path: source_path.clone(),
+            input_path: source_path,
+            grabbed,
+            grab_pending,
+            trigger,
+            end_key: None,
+            touch,
+            stick,
+            span,
+            started_at: Instant::now(),
+            updates: 0,
+            rel_events: 0,
+            raw_motion: (0, 0),
+            frames_at_start,
+            read_errors: 0,
+            buffered: (0, 0),
+            filter_x: MotionFilter::default(),
+            filter_y: MotionFilter::default(),
+            merge_direction,
+        })
+    }
+}
+
+/// Virtual fingers which are touching the virtual trackpad
+#[derive(Debug)]
+pub struct Touch {
+    pub fingers: Fingers,
+    /// Accumulated motion on the X axis, after gain but before multipliers
+    pub x: f32,
+    /// Accumulated motion on the Y axis, after gain but before multipliers
+    pub y: f32,
+    /// Tracking ID of each finger, which are kept when the fingers retouch
+    tracking_ids: Vec<i32>,
+    /// Position, after multipliers, at which the fingers last touched down
+    /// at the origin
+    recentered_at: (i32, i32),
+    /// Velocity of the most recent source frame, after gain, in units per
+    /// second
+    velocity: (f32, f32),
+    last_motion: Option<SystemTime>,
+    frame_interval: Duration,
+    /// Multiplier for new motion, after gain, which is changed while a turbo
+    /// key is held
+    pub boost: f32,
+    /// Monitor which was focused when the swipe started, whose scale applies
+    /// to the multipliers
+    pub monitor: Option<String>,
+    /// When the fingers touched down, if their pressure ramps up from zero
+    /// rather than starting at full pressure
+    pressed_at: Option<Instant>,
+}
+
+impl Touch {
+    /// Puts `fingers` down on the trackpad at its origin
+    pub fn down(config: &Config, sink: &mut Sink, fingers: Fingers) -> Result<Self> {
+        Self::touch_down(config, sink, fingers, false)
+    }
+
+    /// Puts `fingers` down on the trackpad at its origin, with their pressure
+    /// ramping up from zero if `ramp_pressure` is set
+    fn touch_down(
+        config: &Config,
+        sink: &mut Sink,
+        fingers: Fingers,
+        ramp_pressure: bool,
+    ) -> Result<Self> {
         /*
         E: 0.000001 0003 0039 8661	# EV_ABS / ABS_MT_TRACKING_ID   8661
         E: 0.000001 0003 0035 0690	# EV_ABS / ABS_MT_POSITION_X    690
@@ -97,58 +552,341 @@ impl Normal {
         E: 0.000001 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +0ms
         */
 
-        let events = (0..i32::from(fingers.count()))
-            .flat_map(|finger| {
-                [
-                    abs_event(AbsoluteAxisType::ABS_MT_SLOT, finger),
-                    abs_event(AbsoluteAxisType::ABS_MT_TRACKING_ID, finger),
-                    abs_event(AbsoluteAxisType::ABS_MT_POSITION_X, 0),
-                    abs_event(AbsoluteAxisType::ABS_MT_POSITION_Y, 0),
-                ]
-            })
-            .chain([
-                InputEvent::new(EventType::KEY, Key::BTN_TOUCH.0, 1),
-                InputEvent::new(EventType::KEY, fingers.btn_tool().0, 1),
-            ]);
-        sink.emit(&events.collect::<Vec<_>>())?;
-
-        Ok(Swiping {
-            input_path: source_path,
+        let tracking_ids = (0..i32::from(fingers.count()))
+            .map(|finger| sink.new_tracking_id(config.tracking_ids, finger))
+            .collect::<Vec<_>>();
+        let touch = Self {
             fingers,
-            trigger,
-            x: 0,
-            y: 0,
-        })
+            x: 0.0,
+            y: 0.0,
+            tracking_ids,
+            recentered_at: (0, 0),
+            velocity: (0.0, 0.0),
+            last_motion: None,
+            frame_interval: FRAME_INTERVAL,
+            boost: 1.0,
+            monitor: None,
+            pressed_at: ramp_pressure.then(Instant::now),
+        };
+
+        touch.emit_touch_down(config, sink)?;
+        Ok(touch)
     }
-}
 
-#[derive(Debug)]
-pub struct Swiping {
-    pub input_path: PathBuf,
-    pub trigger: Key,
-    pub fingers: Fingers,
-    pub x: i32,
-    pub y: i32,
-}
+    /// Reports every finger touching down at the origin with its tracking ID,
+    /// and presses the touch keys
+    fn emit_touch_down(&self, config: &Config, sink: &mut Sink) -> Result<()> {
+        let time = config.clock.now();
+        let origin = sink.origin();
+        let pressure = self.pressure(config);
+        let events = (0..i32::from(self.fingers.count()))
+            .zip(self.tracking_ids.iter().copied())
+            .flat_map(|(finger, tracking_id)| {
+                contact(config, time, finger, Some(tracking_id), origin, pressure)
+            })
+            .chain([key_event(time, Key::BTN_TOUCH, 1)])
+            .chain(
+                (config.device_type == DeviceType::Trackpad)
+                    .then(|| key_event(time, self.fingers.btn_tool(), 1)),
+            );
+        sink.emit(&events.collect::<Vec<_>>())
+    }
 
-impl From<Swiping> for State {
-    fn from(value: Swiping) -> Self {
-        Self::Swiping(value)
+    /// Gets the pressure that the fingers report, if pressure is enabled
+    ///
+    /// Fingers put down for a swipe start at zero, and reach full pressure
+    /// once the pressure ramp has passed.
+    fn pressure(&self, config: &Config) -> Option<i32> {
+        let ramp = config.pressure_ramp?;
+        let elapsed = self
+            .pressed_at
+            .map_or(ramp, |pressed_at| pressed_at.elapsed());
+        if elapsed >= ramp {
+            return Some(MAX_PRESSURE);
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        Some((MAX_PRESSURE as f32 * elapsed.as_secs_f32() / ramp.as_secs_f32()) as i32)
     }
-}
 
-impl Swiping {
-    pub fn update(
+    /// Puts `fingers` down on the trackpad at its origin, with their pressure
+    /// ramping up, then moves them by the start offset
+    fn down_for_swipe(config: &Config, sink: &mut Sink, fingers: Fingers) -> Result<Self> {
+        let mut touch = Self::touch_down(config, sink, fingers, true)?;
+        if config.start_offset != (0, 0) {
+            touch.shift(config, sink, config.start_offset)?;
+        }
+        Ok(touch)
+    }
+
+    /// Applies relative motion which the source device reported at `time`
+    pub async fn update(
         &mut self,
-        sink: &mut VirtualDevice,
+        config: &Config,
+        sink: &mut Sink,
+        time: SystemTime,
         dx: i32,
         dy: i32,
-        x_mult: f32,
-        y_mult: f32,
     ) -> Result<()> {
-        self.x += dx;
-        self.y += dy;
+        // X and Y motion of one frame arrive separately, so the velocity is
+        // only reset when a new frame starts
+        if self.last_motion != Some(time) {
+            self.velocity = (0.0, 0.0);
+        }
+        let gain = self.gain(config, time, dx, dy) * self.boost;
+        #[allow(clippy::cast_precision_loss)]
+        let (dx, dy) = (dx as f32 * gain, dy as f32 * gain);
+        // which way the fingers go depends on the sign of the multipliers
+        let (x_mult, y_mult) = config.multipliers_for(self.fingers, self.monitor.as_deref());
+        let (gain_x, gain_y) = config.direction_gains.for_motion(dx * x_mult, dy * y_mult);
+        let (dx, dy) = (dx * gain_x, dy * gain_y);
+        let interval = self.frame_interval.as_secs_f32();
+        self.velocity.0 += dx / interval;
+        self.velocity.1 += dy / interval;
+        match config.interpolate {
+            Some(max_step) => self.move_smoothly(config, sink, dx, dy, max_step).await,
+            None => self.move_by(config, sink, dx, dy),
+        }
+    }
+
+    /// Moves the fingers like [`Touch::move_by`], but splits a move which
+    /// would jump further than `max_step` trackpad units into smaller ones,
+    /// spread out over a frame
+    ///
+    /// The last step always lands exactly where a single move would have.
+    async fn move_smoothly(
+        &mut self,
+        config: &Config,
+        sink: &mut Sink,
+        dx: f32,
+        dy: f32,
+        max_step: u32,
+    ) -> Result<()> {
+        let (x_mult, y_mult) = config.multipliers_for(self.fingers, self.monitor.as_deref());
+        #[allow(clippy::cast_precision_loss)]
+        let steps = ((dx * x_mult).hypot(dy * y_mult) / max_step as f32).ceil();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let steps = (steps as u32).min(MAX_INTERPOLATION_STEPS);
+        if steps <= 1 {
+            return self.move_by(config, sink, dx, dy);
+        }
+
+        let (start_x, start_y) = (self.x, self.y);
+        let mut interval = tokio::time::interval(FRAME_INTERVAL / steps);
+        for step in 1..steps {
+            interval.tick().await;
+            #[allow(clippy::cast_precision_loss)]
+            let t = step as f32 / steps as f32;
+            self.move_to(config, sink, start_x + dx * t, start_y + dy * t)?;
+        }
+        interval.tick().await;
+        self.move_to(config, sink, start_x + dx, start_y + dy)
+    }
+
+    /// Gets the velocity of the source at the moment it stopped, or zero if
+    /// it had already stopped moving
+    fn release_velocity(&self) -> (f32, f32) {
+        let still_moving = self
+            .last_motion
+            .and_then(|time| time.elapsed().ok())
+            .is_some_and(|since| since < MAX_FRAME_INTERVAL);
+        if still_moving {
+            self.velocity
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    /// Keeps the fingers moving after a flick, slowing down by friction until
+    /// they come to rest
+    async fn momentum(
+        &mut self,
+        config: &Config,
+        sink: &mut Sink,
+        velocity: (f32, f32),
+    ) -> Result<()> {
+        let (mut vx, mut vy) = velocity;
+        let dt = FRAME_INTERVAL.as_secs_f32();
+        let mut interval = tokio::time::interval(FRAME_INTERVAL);
+        for _ in 0..MAX_FLICK_FRAMES {
+            vx *= FLICK_FRICTION;
+            vy *= FLICK_FRICTION;
+            if vx.hypot(vy) < FLICK_SPEED / 10.0 {
+                break;
+            }
+            interval.tick().await;
+            self.move_by(config, sink, vx * dt, vy * dt)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the fingers by an amount of motion which already has gain applied
+    fn move_by(&mut self, config: &Config, sink: &mut Sink, dx: f32, dy: f32) -> Result<()> {
+        self.move_to(config, sink, self.x + dx, self.y + dy)
+    }
+
+    /// Moves the fingers to where `to_x` and `to_y` of accumulated motion puts
+    /// them
+    fn move_to(&mut self, config: &Config, sink: &mut Sink, to_x: f32, to_y: f32) -> Result<()> {
+        self.x = to_x;
+        self.y = to_y;
+
+        let (x, y) = self.relative_position(config);
+        if config
+            .recenter_distance
+            .is_some_and(|distance| x.unsigned_abs() > distance || y.unsigned_abs() > distance)
+        {
+            self.recenter(config, sink)?;
+            self.recentered_at = self.position(config);
+            return Ok(());
+        }
+        self.emit_position(config, sink, x, y)
+    }
+
+    /// Lifts the fingers and puts them back down at the origin, so that they
+    /// can keep moving in the same direction without reaching the edge of the
+    /// position axes
+    ///
+    /// With [`RetouchId::Reuse`], the fingers keep their tracking IDs, and the
+    /// `BTN_TOUCH` and `BTN_TOOL_*` keys stay pressed throughout, so that the
+    /// compositor sees the same fingers briefly leaving the surface rather
+    /// than a new gesture. With the type A protocol, lifting every finger
+    /// would end the gesture, so the fingers jump straight back instead.
+    ///
+    /// With [`RetouchId::New`], the fingers are lifted like at the end of a
+    /// swipe and touch down with new tracking IDs, starting a new gesture.
+    fn recenter(&mut self, config: &Config, sink: &mut Sink) -> Result<()> {
+        if config.retouch_id == RetouchId::New {
+            self.lift(config, sink)?;
+            self.tracking_ids = (0..i32::from(self.fingers.count()))
+                .map(|finger| sink.new_tracking_id(config.tracking_ids, finger))
+                .collect();
+            return self.emit_touch_down(config, sink);
+        }
+
+        if config.mt_protocol == MtProtocol::B {
+            let time = config.clock.now();
+            sink.emit(&lift_contacts(
+                config,
+                time,
+                0..i32::from(self.fingers.count()),
+            ))?;
+        }
+
+        let origin = sink.origin();
+        let time = config.clock.now();
+        let pressure = self.pressure(config);
+        let events = (0..i32::from(self.fingers.count()))
+            .zip(self.tracking_ids.iter().copied())
+            .flat_map(|(finger, tracking_id)| {
+                contact(config, time, finger, Some(tracking_id), origin, pressure)
+            });
+        sink.emit(&events.collect::<Vec<_>>())?;
+        Ok(())
+    }
+
+    /// Estimates the speed of the source device from this motion, and picks
+    /// the gain to apply to it
+    ///
+    /// The gain moves linearly from `gain_low` when stationary to `gain_high`
+    /// at `transition_speed` and above, unless an acceleration profile is set.
+    fn gain(&mut self, config: &Config, time: SystemTime, dx: i32, dy: i32) -> f32 {
+        // X and Y motion of the same frame arrive as separate events with the
+        // same timestamp, so they share the interval since the previous frame
+        if let Some(last_motion) = self.last_motion {
+            if let Ok(interval) = time.duration_since(last_motion) {
+                if !interval.is_zero() {
+                    self.frame_interval = interval.min(MAX_FRAME_INTERVAL);
+                }
+            }
+        }
+        self.last_motion = Some(time);
+
+        #[allow(clippy::cast_precision_loss)]
+        let distance = (dx as f32).hypot(dy as f32);
+        let speed = distance / self.frame_interval.as_secs_f32();
+        if let Some(accel) = config.accel {
+            return accel.gain(speed);
+        }
+        let t = (speed / config.transition_speed).clamp(0.0, 1.0);
+        config.gain_low + (config.gain_high - config.gain_low) * t
+    }
+
+    /// Moves the fingers by `offset` trackpad units straight after they touch
+    /// down, and keeps them shifted by it from then on
+    ///
+    /// Returning the fingers to the start, e.g. with `--boomerang`, moves them
+    /// back to where they touched down, not to the offset.
+    fn shift(&mut self, config: &Config, sink: &mut Sink, offset: (i32, i32)) -> Result<()> {
+        let (x, y) = offset;
+        self.recentered_at = (x.saturating_neg(), y.saturating_neg());
+        self.emit_position(config, sink, x, y)
+    }
+
+    /// Logs whether the fingers moved far and fast enough to meet the
+    /// compositor's [`Thresholds`](config::Thresholds), returning whether they
+    /// moved far enough
+    fn log_thresholds(&self, config: &Config, span: &Span) -> bool {
+        let thresholds = config.thresholds;
+        let units_per_mm = f32::from(config.resolution.max(1));
+        let (x_mult, y_mult) = config.multipliers_for(self.fingers, self.monitor.as_deref());
+
+        let (x, y) = self.position(config);
+        #[allow(clippy::cast_precision_loss)]
+        let distance_mm = (x as f32).hypot(y as f32) / units_per_mm;
+        let (vx, vy) = self.release_velocity();
+        let speed_mm_s = (vx * x_mult).hypot(vy * y_mult) / units_per_mm;
+
+        let distance_met = distance_mm >= thresholds.distance_mm;
+        let speed_met = speed_mm_s >= thresholds.speed_mm_s;
+        let diagnosis = match (distance_met, speed_met) {
+            (false, _) => "too short to be recognized as a gesture",
+            (true, true) => "far and fast enough to commit",
+            (true, false) => "recognized, but only commits if it moved far enough",
+        };
+        debug!(
+            parent: span,
+            distance_mm,
+            distance_met,
+            speed_mm_s,
+            speed_met,
+            "swipe was {diagnosis}"
+        );
+        distance_met
+    }
+
+    /// Gets the position of the virtual fingers relative to where they first
+    /// touched down, after applying multipliers
+    fn position(&self, config: &Config) -> (i32, i32) {
+        let (x_mult, y_mult) = config.multipliers_for(self.fingers, self.monitor.as_deref());
+        let (x, y) = match config.log_scale {
+            Some(base) => (log_scale(self.x, base), log_scale(self.y, base)),
+            None => (self.x, self.y),
+        };
+        let (x, y) = (x * x_mult, y * y_mult);
+        let (x, y) = match config.curvature {
+            Some(curvature) => curve(x, y, curvature),
+            None => (x, y),
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        let x = x as i32;
+        #[allow(clippy::cast_possible_truncation)]
+        let y = y as i32;
+        (x, y)
+    }
+
+    /// Gets the position of the virtual fingers relative to where they last
+    /// touched down at the origin, after applying multipliers
+    fn relative_position(&self, config: &Config) -> (i32, i32) {
+        let (x, y) = self.position(config);
+        (
+            x.saturating_sub(self.recentered_at.0),
+            y.saturating_sub(self.recentered_at.1),
+        )
+    }
 
+    /// Moves the fingers to a position relative to where they touched down,
+    /// in trackpad units
+    pub fn emit_position(&self, config: &Config, sink: &mut Sink, x: i32, y: i32) -> Result<()> {
         /*
         E: 0.020080 0003 002f 0000	# EV_ABS / ABS_MT_SLOT          0
         E: 0.020080 0003 0035 0686	# EV_ABS / ABS_MT_POSITION_X    686
@@ -162,32 +900,204 @@ impl Swiping {
         E: 0.020080 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +7ms
         */
 
-        #[allow(clippy::cast_precision_loss)]
-        #[allow(clippy::cast_possible_truncation)]
-        let x = ((self.x as f32) * x_mult) as i32;
-        #[allow(clippy::cast_precision_loss)]
-        #[allow(clippy::cast_possible_truncation)]
-        let y = ((self.y as f32) * y_mult) as i32;
-
-        let events = (0..i32::from(self.fingers.count())).flat_map(|finger| {
-            [
-                abs_event(AbsoluteAxisType::ABS_MT_SLOT, finger),
-                abs_event(AbsoluteAxisType::ABS_MT_POSITION_X, x),
-                abs_event(AbsoluteAxisType::ABS_MT_POSITION_Y, y),
-            ]
-        });
-        sink.emit(&events.collect::<Vec<_>>())?;
+        self.emit_frame(config, sink, (x, y), self.pressure(config))
+    }
+
+    /// Reports the fingers at a position relative to where they touched down,
+    /// with `pressure` if set
+    fn emit_frame(
+        &self,
+        config: &Config,
+        sink: &mut Sink,
+        (x, y): (i32, i32),
+        pressure: Option<i32>,
+    ) -> Result<()> {
+        let time = config.clock.now();
+        let mut events = Vec::new();
+        for finger in 0..i32::from(self.fingers.count()) {
+            // each finger is jittered separately, like real fingers
+            let (jitter_x, jitter_y) = sink.jitter();
+            let position =
+                sink.absolute_position(x.saturating_add(jitter_x), y.saturating_add(jitter_y));
+            events.extend(contact(config, time, finger, None, position, pressure));
+        }
+        sink.emit(&events)
+    }
+
+    /// Ramps the fingers' pressure down to zero where they are, before they
+    /// lift at the end of a swipe, if there is a pressure ramp
+    async fn release_pressure(&self, config: &Config, sink: &mut Sink) -> Result<()> {
+        let Some(ramp) = config.pressure_ramp.filter(|ramp| !ramp.is_zero()) else {
+            return Ok(());
+        };
+        let from = self.pressure(config).unwrap_or(MAX_PRESSURE);
+        let steps = (ramp.as_millis() / FRAME_INTERVAL.as_millis()).max(1);
+        let steps = i32::try_from(steps).unwrap_or(i32::MAX);
+        let position = self.relative_position(config);
 
+        let mut interval = tokio::time::interval(FRAME_INTERVAL);
+        for step in 1..=steps {
+            interval.tick().await;
+            let pressure =
+                i32::try_from(i64::from(from) * i64::from(steps - step) / i64::from(steps))
+                    .expect("interpolated value should be between 0 and `from`");
+            self.emit_frame(config, sink, position, Some(pressure))?;
+        }
         Ok(())
     }
 
-    pub fn stop(self, source: &mut Device, sink: &mut VirtualDevice, grab: bool) -> Result<Normal> {
-        if grab {
-            source
-                .ungrab()
-                .with_context(|| "failed to ungrab source device")?;
+    /// Slides the fingers from their current position back to the start
+    /// position over `duration`
+    async fn boomerang(&self, config: &Config, sink: &mut Sink, duration: Duration) -> Result<()> {
+        let (from_x, from_y) = self.relative_position(config);
+        let steps = (duration.as_millis() / FRAME_INTERVAL.as_millis()).max(1);
+        let steps = i32::try_from(steps).unwrap_or(i32::MAX);
+
+        let mut interval = tokio::time::interval(FRAME_INTERVAL);
+        for step in 1..=steps {
+            interval.tick().await;
+            let remaining = steps - step;
+            let x = i32::try_from(i64::from(from_x) * i64::from(remaining) / i64::from(steps))
+                .expect("interpolated value should be between 0 and `from_x`");
+            let y = i32::try_from(i64::from(from_y) * i64::from(remaining) / i64::from(steps))
+                .expect("interpolated value should be between 0 and `from_y`");
+            self.emit_position(config, sink, x, y)?;
         }
+        Ok(())
+    }
+
+    /// Keeps the fingers still at their current position for `duration`,
+    /// emitting a frame every frame interval so that the compositor sees them
+    /// resting
+    ///
+    /// With the type B protocol, these are keepalive frames, since the kernel
+    /// drops slot positions which haven't changed. Type A has to report every
+    /// finger in every frame, and its positions are never dropped, so the
+    /// fingers are reported where they are instead.
+    async fn rest(&self, config: &Config, sink: &mut Sink, duration: Duration) -> Result<()> {
+        let steps = (duration.as_millis() / FRAME_INTERVAL.as_millis()).max(1);
+        let (x, y) = self.relative_position(config);
+
+        let mut interval = tokio::time::interval(FRAME_INTERVAL);
+        for _ in 0..=steps {
+            interval.tick().await;
+            match config.mt_protocol {
+                MtProtocol::A => self.emit_position(config, sink, x, y)?,
+                MtProtocol::B => sink.keepalive(config.clock.now())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Lifts the fingers off the trackpad one at a time, a frame apart, like
+    /// real fingers leaving the surface at the end of a flick
+    ///
+    /// The fingers all share one position, so the slots stand in for their
+    /// order along the motion: slot 0 trails when moving right or down, and
+    /// leads otherwise. The trailing finger lifts first.
+    async fn lift_staggered(self, config: &Config, sink: &mut Sink, forward: bool) -> Result<()> {
+        let count = self.fingers.count();
+        let mut order = (0..i32::from(count)).collect::<Vec<_>>();
+        if !forward {
+            order.reverse();
+        }
+
+        // type A has to report the fingers which are still down every frame
+        let (x, y) = self.relative_position(config);
+        let position = sink.absolute_position(x, y);
+
+        let mut interval = tokio::time::interval(FRAME_INTERVAL);
+        for (remaining, finger) in (0..count).rev().zip(order) {
+            interval.tick().await;
+            let time = config.clock.now();
+            let mut events = match config.mt_protocol {
+                MtProtocol::A if remaining > 0 => (0..i32::from(remaining))
+                    .flat_map(|finger| {
+                        contact(config, time, finger, None, position, self.pressure(config))
+                    })
+                    .collect(),
+                _ => lift_contacts(config, time, [finger]),
+            };
+            if config.device_type == DeviceType::Trackpad {
+                events.push(key_event(time, btn_tool_for_count(remaining + 1), 0));
+                if remaining > 0 {
+                    events.push(key_event(time, btn_tool_for_count(remaining), 1));
+                }
+            }
+            if remaining == 0 {
+                events.push(key_event(time, Key::BTN_TOUCH, 0));
+            }
+            sink.emit(&events)?;
+        }
+        Ok(())
+    }
 
+    /// Changes how many fingers are touching, without lifting the ones which
+    /// stay down
+    ///
+    /// Added fingers touch down where the others are, with new tracking IDs,
+    /// and removed fingers lift from the last slot backwards. The `BTN_TOOL_*`
+    /// key changes to match in the same frame, like when a real finger lands
+    /// or leaves mid-gesture.
+    pub fn change_fingers(
+        &mut self,
+        config: &Config,
+        sink: &mut Sink,
+        fingers: Fingers,
+    ) -> Result<()> {
+        let (from, to) = (i32::from(self.fingers.count()), i32::from(fingers.count()));
+        if from == to {
+            return Ok(());
+        }
+
+        let time = config.clock.now();
+        let (x, y) = self.relative_position(config);
+        let position = sink.absolute_position(x, y);
+        let pressure = self.pressure(config);
+        let mut events = Vec::new();
+        match config.mt_protocol {
+            // type A has to report every finger which is still down
+            MtProtocol::A => {
+                for finger in 0..to {
+                    events.extend(contact(config, time, finger, None, position, pressure));
+                }
+            }
+            MtProtocol::B if to > from => {
+                for finger in from..to {
+                    let tracking_id = sink.new_tracking_id(config.tracking_ids, finger);
+                    self.tracking_ids.push(tracking_id);
+                    events.extend(contact(
+                        config,
+                        time,
+                        finger,
+                        Some(tracking_id),
+                        position,
+                        pressure,
+                    ));
+                }
+            }
+            MtProtocol::B => events.extend(lift_contacts(config, time, (to..from).rev())),
+        }
+        self.tracking_ids.truncate(usize::from(fingers.count()));
+        if config.device_type == DeviceType::Trackpad {
+            events.push(key_event(time, self.fingers.btn_tool(), 0));
+            events.push(key_event(time, fingers.btn_tool(), 1));
+        }
+        sink.emit(&events)?;
+        self.fingers = fingers;
+        Ok(())
+    }
+
+    /// Lifts the fingers at the end of a gesture, ramping their pressure down
+    /// first, then clears every touch if `--post-stop-clear` is set
+    async fn release(&self, config: &Config, sink: &mut Sink) -> Result<()> {
+        self.release_pressure(config, sink).await?;
+        self.lift(config, sink)?;
+        clear_after_stop(config, sink).await
+    }
+
+    /// Lifts the fingers off the trackpad
+    pub fn lift(&self, config: &Config, sink: &mut Sink) -> Result<()> {
         /*
         E: 2.992985 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +7ms
         E: 3.000143 0003 002f 0001	# EV_ABS / ABS_MT_SLOT          1
@@ -206,19 +1116,1080 @@ impl Swiping {
         E: 3.007174 0000 0000 0000	# ------------ SYN_REPORT (0) ---------- +7ms
         */
 
-        let events = (0..i32::from(self.fingers.count()))
-            .flat_map(|finger| {
-                [
-                    abs_event(AbsoluteAxisType::ABS_MT_SLOT, finger),
-                    abs_event(AbsoluteAxisType::ABS_MT_TRACKING_ID, -1),
-                ]
-            })
-            .chain([
-                InputEvent::new_now(EventType::KEY, Key::BTN_TOOL_FINGER.0, 0),
-                InputEvent::new_now(EventType::KEY, self.fingers.btn_tool().0, 0),
-            ]);
+        let time = config.clock.now();
+        let mut slots = (0..i32::from(self.fingers.count())).collect::<Vec<_>>();
+        if config.lift_order == LiftOrder::Reverse {
+            slots.reverse();
+        }
+        let btn_touch_last = config.lift_order == LiftOrder::BtnTouchLast;
+        let events = lift_contacts(config, time, slots)
+            .into_iter()
+            .chain((!btn_touch_last).then(|| key_event(time, Key::BTN_TOUCH, 0)))
+            .chain(
+                (config.device_type == DeviceType::Trackpad)
+                    .then(|| key_event(time, self.fingers.btn_tool(), 0)),
+            );
         sink.emit(&events.collect::<Vec<_>>())?;
 
-        Ok(Normal(()))
+        if btn_touch_last {
+            sink.emit(&[key_event(config.clock.now(), Key::BTN_TOUCH, 0)])?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Swiping {
+    pub input_path: PathBuf,
+    /// Whether the source device is grabbed
+    pub grabbed: bool,
+    /// Whether the source device will be grabbed once it moves
+    grab_pending: bool,
+    pub trigger: Key,
+    /// Device which the trigger was pressed on, and the only one whose keys
+    /// can end the swipe
+    ///
+    /// Composite devices, like wireless receivers, may report the same button
+    /// on several event nodes, and the duplicates mustn't end the swipe.
+    pub trigger_path: PathBuf,
+    /// If set, this swipe is a held drag: releasing `trigger` doesn't end it,
+    /// and it only ends when this key is pressed
+    pub end_key: Option<Key>,
+    pub touch: Touch,
+    /// Analog stick of the source device, if it is being used as a motion
+    /// source
+    stick: Option<Stick>,
+    /// Span covering this swipe from start to stop, for diagnosing timing
+    span: Span,
+    started_at: Instant,
+    /// How many times the fingers have been moved
+    updates: u32,
+    /// How many relative motion events have been received from the source,
+    /// including ones buffered during the emit delay
+    rel_events: u32,
+    /// Sum of the raw relative motion received from the source
+    raw_motion: (i64, i64),
+    /// [`Sink::frames`] when the swipe started, before the fingers touched
+    /// down
+    frames_at_start: u64,
+    /// How many reads from the source device have failed in a row
+    read_errors: u32,
+    /// Motion received during the emit delay, which hasn't been applied yet
+    buffered: (i32, i32),
+    /// Dead zone and smoothing of the source's motion on the X axis
+    filter_x: MotionFilter,
+    /// Dead zone and smoothing of the source's motion on the Y axis
+    filter_y: MotionFilter,
+    /// If set, this swipe continues the fingers held after the last swipe,
+    /// which moved in this direction, and hasn't moved yet
+    merge_direction: Option<Direction>,
+}
+
+/// Deflection of a gamepad's analog stick, which moves the fingers at a speed
+/// proportional to how far it is pushed
+#[derive(Debug)]
+struct Stick {
+    x: StickAxis,
+    y: StickAxis,
+}
+
+impl Stick {
+    /// Reads the current state of the source's right analog stick, if it has
+    /// one
+    fn read(source: &impl Source) -> Option<Self> {
+        let axes = source.supported_absolute_axes()?;
+        if !axes.contains(AbsoluteAxisType::ABS_RX) || !axes.contains(AbsoluteAxisType::ABS_RY) {
+            return None;
+        }
+
+        let abs_state = source
+            .get_abs_state()
+            .map_err(|err| {
+                warn!(
+                    "Failed to read analog stick state, stick will not be used: {:#}",
+                    anyhow::Error::new(err)
+                );
+            })
+            .ok()?;
+        Some(Self {
+            x: StickAxis::new(abs_state[usize::from(AbsoluteAxisType::ABS_RX.0)]),
+            y: StickAxis::new(abs_state[usize::from(AbsoluteAxisType::ABS_RY.0)]),
+        })
+    }
+
+    /// Gets the velocity of the fingers from the stick's deflection, in
+    /// trackpad units per second before multipliers
+    ///
+    /// Inside the deadzone, the fingers don't move. Outside of it, the speed
+    /// ramps up from 0 at the edge of the deadzone to `speed` at full
+    /// deflection.
+    fn velocity(&self, stick_config: config::Stick) -> (f32, f32) {
+        let (x, y) = (self.x.value, self.y.value);
+        let deflection = x.hypot(y);
+        if deflection <= stick_config.deadzone {
+            return (0.0, 0.0);
+        }
+
+        let scale = (deflection.min(1.0) - stick_config.deadzone)
+            / (1.0 - stick_config.deadzone)
+            / deflection
+            * stick_config.speed;
+        (x * scale, y * scale)
+    }
+}
+
+#[derive(Debug)]
+struct StickAxis {
+    min: i32,
+    max: i32,
+    /// Deflection from the center, from -1.0 to 1.0
+    value: f32,
+}
+
+impl StickAxis {
+    fn new(info: libc::input_absinfo) -> Self {
+        let mut axis = Self {
+            min: info.minimum,
+            max: info.maximum,
+            value: 0.0,
+        };
+        axis.set(info.value);
+        axis
+    }
+
+    fn set(&mut self, raw: i32) {
+        let center = (f64::from(self.min) + f64::from(self.max)) / 2.0;
+        let half_range = (f64::from(self.max) - f64::from(self.min)) / 2.0;
+        #[allow(clippy::cast_possible_truncation)]
+        let value = ((f64::from(raw) - center) / half_range) as f32;
+        self.value = if value.is_finite() {
+            value.clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Dead zone and smoothing state of one axis of a swipe's motion
+#[derive(Debug, Default)]
+struct MotionFilter {
+    /// Motion so far, while the swipe is still inside the dead zone
+    in_dead_zone: i64,
+    /// Whether the swipe has left the dead zone
+    escaped: bool,
+    /// Smoothed motion per event
+    smoothed: f32,
+    /// Smoothed motion which was rounded off, and is carried to the next event
+    remainder: f32,
+}
+
+impl MotionFilter {
+    /// Filters one event's motion along the axis
+    #[allow(clippy::cast_possible_truncation)]
+    fn apply(&mut self, filter: config::AxisFilter, delta: i32) -> i32 {
+        let delta = if self.escaped {
+            delta
+        } else {
+            self.in_dead_zone += i64::from(delta);
+            let excess = self.in_dead_zone.abs() - i64::from(filter.dead_zone);
+            if excess <= 0 {
+                return 0;
+            }
+            self.escaped = true;
+            i32::try_from(excess * self.in_dead_zone.signum()).unwrap_or(delta)
+        };
+        if filter.smoothing <= 0.0 {
+            return delta;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let delta = delta as f32;
+        self.smoothed = self.smoothed * filter.smoothing + delta * (1.0 - filter.smoothing);
+        let total = self.smoothed + self.remainder;
+        let rounded = total.round();
+        self.remainder = total - rounded;
+        rounded as i32
+    }
+}
+
+impl From<Swiping> for State {
+    fn from(value: Swiping) -> Self {
+        Self::Swiping(value)
+    }
+}
+
+impl Swiping {
+    /// Grabs the source device, if grabbing it was deferred until it moved
+    ///
+    /// Called after the motion which triggers the grab has been applied, since
+    /// the compositor has already seen it. If the grab fails, the swipe
+    /// carries on without it.
+    pub fn grab_deferred(&mut self, source: Option<&mut impl Source>) {
+        let Some(source) = source.filter(|_| self.grab_pending) else {
+            return;
+        };
+        self.grab_pending = false;
+        match source.grab() {
+            Ok(()) => {
+                self.grabbed = true;
+                self.span.record("grabbed", true);
+                trace!(parent: &self.span, "grabbed source device on motion");
+            }
+            Err(err) => warn!(
+                parent: &self.span,
+                "Failed to grab source device: {:#}",
+                anyhow::Error::new(err)
+            ),
+        }
+    }
+
+    /// Applies relative motion which the source device reported at `time`
+    pub async fn update(
+        &mut self,
+        config: &Config,
+        sink: &mut Sink,
+        time: SystemTime,
+        dx: i32,
+        dy: i32,
+    ) -> Result<()> {
+        if config.cursor_lock && !self.grabbed {
+            sink.cancel_pointer_motion(dx, dy)
+                .with_context(|| "failed to cancel cursor motion")?;
+        }
+        self.rel_events += 1;
+        self.raw_motion.0 += i64::from(dx);
+        self.raw_motion.1 += i64::from(dy);
+        let dx = self.filter_x.apply(config.filter_x, dx);
+        let dy = self.filter_y.apply(config.filter_y, dy);
+        // the first motion sets the direction which some compositors lock onto
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let (dx, dy) = if self.rel_events <= config.anchor_events {
+            (
+                (dx as f32 * config.anchor_boost).round() as i32,
+                (dy as f32 * config.anchor_boost).round() as i32,
+            )
+        } else {
+            (dx, dy)
+        };
+        #[allow(clippy::cast_precision_loss)]
+        if let Some(direction) = self.merge_direction {
+            if let Some(moved) = Direction::dominant(dx as f32, dy as f32) {
+                self.merge_direction = None;
+                if moved != direction {
+                    // going the other way is a separate gesture after all
+                    trace!(parent: &self.span, ?moved, "direction changed, fingers down again");
+                    self.touch.release(config, sink).await?;
+                    self.touch = Touch::down_for_swipe(config, sink, self.touch.fingers)?;
+                }
+            }
+        }
+        if self.in_emit_delay(config) {
+            self.buffered.0 += dx;
+            self.buffered.1 += dy;
+            trace!(parent: &self.span, dx, dy, "buffered motion");
+            return Ok(());
+        }
+        let (buffered_x, buffered_y) = mem::take(&mut self.buffered);
+        let (dx, dy) = (dx + buffered_x, dy + buffered_y);
+        self.touch.update(config, sink, time, dx, dy).await?;
+        self.updates += 1;
+        trace!(parent: &self.span, dx, dy, x = self.touch.x, y = self.touch.y, "motion");
+        if let Some(latency) = sink.latency_mut() {
+            let delay = latency.record(time);
+            debug!(parent: &self.span, latency_us = delay.as_micros(), "motion latency");
+        }
+        Ok(())
+    }
+
+    /// Determines if the fingers are still being held still after touching
+    /// down
+    fn in_emit_delay(&self, config: &Config) -> bool {
+        self.started_at.elapsed() < config.emit_delay
+    }
+
+    /// Counts a failed read from the source device, returning how many have
+    /// failed in a row
+    pub fn record_read_error(&mut self) -> u32 {
+        self.read_errors += 1;
+        self.read_errors
+    }
+
+    /// Resets the failed read count after a successful read from the source
+    /// device
+    pub fn reset_read_errors(&mut self) {
+        self.read_errors = 0;
+    }
+
+    /// Applies a change in the position of the source's analog stick
+    pub fn update_stick(&mut self, axis: AbsoluteAxisType, value: i32) {
+        let Some(stick) = &mut self.stick else {
+            return;
+        };
+        match axis {
+            AbsoluteAxisType::ABS_RX => stick.x.set(value),
+            AbsoluteAxisType::ABS_RY => stick.y.set(value),
+            _ => {}
+        }
+    }
+
+    /// Determines if the analog stick is deflected enough to be moving the
+    /// fingers
+    pub fn stick_moving(&self, config: &Config) -> bool {
+        match (&self.stick, config.stick) {
+            (Some(stick), Some(stick_config)) => stick.velocity(stick_config) != (0.0, 0.0),
+            _ => false,
+        }
+    }
+
+    /// Moves the fingers by one frame's worth of analog stick motion
+    pub fn tick_stick(&mut self, config: &Config, sink: &mut Sink) -> Result<()> {
+        let (Some(stick), Some(stick_config)) = (&self.stick, config.stick) else {
+            return Ok(());
+        };
+        if self.in_emit_delay(config) {
+            return Ok(());
+        }
+        let (vx, vy) = stick.velocity(stick_config);
+        let dt = FRAME_INTERVAL.as_secs_f32();
+        self.touch.move_by(config, sink, vx * dt, vy * dt)?;
+        self.updates += 1;
+        trace!(parent: &self.span, vx, vy, x = self.touch.x, y = self.touch.y, "stick motion");
+        Ok(())
+    }
+
+    /// Ends the swipe by lifting the fingers, which commits the gesture
+    ///
+    /// `source` is the device to ungrab, or `None` if it has been removed.
+    pub async fn stop(
+        self,
+        config: &Config,
+        source: Option<&mut impl Source>,
+        sink: &mut Sink,
+    ) -> Result<Normal> {
+        let mut lift = Lift::Together;
+        if let Some(duration) = config.boomerang {
+            self.touch
+                .boomerang(config, sink, duration)
+                .await
+                .with_context(|| "failed to move fingers back to start")?;
+        } else if let Some(physical) = config.physical {
+            // resting stops the fingers, so there's no momentum to flick with
+            if config.hold_end.is_zero() {
+                return self.flick(config, source, sink, physical).await;
+            }
+        } else if config.merge_window.is_some() {
+            lift = Lift::Hold;
+        }
+
+        // held fingers rest first too, since they may be lifted without
+        // moving again
+        if !config.hold_end.is_zero() {
+            self.touch
+                .rest(config, sink, config.hold_end)
+                .await
+                .with_context(|| "failed to rest fingers before lifting")?;
+        }
+        let outcome = match lift {
+            Lift::Hold => Outcome::Held,
+            _ => Outcome::Stopped,
+        };
+        self.finish(config, source, sink, outcome, lift).await
+    }
+
+    /// Ends the swipe like [`Swiping::stop`], but if the source was moving
+    /// fast when it stopped, carries the fingers on with momentum and lifts
+    /// them one at a time
+    async fn flick(
+        mut self,
+        config: &Config,
+        source: Option<&mut impl Source>,
+        sink: &mut Sink,
+        physical: config::Physical,
+    ) -> Result<Normal> {
+        let (vx, vy) = self.touch.release_velocity();
+        if vx.hypot(vy) * physical.flick_sensitivity < FLICK_SPEED {
+            return self
+                .finish(config, source, sink, Outcome::Stopped, Lift::Together)
+                .await;
+        }
+
+        trace!(parent: &self.span, vx, vy, "flick");
+        self.touch
+            .momentum(config, sink, (vx, vy))
+            .await
+            .with_context(|| "failed to continue flick")?;
+        let forward = vx + vy >= 0.0;
+        self.finish(
+            config,
+            source,
+            sink,
+            Outcome::Flicked,
+            Lift::Staggered { forward },
+        )
+        .await
+    }
+
+    /// Aborts the swipe, so that the compositor doesn't act on the gesture
+    ///
+    /// Unlike [`Swiping::stop`], which lifts the fingers and lets the
+    /// compositor commit to the gesture, this always slides the fingers back
+    /// to where they touched down first. The gesture then ends with no net
+    /// displacement, which compositors that follow the gesture's progress
+    /// (e.g. a workspace swipe) treat as a cancellation and snap back.
+    pub async fn cancel(
+        self,
+        config: &Config,
+        source: Option<&mut impl Source>,
+        sink: &mut Sink,
+    ) -> Result<Normal> {
+        self.touch
+            .boomerang(config, sink, config.cancel_duration)
+            .await
+            .with_context(|| "failed to move fingers back to start")?;
+
+        self.finish(config, source, sink, Outcome::Cancelled, Lift::Together)
+            .await
+    }
+
+    async fn finish(
+        self,
+        config: &Config,
+        source: Option<&mut impl Source>,
+        sink: &mut Sink,
+        outcome: Outcome,
+        lift: Lift,
+    ) -> Result<Normal> {
+        let Self {
+            grabbed,
+            touch,
+            span,
+            started_at,
+            updates,
+            rel_events,
+            raw_motion: (raw_x, raw_y),
+            frames_at_start,
+            ..
+        } = self;
+
+        if let Some(summary) = sink.latency_mut().and_then(|latency| latency.summary()) {
+            debug!(
+                parent: &span,
+                samples = summary.samples,
+                mean_us = summary.mean.as_micros(),
+                p50_us = summary.p50.as_micros(),
+                p99_us = summary.p99.as_micros(),
+                "motion latency"
+            );
+        }
+
+        let (x, y) = (touch.x, touch.y);
+        let (scaled_x, scaled_y) = touch.position(config);
+        let distance_met = touch.log_thresholds(config, &span);
+        // a cancelled swipe ends where it started on purpose
+        if outcome != Outcome::Cancelled {
+            if let Some(auto_tune) = sink.auto_tune_mut() {
+                auto_tune.record(config, touch.fingers, distance_met);
+            }
+            if let Some(commands) = sink.swipe_commands_mut().filter(|_| distance_met) {
+                commands.dispatch(touch.fingers, scaled_x, scaled_y);
+            }
+        }
+        #[cfg(feature = "notifications")]
+        sink.notify(
+            crate::notifications::NotifyOn::Gestures,
+            format!("{}-finger swipe {outcome}", touch.fingers.count()),
+        );
+        let held = match (lift, Direction::dominant(x, y)) {
+            (Lift::Hold, Some(direction)) => Some(Held {
+                touch,
+                direction,
+                stopped_at: Instant::now(),
+            }),
+            (Lift::Together | Lift::Hold, _) => {
+                touch.release(config, sink).await?;
+                None
+            }
+            (Lift::Staggered { forward }, _) => {
+                touch.lift_staggered(config, sink, forward).await?;
+                clear_after_stop(config, sink).await?;
+                None
+            }
+        };
+
+        // only ungrab once the fingers are lifted, so that the virtual trackpad
+        // isn't left with fingers down if this fails
+        if let Some(source) = source.filter(|_| grabbed) {
+            if let Err(err) = source.ungrab() {
+                warn!(
+                    parent: &span,
+                    "Failed to ungrab source device: {:#}",
+                    anyhow::Error::new(err)
+                );
+            }
+        }
+
+        // the span closes when it's dropped at the end of this function
+        span.record("updates", updates);
+        debug!(
+            parent: &span,
+            %outcome,
+            updates,
+            rel_events,
+            frames = sink.frames() - frames_at_start,
+            duration_ms = u64::try_from(started_at.elapsed().as_millis()).unwrap_or(u64::MAX),
+            raw_x,
+            raw_y,
+            x,
+            y,
+            scaled_x,
+            scaled_y,
+            "swipe finished"
+        );
+        Ok(Normal {
+            pending_tap: None,
+            held,
+        })
+    }
+}
+
+/// How the fingers are lifted at the end of a swipe
+#[derive(Debug, Clone, Copy)]
+enum Lift {
+    /// All at once, in a single frame
+    Together,
+    /// One at a time, starting from the finger trailing the motion
+    Staggered { forward: bool },
+    /// Not at all, so that another swipe can continue the gesture, unless
+    /// the fingers never moved
+    Hold,
+}
+
+/// How a swipe ended, as logged and notified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Stopped,
+    Held,
+    Flicked,
+    Cancelled,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Stopped => "stopped",
+            Self::Held => "held",
+            Self::Flicked => "flicked",
+            Self::Cancelled => "cancelled",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Scrolling {
+    pub input_path: PathBuf,
+    /// Whether the source device was grabbed when scrolling started
+    pub grabbed: bool,
+    pub trigger: Key,
+    /// Scroll which hasn't added up to a whole high-resolution unit yet
+    remainder: (f32, f32),
+    /// Whether the source has a high-resolution horizontal and vertical
+    /// scroll wheel
+    hi_res_wheel: (bool, bool),
+}
+
+impl From<Scrolling> for State {
+    fn from(value: Scrolling) -> Self {
+        Self::Scrolling(value)
+    }
+}
+
+impl Normal {
+    #[allow(clippy::unused_self)]
+    pub fn start_scrolling(
+        self,
+        config: &Config,
+        source_path: PathBuf,
+        source: &mut impl Source,
+        sink: &mut Sink,
+        trigger: Key,
+    ) -> Result<Scrolling> {
+        let grabbed = config.should_grab(&source_path, source.name());
+        if grabbed {
+            source
+                .grab()
+                .with_context(|| "failed to grab source device")?;
+        }
+
+        let has_axis = |axis| {
+            source
+                .supported_relative_axes()
+                .is_some_and(|axes| axes.contains(axis))
+        };
+        let hi_res_wheel = (
+            has_axis(RelativeAxisType::REL_HWHEEL_HI_RES),
+            has_axis(RelativeAxisType::REL_WHEEL_HI_RES),
+        );
+
+        sink.reset_scroll();
+        Ok(Scrolling {
+            input_path: source_path,
+            grabbed,
+            trigger,
+            remainder: (0.0, 0.0),
+            hi_res_wheel,
+        })
+    }
+}
+
+impl Scrolling {
+    /// Converts relative motion from the source device into scrolling
+    ///
+    /// Moving the mouse down scrolls down, and moving it right scrolls right.
+    pub fn update(&mut self, config: &Config, sink: &mut Sink, dx: i32, dy: i32) -> Result<()> {
+        #[allow(clippy::cast_precision_loss)]
+        let (horizontal, vertical) = (
+            self.remainder.0 + dx as f32 * config.scroll_factor,
+            self.remainder.1 - dy as f32 * config.scroll_factor,
+        );
+        #[allow(clippy::cast_possible_truncation)]
+        let (whole_h, whole_v) = (horizontal.trunc() as i32, vertical.trunc() as i32);
+        self.remainder = (horizontal.fract(), vertical.fract());
+
+        sink.scroll(whole_h, whole_v)
+    }
+
+    /// Scrolls by the source's own scroll wheel, which the compositor doesn't
+    /// see while the source is grabbed
+    ///
+    /// Mice with a high-resolution wheel send legacy notches as well, so only
+    /// the high-resolution axes are used from them. Notches from other mice
+    /// are scaled to [`HI_RES_PER_NOTCH`] units each.
+    pub fn wheel(&self, sink: &mut Sink, axis: RelativeAxisType, value: i32) -> Result<()> {
+        let notch = |notches: i32| notches.saturating_mul(HI_RES_PER_NOTCH);
+        let (horizontal, vertical) = match axis {
+            RelativeAxisType::REL_HWHEEL_HI_RES => (value, 0),
+            RelativeAxisType::REL_WHEEL_HI_RES => (0, value),
+            RelativeAxisType::REL_HWHEEL if !self.hi_res_wheel.0 => (notch(value), 0),
+            RelativeAxisType::REL_WHEEL if !self.hi_res_wheel.1 => (0, notch(value)),
+            _ => return Ok(()),
+        };
+        sink.scroll(horizontal, vertical)
+    }
+
+    /// Stops scrolling
+    ///
+    /// `source` is the device to ungrab, or `None` if it has been removed. If
+    /// ungrabbing fails, scrolling still stops.
+    pub fn stop(self, source: Option<&mut impl Source>) -> Normal {
+        if let Some(source) = source.filter(|_| self.grabbed) {
+            if let Err(err) = source.ungrab() {
+                warn!(
+                    "Failed to ungrab source device: {:#}",
+                    anyhow::Error::new(err)
+                );
+            }
+        }
+        Normal::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::{self, TestSource, Trackpad};
+
+    const UPDATE_INTERVAL: Duration = Duration::from_millis(8);
+
+    async fn start(
+        config: &Config,
+        source: &mut TestSource,
+        sink: &mut Sink,
+        fingers: Fingers,
+    ) -> Swiping {
+        Normal::new()
+            .start_swiping(
+                config,
+                PathBuf::from("/dev/input/test-mouse"),
+                source,
+                sink,
+                Key::BTN_SIDE,
+                fingers,
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn start_touches_down_at_origin() {
+        let config = harness::config(&[]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let _swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        trackpad.read(&mut sink);
+        assert!(source.grabbed);
+        assert_eq!(trackpad.frames.len(), 1);
+        assert_eq!(trackpad.fingers(), 3);
+        assert_eq!(trackpad.position(), Some(sink.origin()));
+        assert!(trackpad.pressed(Key::BTN_TOUCH));
+        assert!(trackpad.pressed(Key::BTN_TOOL_TRIPLETAP));
+    }
+
+    #[tokio::test]
+    async fn updates_move_every_finger() {
+        let config = harness::config(&[]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let mut swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        trackpad.read(&mut sink);
+        let (origin_x, origin_y) = sink.origin();
+        let mut time = SystemTime::now();
+        let mut last_x = origin_x;
+        for _ in 0..5 {
+            time += UPDATE_INTERVAL;
+            swiping
+                .update(&config, &mut sink, time, 10, 0)
+                .await
+                .unwrap();
+            trackpad.read(&mut sink);
+            let (x, y) = trackpad.position().unwrap();
+            assert!(x > last_x, "fingers should keep moving right");
+            assert_eq!(y, origin_y);
+            assert!(trackpad.contacts.values().all(|contact| contact.x == x));
+            last_x = x;
+        }
+
+        time += UPDATE_INTERVAL;
+        swiping
+            .update(&config, &mut sink, time, 0, -10)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        let (_, y) = trackpad.position().unwrap();
+        assert!(y < origin_y, "fingers should move up");
+        assert_eq!(trackpad.fingers(), 3);
+    }
+
+    #[tokio::test]
+    async fn finger_change_keeps_fingers_down() {
+        let config = harness::config(&[]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let mut swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        swiping
+            .update(&config, &mut sink, SystemTime::now(), 20, 0)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        let tracking_ids = trackpad.tracking_ids();
+        let position = trackpad.position();
+
+        swiping
+            .touch
+            .change_fingers(&config, &mut sink, Fingers::Four)
+            .unwrap();
+        trackpad.read(&mut sink);
+        assert_eq!(trackpad.fingers(), 4);
+        assert_eq!(trackpad.tracking_ids()[..3], tracking_ids);
+        assert_eq!(trackpad.position(), position);
+        assert!(trackpad.pressed(Key::BTN_TOUCH));
+        assert!(trackpad.pressed(Key::BTN_TOOL_QUADTAP));
+        assert!(!trackpad.pressed(Key::BTN_TOOL_TRIPLETAP));
+
+        swiping
+            .touch
+            .change_fingers(&config, &mut sink, Fingers::Two)
+            .unwrap();
+        trackpad.read(&mut sink);
+        assert_eq!(trackpad.fingers(), 2);
+        assert_eq!(trackpad.tracking_ids(), tracking_ids[..2]);
+        assert!(trackpad.pressed(Key::BTN_TOOL_DOUBLETAP));
+        assert!(!trackpad.pressed(Key::BTN_TOOL_QUADTAP));
+    }
+
+    #[tokio::test]
+    async fn stop_lifts_every_finger() {
+        let config = harness::config(&[]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let mut swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        swiping
+            .update(&config, &mut sink, SystemTime::now(), 20, 0)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        let frames = trackpad.frames.len();
+
+        swiping
+            .stop(&config, Some(&mut source), &mut sink)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        assert!(!source.grabbed);
+        assert_eq!(
+            trackpad.frames.len(),
+            frames + 1,
+            "fingers should lift in one frame"
+        );
+        assert_eq!(trackpad.fingers(), 0);
+        assert!(!trackpad.pressed(Key::BTN_TOUCH));
+        assert!(!trackpad.pressed(Key::BTN_TOOL_TRIPLETAP));
+    }
+
+    #[tokio::test]
+    async fn stop_lifts_when_ungrab_fails() {
+        let config = harness::config(&[]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        source.fail_ungrab = true;
+        swiping
+            .stop(&config, Some(&mut source), &mut sink)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        assert_eq!(trackpad.fingers(), 0);
+        assert!(!trackpad.pressed(Key::BTN_TOUCH));
+    }
+
+    #[test]
+    fn scrolling_stops_when_ungrab_fails() {
+        let config = harness::config(&[]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+
+        let scrolling = Normal::new()
+            .start_scrolling(
+                &config,
+                PathBuf::from("/dev/input/test-mouse"),
+                &mut source,
+                &mut sink,
+                Key::BTN_SIDE,
+            )
+            .unwrap();
+        assert!(source.grabbed);
+        source.fail_ungrab = true;
+        let normal = scrolling.stop(Some(&mut source));
+        assert!(normal.held.is_none());
+    }
+
+    #[tokio::test]
+    async fn rest_reports_fingers_with_type_a() {
+        let config = harness::config(&["--mt-protocol", "a", "--hold-end-ms", "24"]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        trackpad.read(&mut sink);
+        let position = trackpad.position();
+        swiping
+            .touch
+            .rest(&config, &mut sink, config.hold_end)
+            .await
+            .unwrap();
+        let rest_frames = sink.take_frames();
+        assert_eq!(rest_frames.len(), 4);
+        for frame in rest_frames {
+            let reported = frame
+                .iter()
+                .filter(|event| {
+                    event.event_type() == EventType::SYNCHRONIZATION
+                        && event.code() == Synchronization::SYN_MT_REPORT.0
+                })
+                .count();
+            assert_eq!(reported, 3, "every finger should be reported");
+            trackpad.read_frame(frame);
+            assert_eq!(trackpad.fingers(), 3);
+            assert_eq!(trackpad.position(), position);
+        }
+    }
+
+    #[tokio::test]
+    async fn held_fingers_rest_first() {
+        let config = harness::config(&["--merge-window-ms", "100", "--hold-end-ms", "16"]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let mut swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        swiping
+            .update(&config, &mut sink, SystemTime::now(), 20, 0)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        let frames = trackpad.frames.len();
+        let normal = swiping
+            .stop(&config, Some(&mut source), &mut sink)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        assert!(normal.held.is_some());
+        assert_eq!(trackpad.frames.len(), frames + 3, "fingers should rest");
+        assert_eq!(trackpad.fingers(), 3);
+    }
+
+    #[tokio::test]
+    async fn held_fingers_release_pressure() {
+        let config = harness::config(&[
+            "--merge-window-ms",
+            "100",
+            "--pressure",
+            "--pressure-ramp-ms",
+            "16",
+        ]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let mut swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        swiping
+            .update(&config, &mut sink, SystemTime::now(), 20, 0)
+            .await
+            .unwrap();
+        let mut normal = swiping
+            .stop(&config, Some(&mut source), &mut sink)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        let frames = trackpad.frames.len();
+        normal.release_held(&config, &mut sink).await.unwrap();
+        trackpad.read(&mut sink);
+        assert_eq!(
+            trackpad.frames.len(),
+            frames + 3,
+            "pressure should ramp down before lifting"
+        );
+        assert_eq!(trackpad.fingers(), 0);
+    }
+
+    #[tokio::test]
+    async fn physical_swipes_never_merge() {
+        let config = harness::config(&[
+            "--merge-window-ms",
+            "100",
+            "--hold-end-ms",
+            "16",
+            "--simulate-physical",
+        ]);
+        let mut sink = Sink::in_memory(&config).unwrap();
+        let mut source = TestSource::default();
+        let mut trackpad = Trackpad::default();
+
+        let swiping = start(&config, &mut source, &mut sink, Fingers::Three).await;
+        let normal = swiping
+            .stop(&config, Some(&mut source), &mut sink)
+            .await
+            .unwrap();
+        trackpad.read(&mut sink);
+        assert!(normal.held.is_none());
+        assert_eq!(trackpad.fingers(), 0);
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{actual} should be close to {expected}"
+        );
+    }
+
+    #[test]
+    fn log_scale_starts_with_slope_of_one() {
+        assert_close(log_scale(0.0, 100.0), 0.0);
+        assert_close(log_scale(0.01, 100.0), 0.01);
+        // the slope doubles every `base` units, so the curve reaches
+        // `base / ln 2` after `base`
+        assert_close(log_scale(100.0, 100.0), 100.0 / LN_2);
+    }
+
+    #[test]
+    fn log_scale_is_odd_and_increasing() {
+        let mut last = f32::NEG_INFINITY;
+        for distance in (-50i16..=50).map(|distance| f32::from(distance) * 10.0) {
+            let scaled = log_scale(distance, 100.0);
+            assert!(scaled > last);
+            assert_close(log_scale(-distance, 100.0), -scaled);
+            last = scaled;
+        }
+    }
+
+    #[test]
+    fn curve_without_curvature_is_straight() {
+        let (x, y) = curve(30.0, -40.0, 0.0);
+        assert_close(x, 30.0);
+        assert_close(y, -40.0);
+    }
+
+    #[test]
+    fn curve_keeps_distance_from_start() {
+        for curvature in [0.001, -0.001, 0.01] {
+            let (x, y) = curve(300.0, 400.0, curvature);
+            assert_close(x.hypot(y), 500.0);
+        }
+    }
+
+    #[test]
+    fn curve_turns_towards_sign_of_curvature() {
+        // with Y increasing downwards, clockwise bends a move right down
+        let (_, y) = curve(500.0, 0.0, 0.001);
+        assert!(y > 0.0);
+        let (_, y) = curve(500.0, 0.0, -0.001);
+        assert!(y < 0.0);
+        // the chord turns by half of the arc's total turn
+        let (x, y) = curve(500.0, 0.0, 0.001);
+        assert_close(y.atan2(x), 0.25);
+    }
+
+    fn filter(dead_zone: u32, smoothing: f32) -> config::AxisFilter {
+        config::AxisFilter {
+            dead_zone,
+            smoothing,
+        }
+    }
+
+    #[test]
+    fn motion_filter_passes_motion_through_by_default() {
+        let mut motion = MotionFilter::default();
+        for delta in [3, -7, 0, 120] {
+            assert_eq!(motion.apply(filter(0, 0.0), delta), delta);
+        }
+    }
+
+    #[test]
+    fn motion_filter_applies_only_motion_past_dead_zone() {
+        let mut motion = MotionFilter::default();
+        let filter = filter(10, 0.0);
+        assert_eq!(motion.apply(filter, 6), 0);
+        assert_eq!(motion.apply(filter, -4), 0);
+        assert_eq!(motion.apply(filter, 7), 0);
+        assert_eq!(motion.apply(filter, -13), 0);
+        // 17 units left of where the swipe started, 7 past the dead zone
+        assert_eq!(motion.apply(filter, -13), -7);
+        // once out, motion is applied as it is, even going back
+        assert_eq!(motion.apply(filter, 2), 2);
+    }
+
+    #[test]
+    fn motion_filter_smooths_without_losing_motion() {
+        let mut motion = MotionFilter::default();
+        let filter = filter(0, 0.5);
+        let applied = (0..20)
+            .map(|_| motion.apply(filter, 10))
+            .collect::<Vec<_>>();
+        assert_eq!(applied[0], 5, "motion should ramp up");
+        assert_eq!(applied[19], 10);
+        // the motion lags behind by 10 units which are still being smoothed
+        // out, but none of it is rounded away
+        let total = applied.iter().sum::<i32>();
+        assert!((189..=191).contains(&total), "total was {total}");
     }
 }
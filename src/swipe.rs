@@ -1,259 +1,269 @@
-use std::{collections::hash_map::Entry, path::PathBuf, time::Duration};
+use std::{
+    collections::hash_map::Entry,
+    iter,
+    path::{Path, PathBuf},
+    sync::PoisonError,
+    time::{Duration, Instant},
+};
 
 use ahash::AHashMap;
 use anyhow::{anyhow, Context, Result};
 use evdev::{
-    uinput::{VirtualDevice, VirtualDeviceBuilder},
-    AbsInfo, AbsoluteAxisType, AttributeSet, Device, EventStream, InputEvent, InputEventKind, Key,
-    PropType, RelativeAxisType, UinputAbsSetup,
+    AbsoluteAxisType, Device, EventStream, InputEvent, InputEventKind, Key, RelativeAxisType,
 };
 use futures::{never::Never, stream::FuturesUnordered, StreamExt};
 use log::{debug, info, trace, warn};
-use tokio::sync::mpsc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+    time::{Interval, MissedTickBehavior},
+};
 
 use crate::{
-    states::{Fingers, State},
+    backlog,
+    blacklist::Blacklist,
+    config::{Config, Warmup},
+    control::{Command, Controls, Request, Status},
+    dropped::DroppedEvents,
+    dump::Snapshot,
+    gesture_loop,
+    states::{
+        Fingers, MotionSource, Normal, PendingTap, Scrolling, State, Swiping, Touch, FRAME_INTERVAL,
+    },
+    trackpad::{self, Sink},
     NotifyEvent,
 };
 
-#[allow(clippy::too_many_arguments)]
+#[allow(clippy::too_many_lines)]
 pub async fn simulate(
     device_events: &mut mpsc::UnboundedReceiver<NotifyEvent>,
-    input_allow: &[PathBuf],
-    input_deny: &[PathBuf],
-    swipe_2: Option<Key>,
-    swipe_3: Option<Key>,
-    swipe_4: Option<Key>,
-    swipe_5: Option<Key>,
-    resolution: u16,
-    x_mult: f32,
-    y_mult: f32,
-    grab: bool,
+    screen_locks: &mut mpsc::UnboundedReceiver<bool>,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+    config: &Config,
 ) -> Result<Never> {
-    info!("Creating virtual trackpad");
-    let (mut sink, sink_dev_nodes) = create_trackpad(resolution).await?;
+    let (mut sink, sink_dev_nodes) = start_trackpad(config).await?;
     let mut state = State::default();
     let mut devices = AHashMap::<PathBuf, EventStream>::new();
+    let mut blacklist = Blacklist::default();
+    let mut dropped = DroppedEvents::default();
+    let mut stick_interval = stick_interval();
+    // while the screen is locked, or a controller has paused us, no new
+    // gestures are started
+    let mut locked = false;
+    let mut controls = Controls::default();
+    let mut dump_signals =
+        signal(SignalKind::user_defined1()).with_context(|| "failed to listen for SIGUSR1")?;
 
     loop {
+        let held_until = state.held_until(config);
+        let keepalive_at = state.keepalive_at(config, &sink);
         let mut input_events = devices
             .iter_mut()
             .map(|(path, events)| async move {
                 let res = events.next_event().await;
-                (path, events.device_mut(), res)
+                (path.clone(), res)
             })
             .collect::<FuturesUnordered<_>>();
 
         state = tokio::select! {
             Some(event) = device_events.recv() => {
                 drop(input_events);
-                on_device_event(
-                    event,
-                    &mut sink,
-                    &sink_dev_nodes,
-                    input_allow,
-                    input_deny,
-                    &mut devices,
-                    state
-                )?
+                let events = match config.device_debounce {
+                    Some(window) => coalesce_device_events(device_events, event, window).await,
+                    None => vec![event],
+                };
+                let mut state = state;
+                for event in events {
+                    state = on_device_event(
+                        config,
+                        event,
+                        &mut sink,
+                        &sink_dev_nodes,
+                        &mut devices,
+                        &mut blacklist,
+                        state,
+                    )
+                    .await?;
+                }
+                state
             }
-            Some((source_path, source, input)) = input_events.next() => {
-                on_input_event(
-                    swipe_2,
-                    swipe_3,
-                    swipe_4,
-                    swipe_5,
-                    x_mult,
-                    y_mult,
-                    grab,
-                    source,
-                    source_path,
+            Some(now_locked) = screen_locks.recv() => {
+                drop(input_events);
+                locked = now_locked;
+                on_screen_lock(config, &mut devices, &mut sink, locked, state).await?
+            }
+            Some(Command { request, reply }) = commands.recv() => {
+                drop(input_events);
+                let (state, result) =
+                    on_request(config, &mut devices, &mut sink, request, state, &mut controls, locked)
+                        .await?;
+                let _ = reply.send(result.map(|()| Status::of(config, &state, &controls, locked)));
+                state
+            }
+            Some((source_path, input)) = input_events.next() => {
+                drop(input_events);
+                if (controls.paused || locked) && matches!(state, State::Normal(_)) {
+                    continue;
+                }
+                on_input_backlog(
+                    config,
+                    &controls,
+                    &mut devices,
+                    &mut dropped,
+                    &source_path,
                     &mut sink,
                     input,
                     state,
-                )?
+                ).await?
+            }
+            Some(()) = dump_signals.recv() => {
+                drop(input_events);
+                let snapshot = Snapshot {
+                    config,
+                    state: &state,
+                    controls: &controls,
+                    locked,
+                    devices: &devices,
+                    sink: &sink,
+                    sink_dev_nodes: &sink_dev_nodes,
+                };
+                info!("{snapshot}");
+                state
+            }
+            () = sleep_until(held_until), if held_until.is_some() => {
+                drop(input_events);
+                on_merge_window_end(config, &mut sink, state).await?
+            }
+            () = sleep_until(keepalive_at), if keepalive_at.is_some() => {
+                drop(input_events);
+                sink.keepalive(config.clock.now()).with_context(|| "failed to emit keepalive frame")?;
+                state
+            }
+            _ = stick_interval.tick(), if matches!(&state, State::Swiping(swiping) if swiping.stick_moving(config)) => {
+                drop(input_events);
+                on_stick_tick(config, &mut sink, state)?
             }
         };
     }
 }
 
-async fn create_trackpad(resolution: u16) -> Result<(VirtualDevice, Vec<PathBuf>)> {
-    /*
-    # Supported events:
-    #   Event type 0 (EV_SYN)
-    #     Event code 0 (SYN_REPORT)
-    #     Event code 1 (SYN_CONFIG)
-    #     Event code 2 (SYN_MT_REPORT)
-    #     Event code 3 (SYN_DROPPED)
-    #     Event code 4 ((null))
-    #     Event code 5 ((null))
-    #     Event code 6 ((null))
-    #     Event code 7 ((null))
-    #     Event code 8 ((null))
-    #     Event code 9 ((null))
-    #     Event code 10 ((null))
-    #     Event code 11 ((null))
-    #     Event code 12 ((null))
-    #     Event code 13 ((null))
-    #     Event code 14 ((null))
-    #     Event code 15 (SYN_MAX)
-    #   Event type 1 (EV_KEY)
-    #     Event code 272 (BTN_LEFT)
-    #     Event code 273 (BTN_RIGHT)
-    #     Event code 325 (BTN_TOOL_FINGER)
-    #     Event code 328 (BTN_TOOL_QUINTTAP)
-    #     Event code 330 (BTN_TOUCH)
-    #     Event code 333 (BTN_TOOL_DOUBLETAP)
-    #     Event code 334 (BTN_TOOL_TRIPLETAP)
-    #     Event code 335 (BTN_TOOL_QUADTAP)
-    #   Event type 3 (EV_ABS)
-    #     Event code 0 (ABS_X)
-    #       Value      848
-    #       Min          0
-    #       Max       1337
-    #       Fuzz         0
-    #       Flat         0
-    #       Resolution  12
-    #     Event code 1 (ABS_Y)
-    #       Value      467
-    #       Min          0
-    #       Max        876
-    #       Fuzz         0
-    #       Flat         0
-    #       Resolution  12
-    #     Event code 47 (ABS_MT_SLOT)
-    #       Value        0
-    #       Min          0
-    #       Max          4
-    #       Fuzz         0
-    #       Flat         0
-    #       Resolution   0
-    #     Event code 53 (ABS_MT_POSITION_X)
-    #       Value        0
-    #       Min          0
-    #       Max       1337
-    #       Fuzz         0
-    #       Flat         0
-    #       Resolution  12
-    #     Event code 54 (ABS_MT_POSITION_Y)
-    #       Value        0
-    #       Min          0
-    #       Max        876
-    #       Fuzz         0
-    #       Flat         0
-    #       Resolution  12
-    #     Event code 55 (ABS_MT_TOOL_TYPE)
-    #       Value        0
-    #       Min          0
-    #       Max          2
-    #       Fuzz         0
-    #       Flat         0
-    #       Resolution   0
-    #     Event code 57 (ABS_MT_TRACKING_ID)
-    #       Value        0
-    #       Min          0
-    #       Max      65535
-    #       Fuzz         0
-    #       Flat         0
-    #       Resolution   0
-    #   Event type 4 (EV_MSC)
-    #     Event code 5 (MSC_TIMESTAMP)
-    # Properties:
-    #   Property  type 0 (INPUT_PROP_POINTER)
-    #   Property  type 2 (INPUT_PROP_BUTTONPAD)
-    */
-
-    // https://www.kernel.org/doc/html/v4.12/input/event-codes.html
-    // https://www.kernel.org/doc/html/v4.12/input/multi-touch-protocol.html
-
-    const VIRTUAL_DEVICE_NAME: &str = "fukomaster virtual trackpad";
-
-    fn abs(min: i32, max: i32, resolution: i32) -> AbsInfo {
-        AbsInfo::new(0, min, max, 0, 0, resolution)
-    }
+/// Creates the timer which moves the fingers while an analog stick is held
+/// deflected, since the stick only sends events when its position changes
+fn stick_interval() -> Interval {
+    let mut interval = tokio::time::interval(FRAME_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
 
-    fn abs_with_max(max: i32) -> AbsInfo {
-        abs(0, max, 0)
+async fn sleep_until(deadline: Option<Instant>) {
+    if let Some(deadline) = deadline {
+        tokio::time::sleep_until(deadline.into()).await;
     }
+}
 
-    let resolution = i32::from(resolution);
-    let mut dev = VirtualDeviceBuilder::new()?
-        .name(VIRTUAL_DEVICE_NAME)
-        .with_properties(&AttributeSet::from_iter([PropType::POINTER]))?
-        .with_keys(&AttributeSet::from_iter([
-            Key::BTN_TOOL_FINGER,
-            Key::BTN_TOUCH,
-            Key::BTN_TOOL_DOUBLETAP,
-            Key::BTN_TOOL_TRIPLETAP,
-            Key::BTN_TOOL_QUADTAP,
-            Key::BTN_TOOL_QUINTTAP,
-        ]))?
-        .with_absolute_axis(&UinputAbsSetup::new(
-            AbsoluteAxisType::ABS_MT_SLOT,
-            abs_with_max(4), // max 5 touches
-        ))?
-        .with_absolute_axis(&UinputAbsSetup::new(
-            AbsoluteAxisType::ABS_MT_TRACKING_ID,
-            abs_with_max(i32::MAX),
-        ))?
-        .with_absolute_axis(&UinputAbsSetup::new(
-            AbsoluteAxisType::ABS_MT_POSITION_X,
-            abs(i32::MIN, i32::MAX, resolution),
-        ))?
-        .with_absolute_axis(&UinputAbsSetup::new(
-            AbsoluteAxisType::ABS_MT_POSITION_Y,
-            abs(i32::MIN, i32::MAX, resolution),
-        ))?
-        .build()?;
-
-    // we need a slight delay after creating the input device
-    // so that other processes (i.e. compositor) can recognize it
-    tokio::time::sleep(Duration::from_millis(200)).await;
-
-    info!("Created virtual trackpad");
-
-    let dev_nodes = collect_dev_nodes(&mut dev)
+/// Lifts the fingers held after a swipe, now that no swipe has continued them
+async fn on_merge_window_end(config: &Config, sink: &mut Sink, state: State) -> Result<State> {
+    let State::Normal(mut normal) = state else {
+        return Ok(state);
+    };
+    normal
+        .release_held(config, sink)
         .await
-        .with_context(|| "failed to enumerate dev nodes of device")?;
-    let sys_path = dev
-        .get_syspath()
-        .with_context(|| "failed to get sys path of device")?;
-    info!("  sys path = {sys_path:?}");
-    for dev_node in &dev_nodes {
-        info!("  dev node = {dev_node:?}");
+        .with_context(|| "failed to lift held fingers")?;
+    Ok(normal.into())
+}
+
+/// Creates the virtual trackpad, warming it up if `--warmup` is used
+async fn start_trackpad(config: &Config) -> Result<(Sink, Vec<PathBuf>)> {
+    info!("Creating virtual trackpad");
+    let (mut sink, sink_dev_nodes) = trackpad::create_trackpad(config).await?;
+    if let Some(warmup) = config.warmup {
+        warm_up(config, &mut sink, warmup)
+            .await
+            .with_context(|| "failed to perform warmup gesture")?;
     }
+    Ok((sink, sink_dev_nodes))
+}
 
-    Ok((dev, dev_nodes))
+async fn warm_up(config: &Config, sink: &mut Sink, warmup: Warmup) -> Result<()> {
+    let touch = Touch::down(config, sink, warmup.fingers)?;
+    tokio::time::sleep(FRAME_INTERVAL).await;
+    touch.emit_position(config, sink, warmup.distance, 0)?;
+    tokio::time::sleep(FRAME_INTERVAL).await;
+    touch.lift(config, sink)?;
+    info!(
+        "Performed warmup gesture with {} fingers",
+        warmup.fingers.count()
+    );
+    Ok(())
 }
 
-async fn collect_dev_nodes(device: &mut VirtualDevice) -> Result<Vec<PathBuf>> {
-    let mut iter = device.enumerate_dev_nodes().await?;
-    let mut nodes = Vec::new();
-    while let Ok(Some(node)) = iter.next_entry().await {
-        nodes.push(node);
+/// Waits for `window` after `first`, then merges it with all device events
+/// received in the meantime, so that each path settles to its final state
+///
+/// A path which was removed at any point is still removed, then created again
+/// if its last event was a creation, so that a device which was reset is
+/// reopened rather than left with a stale file.
+async fn coalesce_device_events(
+    device_events: &mut mpsc::UnboundedReceiver<NotifyEvent>,
+    first: NotifyEvent,
+    window: Duration,
+) -> Vec<NotifyEvent> {
+    tokio::time::sleep(window).await;
+
+    // (path, removed at any point, created by the last event)
+    let mut paths = Vec::<(PathBuf, bool, bool)>::new();
+    let mut received = 0;
+    for event in iter::once(first).chain(iter::from_fn(|| device_events.try_recv().ok())) {
+        received += 1;
+        let (path, created) = match event {
+            NotifyEvent::Created(path) => (path, true),
+            NotifyEvent::Removed(path) => (path, false),
+        };
+        if let Some((_, removed, last_created)) = paths.iter_mut().find(|(p, ..)| *p == path) {
+            *removed |= !created;
+            *last_created = created;
+        } else {
+            paths.push((path, !created, created));
+        }
+    }
+
+    let events = paths
+        .into_iter()
+        .flat_map(|(path, removed, created)| {
+            [
+                removed.then(|| NotifyEvent::Removed(path.clone())),
+                created.then_some(NotifyEvent::Created(path)),
+            ]
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    if events.len() < received {
+        debug!("Coalesced {received} device events into {}", events.len());
     }
-    Ok(nodes)
+    events
 }
 
-fn on_device_event(
+async fn on_device_event(
+    config: &Config,
     event: NotifyEvent,
-    sink: &mut VirtualDevice,
+    sink: &mut Sink,
     sink_dev_nodes: &[PathBuf],
-    input_allow: &[PathBuf],
-    input_deny: &[PathBuf],
     devices: &mut AHashMap<PathBuf, EventStream>,
+    blacklist: &mut Blacklist,
     state: State,
 ) -> Result<State> {
     match event {
         NotifyEvent::Created(source_path) => {
-            match add_device(
-                source_path.clone(),
-                sink_dev_nodes,
-                input_allow,
-                input_deny,
-                devices,
-            ) {
+            if let Some(remaining) = blacklist.remaining(&source_path) {
+                debug!(
+                    "Will not track {source_path:?} for another {remaining:?}, it keeps failing"
+                );
+                return Ok(state);
+            }
+            match add_device(source_path.clone(), sink_dev_nodes, config, devices) {
                 Ok(Ok(source)) => {
+                    blacklist.record_success(&source_path);
                     if let Some(name) = source.name() {
                         info!("Tracking {name:?} ({source_path:?})");
                     } else {
@@ -265,12 +275,13 @@ fn on_device_event(
                 }
                 Err(err) => {
                     warn!("Failed to track device {source_path:?}: {err:#}");
+                    blacklist.record_failure(&source_path);
                 }
             }
             Ok(state)
         }
         NotifyEvent::Removed(path) => Ok({
-            let Some(mut events) = devices.remove(&path) else {
+            let Some(events) = devices.remove(&path) else {
                 return Ok(state);
             };
 
@@ -283,23 +294,39 @@ fn on_device_event(
             match state {
                 State::Swiping(swiping) if swiping.input_path == path => {
                     info!("Stopped swiping because the swipe device was removed");
+                    #[cfg(feature = "notifications")]
+                    sink.notify(
+                        crate::notifications::NotifyOn::Errors,
+                        "Swipe stopped because its device was disconnected".to_owned(),
+                    );
                     swiping
                         // we never want to ungrab here, since the device is already removed
-                        .stop(events.device_mut(), sink, false)
+                        .stop(config, None::<&mut Device>, sink)
+                        .await
                         .with_context(|| "failed to stop swiping")?
                         .into()
                 }
+                State::Scrolling(scrolling) if scrolling.input_path == path => {
+                    info!("Stopped scrolling because the scroll device was removed");
+                    #[cfg(feature = "notifications")]
+                    sink.notify(
+                        crate::notifications::NotifyOn::Errors,
+                        "Scroll stopped because its device was disconnected".to_owned(),
+                    );
+                    scrolling.stop(None::<&mut Device>).into()
+                }
                 state => state,
             }
         }),
     }
 }
 
-fn add_device<'a>(
+/// Opens and starts tracking the device at `source_path`, if it passes the
+/// path and capability filters in `config`
+pub fn add_device<'a>(
     source_path: PathBuf,
     sink_dev_nodes: &[PathBuf],
-    input_allow: &[PathBuf],
-    input_deny: &[PathBuf],
+    config: &Config,
     devices: &'a mut AHashMap<PathBuf, EventStream>,
 ) -> Result<Result<&'a mut Device>> {
     const DEVICE_PREFIX: &str = "event";
@@ -308,11 +335,11 @@ fn add_device<'a>(
         return Ok(Err(anyhow!("this is our own virtual device")));
     }
 
-    if input_deny.contains(&source_path) {
+    if config.input_deny.contains(&source_path) {
         return Ok(Err(anyhow!("device is in the deny list")));
     }
 
-    if !input_allow.is_empty() && !input_allow.contains(&source_path) {
+    if !config.input_allow.is_empty() && !config.input_allow.contains(&source_path) {
         return Ok(Err(anyhow!("device is not in the allow list")));
     }
 
@@ -328,6 +355,21 @@ fn add_device<'a>(
     }
 
     let device = Device::open(&source_path).with_context(|| "failed to open device file")?;
+    // in case our own dev nodes couldn't be found
+    if trackpad::is_own_device(&device) {
+        return Ok(Err(anyhow!("this is one of our own virtual devices")));
+    }
+    if let Some(caps) = config.deny_caps.iter().find(|caps| caps.matches(&device)) {
+        return Ok(Err(anyhow!(
+            "device looks like a {caps:?}, which is denied"
+        )));
+    }
+    if !config.allow_caps.is_empty() && !config.allow_caps.iter().any(|caps| caps.matches(&device))
+    {
+        return Ok(Err(anyhow!(
+            "device does not look like any of the allowed kinds"
+        )));
+    }
     let Entry::Vacant(entry) = devices.entry(source_path) else {
         return Err(anyhow!("device with this file is already being tracked"));
     };
@@ -338,18 +380,40 @@ fn add_device<'a>(
     Ok(Ok(event_stream.device_mut()))
 }
 
+/// Handles an event read from `source_path`, along with the backlog of events
+/// queued up behind it, shaped by `--input-queue`
 #[allow(clippy::too_many_arguments)]
-fn on_input_event(
-    swipe_2: Option<Key>,
-    swipe_3: Option<Key>,
-    swipe_4: Option<Key>,
-    swipe_5: Option<Key>,
-    x_mult: f32,
-    y_mult: f32,
-    grab: bool,
-    source: &mut Device,
-    source_path: &PathBuf,
-    sink: &mut VirtualDevice,
+async fn on_input_backlog(
+    config: &Config,
+    controls: &Controls,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    dropped: &mut DroppedEvents,
+    source_path: &Path,
+    sink: &mut Sink,
+    input: Result<InputEvent, std::io::Error>,
+    mut state: State,
+) -> Result<State> {
+    let inputs = match (input, devices.get_mut(source_path)) {
+        (Ok(input), Some(events)) => {
+            let backlog =
+                backlog::read(config.input_queue, config.input_queue_depth, events, input);
+            dropped.check(source_path, events.device().cached_state().timestamp());
+            backlog.into_iter().map(Ok).collect()
+        }
+        (input, _) => vec![input],
+    };
+    for input in inputs {
+        state = on_input_event(config, controls, devices, source_path, sink, input, state).await?;
+    }
+    Ok(state)
+}
+
+async fn on_input_event(
+    config: &Config,
+    controls: &Controls,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
     input: Result<InputEvent, std::io::Error>,
     state: State,
 ) -> Result<State> {
@@ -359,6 +423,11 @@ fn on_input_event(
         return Ok(state);
     }
 
+    // `SYN_DROPPED` never gets here: evdev's event stream discards the frames
+    // around it, then reports the changes to the source's key and axis state
+    // as events, so a trigger released during the drop still ends the swipe.
+    // Relative motion in the dropped frames is lost, since it has no state,
+    // which `DroppedEvents` warns about when the backlog is read.
     let input = match input {
         Ok(input) => input,
         Err(err) => {
@@ -366,61 +435,689 @@ fn on_input_event(
                 "Failed to read events from {source_path:?}: {:#}",
                 anyhow::Error::new(err)
             );
-            return Ok(state);
+            return on_read_error(config, devices, source_path, sink, state).await;
         }
     };
 
+    let state = match state {
+        State::Swiping(mut swiping) if swiping.input_path == source_path => {
+            swiping.reset_read_errors();
+            swiping.into()
+        }
+        state => state,
+    };
+
     Ok(match state {
         State::Normal(normal) => {
-            struct StartInfo {
-                trigger: Key,
-                fingers: Fingers,
-            }
+            on_normal_input(config, controls, devices, source_path, sink, &input, normal).await?
+        }
+        State::Scrolling(scrolling) => {
+            on_scrolling_input(config, devices, source_path, sink, &input, scrolling)?
+        }
+        State::Swiping(swiping) => {
+            on_swiping_input(config, devices, source_path, sink, &input, swiping).await?
+        }
+    })
+}
 
-            let mut start_info = None;
-            let mut test_start_swipe = |trigger: Option<Key>, fingers| {
-                let Some(trigger) = trigger else { return };
-                if input.kind() == InputEventKind::Key(trigger) && input.value() == 1 {
-                    start_info = Some(StartInfo { trigger, fingers });
-                }
-            };
+async fn on_normal_input(
+    config: &Config,
+    controls: &Controls,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
+    input: &InputEvent,
+    mut normal: Normal,
+) -> Result<State> {
+    if let Some(pending) = normal.pending_tap.take() {
+        return on_pending_tap_input(config, devices, source_path, sink, input, normal, pending)
+            .await;
+    }
+
+    let mut start_info = None;
+    let mut test_start_swipe = |trigger: Option<Key>, fingers, end_key| {
+        let Some(trigger) = trigger else { return };
+        if controls.disabled_fingers.contains(&fingers) {
+            return;
+        }
+        if input.kind() == InputEventKind::Key(trigger) && input.value() == 1 {
+            start_info = Some(StartInfo {
+                trigger,
+                fingers,
+                end_key,
+            });
+        }
+    };
+
+    for (fingers, trigger) in config.triggers().iter() {
+        test_start_swipe(trigger, fingers, None);
+    }
+    if let Some(drag) = config.drag {
+        test_start_swipe(Some(drag.key), drag.fingers, Some(drag.end_key));
+    }
+    if let Some(drag_lock) = config.drag_lock {
+        test_start_swipe(Some(drag_lock.key), Fingers::One, None);
+    }
+
+    // with a separate trigger device, only its keys can start a swipe
+    if config
+        .trigger_device
+        .as_ref()
+        .is_some_and(|trigger_device| trigger_device != source_path)
+    {
+        start_info = None;
+    }
+
+    Ok(if let Some(start_info) = start_info {
+        // checked on every attempt, so that an external process can
+        // toggle gestures on and off at any time
+        if let Some(enable_file) = config.enable_file.as_ref().filter(|path| !path.exists()) {
+            debug!("Not starting swipe because {enable_file:?} does not exist");
+            return Ok(normal.into());
+        }
+        #[cfg(feature = "focus")]
+        if !crate::focus::allows(&config.focus_apps).await {
+            return Ok(normal.into());
+        }
 
-            test_start_swipe(swipe_2, Fingers::Two);
-            test_start_swipe(swipe_3, Fingers::Three);
-            test_start_swipe(swipe_4, Fingers::Four);
-            test_start_swipe(swipe_5, Fingers::Five);
+        let drag_lock = config
+            .drag_lock
+            .filter(|drag_lock| drag_lock.key == start_info.trigger);
 
-            if let Some(StartInfo { trigger, fingers }) = start_info {
-                trace!("Started swipe on {source_path:?} with {fingers:?} fingers");
+        // drags are always held, so they can't be taps
+        if config.tap_window.is_some() && start_info.end_key.is_none() && drag_lock.is_none() {
+            trace!("Waiting to see if {:?} is a tap", start_info.trigger);
+            normal.pending_tap = Some(PendingTap {
+                trigger: start_info.trigger,
+                trigger_path: source_path.to_owned(),
+                fingers: start_info.fingers,
+                pressed_at: Instant::now(),
+            });
+            return Ok(normal.into());
+        }
+
+        // the tap and the touch which follows it make a tap-and-drag
+        if let Some(drag_lock) = drag_lock {
+            trace!("Tapping before drag on {source_path:?}");
+            normal
+                .tap(config, sink, Fingers::One)
+                .await
+                .with_context(|| "failed to tap before dragging")?;
+            tokio::time::sleep(drag_lock.gap).await;
+        }
+
+        start_swipe(config, devices, source_path, sink, normal, &start_info).await?
+    } else if let Some(trigger) = config
+        .scroll_key
+        .filter(|&key| input.kind() == InputEventKind::Key(key) && input.value() == 1)
+    {
+        start_scroll(config, devices, source_path, sink, normal, trigger).await?
+    } else {
+        normal.into()
+    })
+}
+
+/// Starts turning motion from the device at `source_path` into scrolling,
+/// until `trigger` is released
+async fn start_scroll(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
+    mut normal: Normal,
+    trigger: Key,
+) -> Result<State> {
+    let Some(source) = devices.get_mut(source_path).map(EventStream::device_mut) else {
+        return Ok(normal.into());
+    };
+    trace!("Started scrolling on {source_path:?}");
+    normal
+        .release_held(config, sink)
+        .await
+        .with_context(|| "failed to lift held fingers")?;
+    Ok(normal
+        .start_scrolling(config, source_path.to_owned(), source, sink, trigger)
+        .with_context(|| "failed to start scrolling")?
+        .into())
+}
+
+struct StartInfo {
+    trigger: Key,
+    fingers: Fingers,
+    end_key: Option<Key>,
+}
+
+// only waits for the compositor when it can be asked for the focused monitor
+#[cfg_attr(not(feature = "focus"), allow(clippy::unused_async))]
+async fn start_swipe(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
+    normal: Normal,
+    start_info: &StartInfo,
+) -> Result<State> {
+    if config.scroll_two_finger
+        && start_info.fingers == Fingers::Two
+        && start_info.end_key.is_none()
+    {
+        return start_scroll(
+            config,
+            devices,
+            source_path,
+            sink,
+            normal,
+            start_info.trigger,
+        )
+        .await;
+    }
+
+    // motion comes from the motion device if there is one, and
+    // that's the device which gets grabbed
+    let motion_path = config.motion_device.as_deref().unwrap_or(source_path);
+    let Some(motion) = devices.get_mut(motion_path).map(EventStream::device_mut) else {
+        warn!("Not starting swipe because motion device {motion_path:?} is not being tracked");
+        return Ok(normal.into());
+    };
+
+    let fingers = start_info.fingers;
+    trace!("Started swipe on {motion_path:?} with {fingers:?} fingers");
+    let mut swiping = normal
+        .start_swiping(
+            config,
+            motion_path.to_owned(),
+            motion,
+            sink,
+            start_info.trigger,
+            fingers,
+        )
+        .await
+        .with_context(|| "failed to start swiping")?;
+    #[cfg(feature = "focus")]
+    {
+        swiping.touch.monitor = crate::focus::swipe_monitor(config).await;
+    }
+    source_path.clone_into(&mut swiping.trigger_path);
+    swiping.end_key = start_info.end_key;
+    #[cfg(feature = "notifications")]
+    sink.notify(
+        crate::notifications::NotifyOn::Gestures,
+        format!("{}-finger swipe started", fingers.count()),
+    );
+    Ok(swiping.into())
+}
+
+/// Decides whether a held trigger is a tap or a swipe
+///
+/// Releasing the trigger within the tap window, without moving, taps. Moving
+/// starts a swipe as normal, with this motion as its first. Holding the
+/// trigger still for longer than the window and releasing it does nothing.
+async fn on_pending_tap_input(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
+    input: &InputEvent,
+    mut normal: Normal,
+    pending: PendingTap,
+) -> Result<State> {
+    let motion_path = config.motion_device.as_deref().unwrap_or(source_path);
+    match input.kind() {
+        InputEventKind::Key(key)
+            if key == pending.trigger
+                && source_path == pending.trigger_path
+                && input.value() == 0 =>
+        {
+            let tap_window = config.tap_window.unwrap_or_default();
+            if pending.pressed_at.elapsed() <= tap_window {
+                trace!("Tapped with {:?} fingers", pending.fingers);
                 normal
-                    .start_swiping(source_path.clone(), source, sink, trigger, fingers, grab)
-                    .with_context(|| "failed to start swiping")?
-                    .into()
-            } else {
-                normal.into()
+                    .tap(config, sink, pending.fingers)
+                    .await
+                    .with_context(|| "failed to tap")?;
             }
+            Ok(normal.into())
+        }
+        InputEventKind::RelAxis(axis @ (RelativeAxisType::REL_X | RelativeAxisType::REL_Y))
+            if source_path == motion_path =>
+        {
+            let start_info = StartInfo {
+                trigger: pending.trigger,
+                fingers: pending.fingers,
+                end_key: None,
+            };
+            let state =
+                start_swipe(config, devices, source_path, sink, normal, &start_info).await?;
+            let State::Swiping(mut swiping) = state else {
+                return Ok(state);
+            };
+            // this event came from the motion device, which may not be the
+            // one that the trigger was pressed on
+            swiping.trigger_path = pending.trigger_path;
+            let (dx, dy) = if axis == RelativeAxisType::REL_X {
+                (input.value(), 0)
+            } else {
+                (0, input.value())
+            };
+            swiping
+                .update(config, sink, input.timestamp(), dx, dy)
+                .await
+                .with_context(|| "failed to update swipe position")?;
+            swiping.grab_deferred(devices.get_mut(source_path).map(EventStream::device_mut));
+            Ok(swiping.into())
+        }
+        _ => {
+            normal.pending_tap = Some(pending);
+            Ok(normal.into())
+        }
+    }
+}
+
+/// Stops any swipe or scroll in progress when the screen is locked, so that
+/// its source device is ungrabbed
+async fn on_screen_lock(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    sink: &mut Sink,
+    locked: bool,
+    state: State,
+) -> Result<State> {
+    if !locked {
+        info!("Screen unlocked, resuming gestures");
+        return Ok(state);
+    }
+
+    info!("Screen locked, pausing gestures");
+    Ok(match state {
+        State::Normal(normal) => normal.into(),
+        State::Swiping(swiping) => {
+            let motion = devices
+                .get_mut(&swiping.input_path)
+                .map(EventStream::device_mut);
+            swiping
+                .stop(config, motion, sink)
+                .await
+                .with_context(|| "failed to stop swiping")?
+                .into()
+        }
+        State::Scrolling(scrolling) => {
+            let source = devices
+                .get_mut(&scrolling.input_path)
+                .map(EventStream::device_mut);
+            scrolling.stop(source).into()
         }
-        State::Swiping(mut swiping) => match input.kind() {
-            InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
-                swiping
-                    .update(sink, input.value(), 0, x_mult, y_mult)
-                    .with_context(|| "failed to update swipe position")?;
-                swiping.into()
+    })
+}
+
+/// Handles a request from an external controller, returning whether it
+/// succeeded alongside the new state
+///
+/// As with input events, failing to emit to the virtual trackpad is fatal,
+/// but a request which doesn't make sense right now is only reported back to
+/// the controller.
+async fn on_request(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    sink: &mut Sink,
+    request: Request,
+    state: State,
+    controls: &mut Controls,
+    locked: bool,
+) -> Result<(State, Result<()>)> {
+    Ok(match request {
+        Request::StartSwipe { fingers, device } => {
+            on_start_request(config, devices, sink, fingers, device, state).await?
+        }
+        Request::Perform { gesture } => match state {
+            _ if controls.paused || locked => (state, Err(anyhow!("gestures are paused"))),
+            State::Normal(mut normal) if normal.pending_tap.is_none() => {
+                normal
+                    .release_held(config, sink)
+                    .await
+                    .with_context(|| "failed to lift held fingers")?;
+                debug!(
+                    "Performing {:?} swipe with {:?} fingers by request",
+                    gesture.direction, gesture.fingers
+                );
+                gesture_loop::perform(config, sink, &gesture)
+                    .await
+                    .with_context(|| "failed to perform gesture")?;
+                (normal.into(), Ok(()))
             }
-            InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
-                swiping
-                    .update(sink, 0, input.value(), x_mult, y_mult)
-                    .with_context(|| "failed to update swipe position")?;
-                swiping.into()
+            state => (state, Err(anyhow!("a gesture is already in progress"))),
+        },
+        Request::StopSwipe => match state {
+            State::Swiping(swiping) => {
+                debug!("Stopped swipe on {:?} by request", swiping.input_path);
+                let motion = devices
+                    .get_mut(&swiping.input_path)
+                    .map(EventStream::device_mut);
+                let state = swiping
+                    .stop(config, motion, sink)
+                    .await
+                    .with_context(|| "failed to stop swiping")?;
+                (state.into(), Ok(()))
             }
-            InputEventKind::Key(key) if key == swiping.trigger && input.value() == 0 => {
-                trace!("Stopped swipe on {source_path:?}");
-                swiping
-                    .stop(source, sink, grab)
-                    .with_context(|| "failed to stop swiping")?
-                    .into()
+            State::Scrolling(scrolling) => {
+                debug!("Stopped scrolling on {:?} by request", scrolling.input_path);
+                let source = devices
+                    .get_mut(&scrolling.input_path)
+                    .map(EventStream::device_mut);
+                let state = scrolling.stop(source);
+                (state.into(), Ok(()))
             }
-            _ => swiping.into(),
+            state @ State::Normal(_) => (state, Err(anyhow!("no gesture is in progress"))),
         },
+        Request::CancelSwipe => match state {
+            State::Swiping(swiping) => {
+                debug!("Cancelled swipe on {:?} by request", swiping.input_path);
+                let motion = devices
+                    .get_mut(&swiping.input_path)
+                    .map(EventStream::device_mut);
+                let state = swiping
+                    .cancel(config, motion, sink)
+                    .await
+                    .with_context(|| "failed to cancel swiping")?;
+                (state.into(), Ok(()))
+            }
+            state => (state, Err(anyhow!("no swipe is in progress"))),
+        },
+        Request::Pause => {
+            info!("Pausing gestures by request");
+            controls.paused = true;
+            (state, Ok(()))
+        }
+        Request::Resume => {
+            info!("Resuming gestures by request");
+            controls.paused = false;
+            (state, Ok(()))
+        }
+        Request::SetMultipliers {
+            fingers,
+            multipliers,
+        } => {
+            if let Err(err) = multipliers.check(&format!("{} fingers", fingers.count())) {
+                return Ok((state, Err(err)));
+            }
+            info!("Set multipliers for {fingers:?} fingers to {multipliers:?} by request");
+            config
+                .multipliers
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set(fingers, multipliers);
+            (state, Ok(()))
+        }
+        Request::SetFingersEnabled { fingers, enabled } => {
+            info!(
+                "{} {fingers:?} finger gestures by request",
+                if enabled { "Enabled" } else { "Disabled" }
+            );
+            controls
+                .disabled_fingers
+                .retain(|&disabled| disabled != fingers);
+            if !enabled {
+                controls.disabled_fingers.push(fingers);
+            }
+            (state, Ok(()))
+        }
+        Request::Status => (state, Ok(())),
+    })
+}
+
+#[cfg_attr(not(feature = "focus"), allow(clippy::unused_async))]
+async fn on_start_request(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    sink: &mut Sink,
+    fingers: Fingers,
+    device: Option<PathBuf>,
+    state: State,
+) -> Result<(State, Result<()>)> {
+    let normal = match state {
+        State::Normal(normal) if normal.pending_tap.is_none() => normal,
+        state => return Ok((state, Err(anyhow!("a gesture is already in progress")))),
+    };
+    let Some(motion_path) = device.or_else(|| config.motion_device.clone()) else {
+        return Ok((
+            normal.into(),
+            Err(anyhow!(
+                "no device was given, and there is no motion device"
+            )),
+        ));
+    };
+    let Some(motion) = devices.get_mut(&motion_path).map(EventStream::device_mut) else {
+        return Ok((
+            normal.into(),
+            Err(anyhow!("device {motion_path:?} is not being tracked")),
+        ));
+    };
+
+    debug!("Started swipe on {motion_path:?} with {fingers:?} fingers by request");
+    // no key is held for this swipe, and `KEY_RESERVED` is never sent, so
+    // only a stop or cancel request ends it
+    #[cfg_attr(not(feature = "focus"), allow(unused_mut))]
+    let mut swiping = normal
+        .start_swiping(
+            config,
+            motion_path,
+            motion,
+            sink,
+            Key::KEY_RESERVED,
+            fingers,
+        )
+        .await
+        .with_context(|| "failed to start swiping")?;
+    #[cfg(feature = "focus")]
+    {
+        swiping.touch.monitor = crate::focus::swipe_monitor(config).await;
+    }
+    Ok((swiping.into(), Ok(())))
+}
+
+/// Stops the swipe if its source device keeps failing to be read, so that the
+/// fingers aren't left down indefinitely
+async fn on_read_error(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
+    state: State,
+) -> Result<State> {
+    let State::Swiping(mut swiping) = state else {
+        return Ok(state);
+    };
+    if swiping.input_path != source_path {
+        return Ok(swiping.into());
+    }
+
+    let errors = swiping.record_read_error();
+    if config.max_read_errors == 0 || errors < config.max_read_errors {
+        return Ok(swiping.into());
+    }
+
+    warn!("Stopped swiping after {errors} consecutive read errors from {source_path:?}");
+    #[cfg(feature = "notifications")]
+    sink.notify(
+        crate::notifications::NotifyOn::Errors,
+        format!("Swipe stopped after {errors} errors reading its device"),
+    );
+    let motion = devices.get_mut(source_path).map(EventStream::device_mut);
+    Ok(swiping
+        .stop(config, motion, sink)
+        .await
+        .with_context(|| "failed to stop swiping")?
+        .into())
+}
+
+/// Handles an event while swiping
+///
+/// Only the device which the trigger was pressed on can end the swipe, so
+/// that a composite device reporting the same button on another event node
+/// doesn't end it early. Presses of trigger keys never start another swipe
+/// while one is in progress. Other devices' relative motion only moves the
+/// swipe with [`MotionSource::Any`].
+async fn on_swiping_input(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
+    input: &InputEvent,
+    mut swiping: Swiping,
+) -> Result<State> {
+    // with a separate motion device, other devices' motion is left alone
+    if config.motion_device.is_some()
+        && source_path != swiping.input_path
+        && matches!(
+            input.kind(),
+            InputEventKind::RelAxis(_) | InputEventKind::AbsAxis(_)
+        )
+    {
+        return Ok(swiping.into());
+    }
+
+    let moves_swipe =
+        config.motion_source == MotionSource::Any || source_path == swiping.input_path;
+    let motion = devices
+        .get_mut(&swiping.input_path)
+        .map(EventStream::device_mut);
+    Ok(match input.kind() {
+        InputEventKind::RelAxis(RelativeAxisType::REL_X) if moves_swipe => {
+            swiping
+                .update(config, sink, input.timestamp(), input.value(), 0)
+                .await
+                .with_context(|| "failed to update swipe position")?;
+            if source_path == swiping.input_path {
+                swiping.grab_deferred(motion);
+            }
+            swiping.into()
+        }
+        InputEventKind::RelAxis(RelativeAxisType::REL_Y) if moves_swipe => {
+            swiping
+                .update(config, sink, input.timestamp(), 0, input.value())
+                .await
+                .with_context(|| "failed to update swipe position")?;
+            if source_path == swiping.input_path {
+                swiping.grab_deferred(motion);
+            }
+            swiping.into()
+        }
+        InputEventKind::Key(key) if Some(key) == config.cancel_key && input.value() == 1 => {
+            trace!("Cancelled swipe on {source_path:?}");
+            swiping
+                .cancel(config, motion, sink)
+                .await
+                .with_context(|| "failed to cancel swiping")?
+                .into()
+        }
+        InputEventKind::Key(key)
+            if Some(key) == swiping.end_key
+                && source_path == swiping.trigger_path
+                && input.value() == 1 =>
+        {
+            trace!("Ended drag on {source_path:?}");
+            swiping
+                .stop(config, motion, sink)
+                .await
+                .with_context(|| "failed to stop dragging")?
+                .into()
+        }
+        InputEventKind::Key(key)
+            if key == swiping.trigger
+                && swiping.end_key.is_none()
+                && source_path == swiping.trigger_path
+                && input.value() == 0 =>
+        {
+            trace!("Stopped swipe on {source_path:?}");
+            swiping
+                .stop(config, motion, sink)
+                .await
+                .with_context(|| "failed to stop swiping")?
+                .into()
+        }
+        InputEventKind::Key(key) if config.turbo.is_some_and(|turbo| turbo.key == key) => {
+            let factor = config.turbo.map_or(1.0, |turbo| turbo.factor);
+            // key repeats don't change anything
+            match input.value() {
+                0 => swiping.touch.boost = 1.0,
+                1 => swiping.touch.boost = factor,
+                _ => {}
+            }
+            trace!("Turbo boost is now {}", swiping.touch.boost);
+            swiping.into()
+        }
+        InputEventKind::AbsAxis(axis @ (AbsoluteAxisType::ABS_RX | AbsoluteAxisType::ABS_RY)) => {
+            swiping.update_stick(axis, input.value());
+            swiping.into()
+        }
+        _ => {
+            // only the grabbed device's events are kept from applications, and
+            // the trigger's press was never passed through, so neither is its
+            // release
+            if swiping.grabbed
+                && source_path == swiping.input_path
+                && input.kind() != InputEventKind::Key(swiping.trigger)
+            {
+                sink.pass_through(*input)
+                    .with_context(|| "failed to pass through event")?;
+            }
+            swiping.into()
+        }
     })
 }
+
+fn on_scrolling_input(
+    config: &Config,
+    devices: &mut AHashMap<PathBuf, EventStream>,
+    source_path: &Path,
+    sink: &mut Sink,
+    input: &InputEvent,
+    mut scrolling: Scrolling,
+) -> Result<State> {
+    Ok(match input.kind() {
+        InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
+            scrolling
+                .update(config, sink, input.value(), 0)
+                .with_context(|| "failed to scroll")?;
+            scrolling.into()
+        }
+        InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
+            scrolling
+                .update(config, sink, 0, input.value())
+                .with_context(|| "failed to scroll")?;
+            scrolling.into()
+        }
+        InputEventKind::RelAxis(
+            axis @ (RelativeAxisType::REL_WHEEL
+            | RelativeAxisType::REL_HWHEEL
+            | RelativeAxisType::REL_WHEEL_HI_RES
+            | RelativeAxisType::REL_HWHEEL_HI_RES),
+        ) if scrolling.grabbed && source_path == scrolling.input_path => {
+            scrolling
+                .wheel(sink, axis, input.value())
+                .with_context(|| "failed to scroll by wheel")?;
+            scrolling.into()
+        }
+        InputEventKind::Key(key)
+            if key == scrolling.trigger
+                && source_path == scrolling.input_path
+                && input.value() == 0 =>
+        {
+            trace!("Stopped scrolling on {source_path:?}");
+            let source = devices
+                .get_mut(&scrolling.input_path)
+                .map(EventStream::device_mut);
+            scrolling.stop(source).into()
+        }
+        _ => scrolling.into(),
+    })
+}
+
+fn on_stick_tick(config: &Config, sink: &mut Sink, state: State) -> Result<State> {
+    let State::Swiping(mut swiping) = state else {
+        return Ok(state);
+    };
+    swiping
+        .tick_stick(config, sink)
+        .with_context(|| "failed to update swipe position")?;
+    Ok(swiping.into())
+}
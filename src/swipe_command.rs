@@ -0,0 +1,152 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use tokio::process::Command;
+
+use crate::{gesture_loop::Direction, states::Fingers};
+
+/// Command which runs when a swipe with some number of fingers ends in some
+/// direction
+#[derive(Debug, Clone)]
+pub struct SwipeCommand {
+    pub fingers: Fingers,
+    pub direction: Direction,
+    /// Passed to `sh -c`
+    pub command: String,
+}
+
+impl FromStr for SwipeCommand {
+    type Err = anyhow::Error;
+
+    /// Parses `<fingers>-<direction>=<command>`, e.g. `3-up=wmctrl -s 1`
+    fn from_str(s: &str) -> Result<Self> {
+        let usage = || anyhow!("expected `<fingers>-<direction>=<command>`");
+        let (gesture, command) = s.split_once('=').ok_or_else(usage)?;
+        let (fingers, direction) = gesture.split_once('-').ok_or_else(usage)?;
+        let fingers = fingers
+            .parse::<u8>()
+            .ok()
+            .and_then(Fingers::from_count)
+            .ok_or_else(|| anyhow!("finger count must be 2 to 5"))?;
+        let direction = Direction::from_str(direction, true)
+            .map_err(|_| anyhow!("direction must be up, down, left or right"))?;
+        if command.trim().is_empty() {
+            bail!("command is empty");
+        }
+        Ok(Self {
+            fingers,
+            direction,
+            command: command.to_owned(),
+        })
+    }
+}
+
+/// Runs the commands bound to swipes as they end
+#[derive(Debug)]
+pub struct SwipeCommands {
+    commands: Vec<SwipeCommand>,
+    /// Shortest time between two commands running
+    debounce: Duration,
+    last_run: Option<Instant>,
+}
+
+impl SwipeCommands {
+    pub fn new(commands: Vec<SwipeCommand>, debounce: Duration) -> Self {
+        Self {
+            commands,
+            debounce,
+            last_run: None,
+        }
+    }
+
+    /// Runs the command bound to a swipe with `fingers` which ended `(x, y)`
+    /// away from where it started, if there is one
+    ///
+    /// The swipe's direction is whichever of up, down, left and right its
+    /// overall motion is mostly in (see [`Direction::dominant`]), so a swipe
+    /// which changes direction partway through counts by where it ended up.
+    /// A command which would start within the debounce time of the last one
+    /// is skipped. The command runs in the background, and only its failure
+    /// is logged.
+    pub fn dispatch(&mut self, fingers: Fingers, x: i32, y: i32) {
+        #[allow(clippy::cast_precision_loss)]
+        let Some(direction) = Direction::dominant(x as f32, y as f32) else {
+            return;
+        };
+        let Some(bound) = self
+            .commands
+            .iter()
+            .find(|bound| bound.fingers == fingers && bound.direction == direction)
+        else {
+            return;
+        };
+        let count = fingers.count();
+        if self
+            .last_run
+            .is_some_and(|last_run| last_run.elapsed() < self.debounce)
+        {
+            debug!(
+                "Not running command for {count}-finger swipe {direction:?}, ran one too recently"
+            );
+            return;
+        }
+        self.last_run = Some(Instant::now());
+
+        info!(
+            "Running command for {count}-finger swipe {direction:?}: {}",
+            bound.command
+        );
+        let command = bound.command.clone();
+        tokio::spawn(async move {
+            match Command::new("sh").arg("-c").arg(&command).status().await {
+                Ok(status) if status.success() => {}
+                Ok(status) => warn!("Swipe command `{command}` failed: {status}"),
+                Err(err) => warn!(
+                    "Failed to run swipe command `{command}`: {:#}",
+                    anyhow::Error::new(err)
+                ),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_swipe_command() {
+        let command = "3-Up=wmctrl -s 1".parse::<SwipeCommand>().unwrap();
+        assert_eq!(command.fingers, Fingers::Three);
+        assert_eq!(command.direction, Direction::Up);
+        assert_eq!(command.command, "wmctrl -s 1");
+    }
+
+    #[test]
+    fn keeps_rest_of_command_as_it_is() {
+        let command = "2-left=echo a=b  c-d".parse::<SwipeCommand>().unwrap();
+        assert_eq!(command.direction, Direction::Left);
+        assert_eq!(command.command, "echo a=b  c-d");
+    }
+
+    #[test]
+    fn rejects_invalid_swipe_commands() {
+        for s in [
+            "",
+            "3-up",
+            "3up=true",
+            "1-up=true",
+            "three-up=true",
+            "3-sideways=true",
+            "3-up=",
+            "3-up=  ",
+        ] {
+            assert!(s.parse::<SwipeCommand>().is_err(), "{s:?}");
+        }
+    }
+}
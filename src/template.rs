@@ -0,0 +1,245 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+
+use crate::{
+    config::Config,
+    states::{Fingers, Touch, FRAME_INTERVAL},
+    trackpad,
+};
+
+const TEMPLATE_EXTENSION: &str = "gesture";
+
+/// Gets the default directory that gesture templates are loaded from
+///
+/// This is `fukomaster/templates` under `$XDG_CONFIG_HOME` if it is set, and
+/// under `~/.config` otherwise.
+pub fn default_dir() -> PathBuf {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default()
+        .join("fukomaster")
+        .join("templates")
+}
+
+/// Named gesture, made of a sequence of relative moves with timing
+///
+/// Templates are stored as `<name>.gesture` files, with one step per line:
+///
+/// ```text
+/// # swipe to the next workspace
+/// fingers 3
+/// move -40 0 8
+/// move -40 0 8
+/// wait 50
+/// ```
+///
+/// - `fingers <2-5>` sets how many fingers touch down, and must come first
+/// - `move <dx> <dy> [ms]` moves like a mouse would by `(dx, dy)`, then waits
+///   `ms` milliseconds (one frame by default)
+/// - `wait <ms>` waits without moving
+/// - any later `fingers <2-5>` changes how many fingers are down without
+///   lifting the others, for compound gestures like a 2-finger motion which
+///   turns into a 3-finger swipe
+///
+/// Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub fingers: Fingers,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Step {
+    Move { dx: i32, dy: i32, delay: Duration },
+    Wait(Duration),
+    Fingers(Fingers),
+}
+
+impl Template {
+    /// Loads the template called `name` from `dir`
+    pub fn load(dir: &Path, name: &str) -> Result<Self> {
+        let path = dir.join(format!("{name}.{TEMPLATE_EXTENSION}"));
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read template {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("invalid template {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut fingers = None;
+        let mut steps = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line_no = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(step) = parse_line(line, &mut fingers, !steps.is_empty())
+                .with_context(|| format!("line {line_no}"))?
+            {
+                steps.push(step);
+            }
+        }
+
+        Ok(Self {
+            fingers: fingers.ok_or_else(|| anyhow!("missing `fingers` line"))?,
+            steps,
+        })
+    }
+}
+
+/// Parses a single non-empty line, returning the step it adds if any
+fn parse_line(line: &str, fingers: &mut Option<Fingers>, has_steps: bool) -> Result<Option<Step>> {
+    let mut words = line.split_whitespace();
+    let directive = words.next().expect("line should not be empty");
+    let args = words.collect::<Vec<_>>();
+    match (directive, args.as_slice()) {
+        ("fingers", [count]) => {
+            let count = count
+                .parse::<u8>()
+                .ok()
+                .and_then(Fingers::from_count)
+                .ok_or_else(|| anyhow!("finger count must be 2 to 5"))?;
+            if fingers.is_some() {
+                return Ok(Some(Step::Fingers(count)));
+            }
+            if has_steps {
+                bail!("the first `fingers` must come before any other steps");
+            }
+            *fingers = Some(count);
+            Ok(None)
+        }
+        ("move", [dx, dy, rest @ ..]) if rest.len() <= 1 => {
+            let dx = dx.parse().with_context(|| "invalid X movement")?;
+            let dy = dy.parse().with_context(|| "invalid Y movement")?;
+            let delay = match rest {
+                [ms] => parse_millis(ms)?,
+                _ => FRAME_INTERVAL,
+            };
+            Ok(Some(Step::Move { dx, dy, delay }))
+        }
+        ("wait", [ms]) => Ok(Some(Step::Wait(parse_millis(ms)?))),
+        ("fingers" | "move" | "wait", _) => bail!("wrong number of arguments to `{directive}`"),
+        _ => bail!("unknown step `{directive}`"),
+    }
+}
+
+fn parse_millis(ms: &str) -> Result<Duration> {
+    ms.parse()
+        .map(Duration::from_millis)
+        .with_context(|| "invalid duration in milliseconds")
+}
+
+/// Creates a virtual trackpad and plays `template` on it
+///
+/// Moves go through the same gain and multipliers as motion from a real
+/// mouse.
+pub async fn play(config: &Config, name: &str, template: &Template) -> Result<()> {
+    info!("Creating virtual trackpad");
+    let (mut sink, _) = trackpad::create_trackpad(config).await?;
+
+    let mut touch = Touch::down(config, &mut sink, template.fingers)?;
+    for step in &template.steps {
+        match *step {
+            Step::Move { dx, dy, delay } => {
+                touch
+                    .update(config, &mut sink, SystemTime::now(), dx, dy)
+                    .await?;
+                tokio::time::sleep(delay).await;
+            }
+            Step::Wait(duration) => tokio::time::sleep(duration).await,
+            Step::Fingers(fingers) => touch.change_fingers(config, &mut sink, fingers)?,
+        }
+    }
+    touch.lift(config, &mut sink)?;
+
+    info!("Played template {name:?}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_example() {
+        let template = Template::parse(
+            "# swipe to the next workspace
+            fingers 3
+
+            move -40 0 8
+            move -40 0
+            wait 50",
+        )
+        .unwrap();
+        assert_eq!(template.fingers, Fingers::Three);
+        assert!(matches!(
+            template.steps[..],
+            [
+                Step::Move {
+                    dx: -40,
+                    dy: 0,
+                    delay: first_delay,
+                },
+                Step::Move {
+                    dx: -40,
+                    dy: 0,
+                    delay: FRAME_INTERVAL,
+                },
+                Step::Wait(wait),
+            ] if first_delay == Duration::from_millis(8) && wait == Duration::from_millis(50)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for contents in [
+            "",
+            "move 1 1",
+            "fingers 1",
+            "fingers 6",
+            "fingers three",
+            "fingers 3\nmove 1",
+            "fingers 3\nmove 1 2 3 4",
+            "fingers 3\nmove 1.5 0",
+            "fingers 3\nwait",
+            "fingers 3\nwait -1",
+            "fingers 3\njump 1 1",
+        ] {
+            assert!(Template::parse(contents).is_err(), "{contents:?}");
+        }
+    }
+
+    #[test]
+    fn later_fingers_change_finger_count() {
+        let template = Template::parse(
+            "fingers 2
+            move 0 -20
+            fingers 3
+            move 0 -20",
+        )
+        .unwrap();
+        assert_eq!(template.fingers, Fingers::Two);
+        assert!(matches!(
+            template.steps[..],
+            [
+                Step::Move { .. },
+                Step::Fingers(Fingers::Three),
+                Step::Move { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn first_fingers_comes_before_steps() {
+        assert!(Template::parse("move 0 -20\nfingers 3").is_err());
+        assert!(Template::parse("wait 10\nfingers 3").is_err());
+    }
+}
@@ -0,0 +1,1125 @@
+use std::{
+    ffi::CStr,
+    fs, io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use evdev::{
+    uinput::{VirtualDevice, VirtualDeviceBuilder},
+    AbsInfo, AbsoluteAxisType, AttributeSet, BusType, Device, EventType, InputEvent, InputId, Key,
+    MiscType, PropType, Synchronization, UinputAbsSetup,
+};
+use log::{info, warn};
+
+use crate::{
+    auto_tune::AutoTune,
+    config::Config,
+    evemu,
+    jitter::Jitter,
+    latency::Latency,
+    libinput_record,
+    passthrough::{self, Passthrough},
+    pointer::{self, Pointer},
+    recording,
+    states::TrackingIds,
+    swipe_command::SwipeCommands,
+    visualize::Visualizer,
+};
+
+pub const VIRTUAL_DEVICE_NAME: &str = "fukomaster virtual trackpad";
+
+/// Highest pressure that a finger reports, if pressure is enabled
+pub const MAX_PRESSURE: i32 = 255;
+
+/// Kind of input device that the virtual trackpad presents itself as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DeviceType {
+    /// Indirect touch surface (`INPUT_PROP_POINTER`), which the compositor
+    /// turns into gestures and pointer motion
+    ///
+    /// Positions are only meaningful relative to each other, so fingers touch
+    /// down in the middle of a huge axis range and move by the swipe deltas.
+    #[default]
+    Trackpad,
+    /// Direct touch surface (`INPUT_PROP_DIRECT`), which the compositor maps
+    /// onto a screen
+    ///
+    /// The position axes cover the screen exactly, so one unit is one pixel.
+    /// Fingers touch down in the middle of the screen, mouse motion moves them
+    /// to absolute screen positions from there, and they stop at the edges of
+    /// the screen.
+    Touchscreen,
+}
+
+/// Where the coordinate origin of the virtual trackpad's position axes is
+///
+/// Fingers touch down in the middle of the axes either way, and move in the
+/// same directions; only the range of the axes changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Origin {
+    /// (0, 0) is in the middle of the axes, so positions are negative above
+    /// and left of where fingers touch down
+    ///
+    /// The default for a trackpad.
+    Center,
+    /// (0, 0) is the top-left corner of the axes, so positions are never
+    /// negative
+    ///
+    /// The default for a touchscreen.
+    TopLeft,
+}
+
+impl Origin {
+    fn default_for(device_type: DeviceType) -> Self {
+        match device_type {
+            DeviceType::Trackpad => Self::Center,
+            DeviceType::Touchscreen => Self::TopLeft,
+        }
+    }
+}
+
+/// Multi-touch protocol that the virtual trackpad reports fingers with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MtProtocol {
+    /// Type A: every frame lists the position of each finger touching,
+    /// separated by `SYN_MT_REPORT`, and a finger is lifted by leaving it out
+    ///
+    /// There are no slots or tracking IDs, so the consumer has to work out
+    /// which finger is which.
+    A,
+    /// Type B: each finger has a slot, and only changes are reported, with a
+    /// finger's tracking ID set to -1 when it is lifted
+    #[default]
+    B,
+}
+
+/// Bus that the virtual trackpad claims to be connected through
+///
+/// Compositors and libinput may apply quirks based on the bus, e.g. treating
+/// Bluetooth trackpads as external ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Bus {
+    #[default]
+    Usb,
+    I2c,
+    Bluetooth,
+    Virtual,
+}
+
+impl Bus {
+    fn bus_type(self) -> BusType {
+        match self {
+            Self::Usb => BusType::BUS_USB,
+            Self::I2c => BusType::BUS_I2C,
+            Self::Bluetooth => BusType::BUS_BLUETOOTH,
+            Self::Virtual => BusType::BUS_VIRTUAL,
+        }
+    }
+}
+
+/// Capabilities that the virtual trackpad is created with
+pub struct TrackpadSpec {
+    pub name: &'static str,
+    pub input_id: InputId,
+    pub properties: AttributeSet<PropType>,
+    pub keys: AttributeSet<Key>,
+    pub abs_axes: Vec<UinputAbsSetup>,
+    /// Miscellaneous events, which carry the timestamps of keepalive frames
+    pub misc: AttributeSet<MiscType>,
+    /// Position that fingers touch down at, in the middle of the position axes
+    pub origin: (i32, i32),
+    /// Position axes that finger positions are clamped to, if they must stay
+    /// within range
+    pub bounds: Option<(PositionAxis, PositionAxis)>,
+}
+
+/// Range and resolution of one of the trackpad's position axes
+#[derive(Debug, Clone, Copy)]
+pub struct PositionAxis {
+    pub min: i32,
+    pub max: i32,
+    pub resolution: i32,
+}
+
+impl PositionAxis {
+    pub fn center(self) -> i32 {
+        i32::try_from((i64::from(self.min) + i64::from(self.max)) / 2)
+            .expect("midpoint of two `i32`s should fit in an `i32`")
+    }
+
+    fn abs_info(self, config: &Config) -> AbsInfo {
+        AbsInfo::new(
+            0,
+            self.min,
+            self.max,
+            config.abs_fuzz,
+            config.abs_flat,
+            self.resolution,
+        )
+    }
+}
+
+/// Gets the position axes of the virtual trackpad when it isn't matching a
+/// real one
+fn default_position_axes(config: &Config) -> (PositionAxis, PositionAxis) {
+    let resolution = i32::from(config.resolution);
+    let origin = config
+        .origin
+        .unwrap_or_else(|| Origin::default_for(config.device_type));
+    match config.device_type {
+        DeviceType::Trackpad => {
+            let axis = PositionAxis {
+                min: match origin {
+                    Origin::Center => i32::MIN,
+                    Origin::TopLeft => 0,
+                },
+                max: i32::MAX,
+                resolution,
+            };
+            (axis, axis)
+        }
+        DeviceType::Touchscreen => {
+            let (width, height) = config.screen_size;
+            let axis = |size: u16| {
+                let size = i32::from(size.max(1));
+                let min = match origin {
+                    Origin::Center => -(size / 2),
+                    Origin::TopLeft => 0,
+                };
+                PositionAxis {
+                    min,
+                    max: min + size - 1,
+                    resolution,
+                }
+            };
+            (axis(width), axis(height))
+        }
+    }
+}
+
+/// Reads the position axes of a real trackpad, so that the virtual trackpad
+/// can be made to match it
+///
+/// Multi-touch position axes are preferred, falling back to `ABS_X`/`ABS_Y`.
+pub fn read_position_axes(path: &Path) -> Result<(PositionAxis, PositionAxis)> {
+    let device = Device::open(path).with_context(|| "failed to open device")?;
+    let Some(supported) = device.supported_absolute_axes() else {
+        bail!("device has no absolute axes");
+    };
+
+    let (x_axis, y_axis) = if supported.contains(AbsoluteAxisType::ABS_MT_POSITION_X)
+        && supported.contains(AbsoluteAxisType::ABS_MT_POSITION_Y)
+    {
+        (
+            AbsoluteAxisType::ABS_MT_POSITION_X,
+            AbsoluteAxisType::ABS_MT_POSITION_Y,
+        )
+    } else if supported.contains(AbsoluteAxisType::ABS_X)
+        && supported.contains(AbsoluteAxisType::ABS_Y)
+    {
+        (AbsoluteAxisType::ABS_X, AbsoluteAxisType::ABS_Y)
+    } else {
+        bail!("device has no X and Y position axes");
+    };
+
+    let state = device
+        .get_abs_state()
+        .with_context(|| "failed to read absolute axes")?;
+    let axis = |axis: AbsoluteAxisType| {
+        let info = state[usize::from(axis.0)];
+        PositionAxis {
+            min: info.minimum,
+            max: info.maximum,
+            resolution: info.resolution,
+        }
+    };
+    Ok((axis(x_axis), axis(y_axis)))
+}
+
+impl TrackpadSpec {
+    pub fn new(config: &Config) -> Self {
+        // capabilities of a real trackpad, for reference - run
+        // `fukomaster describe-target` to see what this actually builds
+        /*
+        # Supported events:
+        #   Event type 0 (EV_SYN)
+        #     Event code 0 (SYN_REPORT)
+        #     Event code 1 (SYN_CONFIG)
+        #     Event code 2 (SYN_MT_REPORT)
+        #     Event code 3 (SYN_DROPPED)
+        #     Event code 4 ((null))
+        #     Event code 5 ((null))
+        #     Event code 6 ((null))
+        #     Event code 7 ((null))
+        #     Event code 8 ((null))
+        #     Event code 9 ((null))
+        #     Event code 10 ((null))
+        #     Event code 11 ((null))
+        #     Event code 12 ((null))
+        #     Event code 13 ((null))
+        #     Event code 14 ((null))
+        #     Event code 15 (SYN_MAX)
+        #   Event type 1 (EV_KEY)
+        #     Event code 272 (BTN_LEFT)
+        #     Event code 273 (BTN_RIGHT)
+        #     Event code 325 (BTN_TOOL_FINGER)
+        #     Event code 328 (BTN_TOOL_QUINTTAP)
+        #     Event code 330 (BTN_TOUCH)
+        #     Event code 333 (BTN_TOOL_DOUBLETAP)
+        #     Event code 334 (BTN_TOOL_TRIPLETAP)
+        #     Event code 335 (BTN_TOOL_QUADTAP)
+        #   Event type 3 (EV_ABS)
+        #     Event code 0 (ABS_X)
+        #       Value      848
+        #       Min          0
+        #       Max       1337
+        #       Fuzz         0
+        #       Flat         0
+        #       Resolution  12
+        #     Event code 1 (ABS_Y)
+        #       Value      467
+        #       Min          0
+        #       Max        876
+        #       Fuzz         0
+        #       Flat         0
+        #       Resolution  12
+        #     Event code 47 (ABS_MT_SLOT)
+        #       Value        0
+        #       Min          0
+        #       Max          4
+        #       Fuzz         0
+        #       Flat         0
+        #       Resolution   0
+        #     Event code 53 (ABS_MT_POSITION_X)
+        #       Value        0
+        #       Min          0
+        #       Max       1337
+        #       Fuzz         0
+        #       Flat         0
+        #       Resolution  12
+        #     Event code 54 (ABS_MT_POSITION_Y)
+        #       Value        0
+        #       Min          0
+        #       Max        876
+        #       Fuzz         0
+        #       Flat         0
+        #       Resolution  12
+        #     Event code 55 (ABS_MT_TOOL_TYPE)
+        #       Value        0
+        #       Min          0
+        #       Max          2
+        #       Fuzz         0
+        #       Flat         0
+        #       Resolution   0
+        #     Event code 57 (ABS_MT_TRACKING_ID)
+        #       Value        0
+        #       Min          0
+        #       Max      65535
+        #       Fuzz         0
+        #       Flat         0
+        #       Resolution   0
+        #   Event type 4 (EV_MSC)
+        #     Event code 5 (MSC_TIMESTAMP)
+        # Properties:
+        #   Property  type 0 (INPUT_PROP_POINTER)
+        #   Property  type 2 (INPUT_PROP_BUTTONPAD)
+        */
+
+        // https://www.kernel.org/doc/html/v4.12/input/event-codes.html
+        // https://www.kernel.org/doc/html/v4.12/input/multi-touch-protocol.html
+
+        fn abs_with_max(max: i32) -> AbsInfo {
+            AbsInfo::new(0, 0, max, 0, 0, 0)
+        }
+
+        let matched =
+            config
+                .match_device
+                .as_deref()
+                .and_then(|path| match read_position_axes(path) {
+                    Ok((x, y)) => {
+                        info!("Matching position axes of {path:?}");
+                        info!("  X = {x:?}");
+                        info!("  Y = {y:?}");
+                        Some((x, y))
+                    }
+                    Err(err) => {
+                        warn!("Failed to read position axes of {path:?}, using defaults: {err:#}");
+                        None
+                    }
+                });
+        let (pos_x, pos_y) = matched.unwrap_or_else(|| default_position_axes(config));
+
+        let (properties, keys, bounds) = match config.device_type {
+            DeviceType::Trackpad => (
+                AttributeSet::from_iter([PropType::POINTER]),
+                AttributeSet::from_iter([
+                    Key::BTN_TOOL_FINGER,
+                    Key::BTN_TOUCH,
+                    Key::BTN_TOOL_DOUBLETAP,
+                    Key::BTN_TOOL_TRIPLETAP,
+                    Key::BTN_TOOL_QUADTAP,
+                    Key::BTN_TOOL_QUINTTAP,
+                ]),
+                None,
+            ),
+            // touchscreens don't report the number of fingers through
+            // `BTN_TOOL_*`, and the compositor rejects touches outside the
+            // screen, so positions are kept within the axes
+            DeviceType::Touchscreen => (
+                AttributeSet::from_iter([PropType::DIRECT]),
+                AttributeSet::from_iter([Key::BTN_TOUCH]),
+                Some((pos_x, pos_y)),
+            ),
+        };
+
+        Self {
+            name: VIRTUAL_DEVICE_NAME,
+            // USB is the same as the `evdev` default, but explicit so that we
+            // can describe it
+            input_id: InputId::new(config.bus.bus_type(), 0x1234, 0x5678, 0x111),
+            properties,
+            keys,
+            abs_axes: match config.mt_protocol {
+                MtProtocol::A => vec![],
+                MtProtocol::B => vec![
+                    UinputAbsSetup::new(
+                        AbsoluteAxisType::ABS_MT_SLOT,
+                        abs_with_max(4), // max 5 touches
+                    ),
+                    UinputAbsSetup::new(
+                        AbsoluteAxisType::ABS_MT_TRACKING_ID,
+                        abs_with_max(i32::MAX),
+                    ),
+                ],
+            }
+            .into_iter()
+            .chain([
+                UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_POSITION_X, pos_x.abs_info(config)),
+                UinputAbsSetup::new(AbsoluteAxisType::ABS_MT_POSITION_Y, pos_y.abs_info(config)),
+            ])
+            .chain(config.pressure_ramp.map(|_| {
+                UinputAbsSetup::new(
+                    AbsoluteAxisType::ABS_MT_PRESSURE,
+                    abs_with_max(MAX_PRESSURE),
+                )
+            }))
+            .collect(),
+            misc: AttributeSet::from_iter([MiscType::MSC_TIMESTAMP]),
+            origin: (pos_x.center(), pos_y.center()),
+            bounds,
+        }
+    }
+
+    pub fn build(&self) -> Result<VirtualDevice> {
+        let mut builder = VirtualDeviceBuilder::new()?
+            .name(self.name)
+            .input_id(self.input_id.clone())
+            .with_properties(&self.properties)?
+            .with_keys(&self.keys)?
+            .with_msc(&self.misc)?;
+        for axis in &self.abs_axes {
+            builder = builder.with_absolute_axis(axis)?;
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Starts each recording of emitted events which is enabled
+///
+/// `event_node` is the virtual trackpad's event node, if it's known.
+fn start_recorders(
+    config: &Config,
+    spec: &TrackpadSpec,
+    event_node: Option<&Path>,
+) -> Result<Vec<Box<dyn Recorder>>> {
+    let mut recorders = Vec::<Box<dyn Recorder>>::new();
+    if let Some(path) = &config.evemu_record {
+        let recorder = evemu::Recorder::create(path, spec)
+            .with_context(|| format!("failed to start recording to {}", path.display()))?;
+        info!("Recording emitted events to {path:?}");
+        recorders.push(Box::new(recorder));
+    }
+    if let Some(path) = &config.libinput_record {
+        let recorder = libinput_record::Recorder::create(path, spec, event_node)
+            .with_context(|| format!("failed to start recording to {}", path.display()))?;
+        info!("Recording emitted events to {path:?} for `libinput replay`");
+        recorders.push(Box::new(recorder));
+    }
+    if let Some(path) = &config.visualize {
+        let visualizer = Visualizer::create(path, spec).with_context(|| {
+            format!("failed to start writing finger path to {}", path.display())
+        })?;
+        info!("Writing finger path to {path:?}");
+        recorders.push(Box::new(visualizer));
+    }
+    if let Some(path) = &config.record {
+        let recorder = recording::Recorder::create(path, config, spec)
+            .with_context(|| format!("failed to start recording to {}", path.display()))?;
+        info!("Recording emitted events to {path:?} for `fukomaster replay`");
+        recorders.push(Box::new(recorder));
+    }
+    Ok(recorders)
+}
+
+/// Writes the frames emitted to the virtual trackpad to a recording
+pub trait Recorder: Send {
+    /// Records a single frame of events, including the trailing `SYN_REPORT`
+    fn record(&mut self, events: &[InputEvent]) -> io::Result<()>;
+}
+
+/// `uinput` device that the virtual trackpad is emitted through
+enum SinkDevice {
+    /// Created by us, and destroyed when dropped
+    Created(VirtualDevice),
+    /// Created by another process, which handed us its file descriptor
+    Inherited(OwnedFd),
+    /// In-memory file, which tests read the emitted frames back from
+    #[cfg(test)]
+    Memory(fs::File),
+}
+
+impl AsRawFd for SinkDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Created(device) => device.as_raw_fd(),
+            Self::Inherited(fd) => fd.as_raw_fd(),
+            #[cfg(test)]
+            Self::Memory(file) => file.as_raw_fd(),
+        }
+    }
+}
+
+/// Virtual trackpad that events are emitted to
+pub struct Sink {
+    device: SinkDevice,
+    origin: (i32, i32),
+    bounds: Option<(PositionAxis, PositionAxis)>,
+    recorders: Vec<Box<dyn Recorder>>,
+    /// Companion pointer used for `--cursor-lock`, `--scroll-key` and
+    /// `--scroll-two-finger`
+    pointer: Option<Pointer>,
+    /// Companion device used for `--passthrough`
+    passthrough: Option<Passthrough>,
+    next_tracking_id: i32,
+    /// Maximum number of bytes in a single write to the device
+    max_write: usize,
+    latency: Option<Latency>,
+    auto_tune: Option<AutoTune>,
+    swipe_commands: Option<SwipeCommands>,
+    /// Whether frames are dropped instead of emitted, for `--swipe-cmd-only`
+    muted: bool,
+    #[cfg(feature = "notifications")]
+    notifier: Option<crate::notifications::Notifier>,
+    jitter: Option<Jitter>,
+    /// How many frames have been emitted to the device
+    frames: u64,
+    /// How many events have been emitted to the device, including each
+    /// frame's `SYN_REPORT`
+    events: u64,
+    /// When the last frame was emitted to the device
+    last_emitted: Instant,
+    sys_path: PathBuf,
+}
+
+impl Sink {
+    /// Gets the position that fingers touch down at
+    pub fn origin(&self) -> (i32, i32) {
+        self.origin
+    }
+
+    /// Converts a position relative to the origin into a position on the
+    /// position axes
+    ///
+    /// On a touchscreen, this is an absolute screen position which stops at
+    /// the edges of the screen.
+    pub fn absolute_position(&self, x: i32, y: i32) -> (i32, i32) {
+        let (origin_x, origin_y) = self.origin;
+        let x = origin_x.saturating_add(x);
+        let y = origin_y.saturating_add(y);
+        match self.bounds {
+            Some((bounds_x, bounds_y)) => (
+                x.clamp(bounds_x.min, bounds_x.max),
+                y.clamp(bounds_y.min, bounds_y.max),
+            ),
+            None => (x, y),
+        }
+    }
+
+    /// Gets the offset to add to the next emitted finger position, which is
+    /// zero unless jitter is enabled
+    pub fn jitter(&mut self) -> (i32, i32) {
+        self.jitter.as_mut().map_or((0, 0), Jitter::next_offset)
+    }
+
+    /// Gets how many frames have been emitted to the device so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Gets how many events have been emitted to the device so far, including
+    /// each frame's `SYN_REPORT`
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Gets when the last frame was emitted to the device
+    pub fn last_emitted(&self) -> Instant {
+        self.last_emitted
+    }
+
+    /// Emits a frame with nothing but an `MSC_TIMESTAMP` of `time`, so that
+    /// the compositor sees the device as still in use
+    ///
+    /// The kernel drops frames with no events besides the `SYN_REPORT`, and
+    /// repeated axis values, so the timestamp is what gets the frame through.
+    /// It carries no touches, so it doesn't change any gesture.
+    pub fn keepalive(&mut self, time: libc::timeval) -> Result<()> {
+        // microseconds which wrap around, like a real device's timestamps
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_possible_wrap,
+            clippy::cast_sign_loss
+        )]
+        let micros = (time.tv_sec * 1_000_000 + time.tv_usec) as u32 as i32;
+        self.emit(&[InputEvent::from(libc::input_event {
+            time,
+            type_: EventType::MISC.0,
+            code: MiscType::MSC_TIMESTAMP.0,
+            value: micros,
+        })])
+    }
+
+    /// Gets the sys path of the virtual trackpad
+    pub fn sys_path(&self) -> &Path {
+        &self.sys_path
+    }
+
+    /// Gets the latency measurements, if latency is being measured
+    pub fn latency_mut(&mut self) -> Option<&mut Latency> {
+        self.latency.as_mut()
+    }
+
+    /// Gets the record of recent swipes, if multipliers are being tuned
+    pub fn auto_tune_mut(&mut self) -> Option<&mut AutoTune> {
+        self.auto_tune.as_mut()
+    }
+
+    /// Shows `message` as a desktop notification, if notifications are shown
+    /// for `kind`
+    #[cfg(feature = "notifications")]
+    pub fn notify(&self, kind: crate::notifications::NotifyOn, message: String) {
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(kind, message);
+        }
+    }
+
+    /// Gets the commands bound to swipes, if there are any
+    pub fn swipe_commands_mut(&mut self) -> Option<&mut SwipeCommands> {
+        self.swipe_commands.as_mut()
+    }
+
+    /// Cancels out cursor motion that the compositor saw from a source device,
+    /// if there is a companion pointer
+    pub fn cancel_pointer_motion(&mut self, dx: i32, dy: i32) -> Result<()> {
+        match &mut self.pointer {
+            Some(pointer) => pointer.cancel_motion(dx, dy),
+            None => Ok(()),
+        }
+    }
+
+    /// Scrolls the companion pointer, in high-resolution scroll units
+    pub fn scroll(&mut self, horizontal: i32, vertical: i32) -> Result<()> {
+        match &mut self.pointer {
+            Some(pointer) => pointer.scroll(horizontal, vertical),
+            None => Ok(()),
+        }
+    }
+
+    /// Re-emits an event from a grabbed source device through the passthrough
+    /// device, if there is one and the event isn't part of the swipe
+    pub fn pass_through(&mut self, event: InputEvent) -> Result<()> {
+        match &mut self.passthrough {
+            Some(passthrough) if Passthrough::forwards(&event) => passthrough.forward(event),
+            _ => Ok(()),
+        }
+    }
+
+    /// Forgets any partially scrolled notch on the companion pointer
+    pub fn reset_scroll(&mut self) {
+        if let Some(pointer) = &mut self.pointer {
+            pointer.reset_scroll();
+        }
+    }
+
+    /// Gets the tracking ID for a finger touching down in `slot`
+    pub fn new_tracking_id(&mut self, scheme: TrackingIds, slot: i32) -> i32 {
+        /// Tracking IDs wrap around at the same value as the kernel's
+        const MAX_TRACKING_ID: i32 = 0xffff;
+
+        match scheme {
+            TrackingIds::Slot => slot,
+            TrackingIds::Sequential => {
+                let id = self.next_tracking_id;
+                self.next_tracking_id = (id + 1) & MAX_TRACKING_ID;
+                id
+            }
+        }
+    }
+
+    /// Emits a single frame of events, followed by a `SYN_REPORT`
+    ///
+    /// The frame is written in one go, or in batches of at most
+    /// `max_batch_events` with the `SYN_REPORT` in the last one. If only part
+    /// of it makes it to the device, writing the rest is retried once. If
+    /// that also fails, a lone `SYN_REPORT` is sent to close off the
+    /// partial frame, so that consumers don't merge it into the next frame.
+    pub fn emit(&mut self, events: &[InputEvent]) -> Result<()> {
+        if self.muted {
+            return Ok(());
+        }
+        let time = events.last().map(|event| event.as_ref().time);
+        let frame = events
+            .iter()
+            .map(|event| *event.as_ref())
+            .chain([syn_report(time)])
+            .collect::<Vec<_>>();
+        let bytes = as_bytes(&frame);
+
+        let fd = self.device.as_raw_fd();
+        let mut written = 0;
+        if let Err(err) = write_from(fd, bytes, &mut written, self.max_write) {
+            warn!(
+                "Only emitted {}/{} events of frame, retrying the rest: {:#}",
+                written / mem::size_of::<libc::input_event>(),
+                frame.len(),
+                anyhow::Error::new(err)
+            );
+
+            if let Err(err) = write_from(fd, bytes, &mut written, self.max_write) {
+                let events_written = written / mem::size_of::<libc::input_event>();
+                if events_written > 0 {
+                    let syn = [syn_report(time)];
+                    if write_from(fd, as_bytes(&syn), &mut 0, self.max_write).is_ok() {
+                        warn!("Closed partially emitted frame with a SYN_REPORT");
+                    }
+                }
+                return Err(anyhow::Error::new(err).context(format!(
+                    "failed to emit frame ({events_written}/{} events written)",
+                    frame.len()
+                )));
+            }
+            info!("Finished emitting partially emitted frame");
+        }
+        self.frames += 1;
+        self.events += frame.len() as u64;
+        self.last_emitted = Instant::now();
+
+        self.recorders
+            .retain_mut(|recorder| match recorder.record(events) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!(
+                        "Failed to record emitted events, recording stopped: {:#}",
+                        anyhow::Error::new(err)
+                    );
+                    false
+                }
+            });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Sink {
+    /// Creates a sink which emits to an in-memory file rather than a device,
+    /// so that tests can run without access to `/dev/uinput`
+    pub fn in_memory(config: &Config) -> Result<Self> {
+        let spec = TrackpadSpec::new(config);
+        let name = CStr::from_bytes_with_nul(b"fukomaster-test-sink\0")
+            .expect("name should be nul-terminated");
+        // SAFETY: `name` is a valid C string
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).with_context(|| "failed to create memfd");
+        }
+        // SAFETY: `fd` was just created, and nothing else owns it
+        let file = unsafe { fs::File::from_raw_fd(fd) };
+
+        Ok(Self {
+            device: SinkDevice::Memory(file),
+            origin: spec.origin,
+            bounds: spec.bounds,
+            recorders: Vec::new(),
+            pointer: None,
+            passthrough: None,
+            next_tracking_id: 0,
+            max_write: usize::MAX,
+            latency: None,
+            auto_tune: None,
+            swipe_commands: None,
+            muted: config.swipe_cmd_only,
+            #[cfg(feature = "notifications")]
+            notifier: None,
+            jitter: None,
+            frames: 0,
+            events: 0,
+            last_emitted: Instant::now(),
+            sys_path: PathBuf::new(),
+        })
+    }
+
+    /// Takes the frames emitted since this was last called, each without its
+    /// trailing `SYN_REPORT`
+    pub fn take_frames(&mut self) -> Vec<Vec<InputEvent>> {
+        use std::io::{Read, Seek};
+
+        let SinkDevice::Memory(file) = &mut self.device else {
+            panic!("sink should be in memory");
+        };
+        let mut bytes = Vec::new();
+        file.rewind()
+            .and_then(|()| file.read_to_end(&mut bytes))
+            .and_then(|_| file.set_len(0))
+            .and_then(|()| file.rewind())
+            .expect("in-memory sink should be readable");
+
+        let mut frames = Vec::new();
+        let mut frame = Vec::new();
+        for chunk in bytes.chunks_exact(mem::size_of::<libc::input_event>()) {
+            // SAFETY: the chunk is exactly one `input_event` long, which was
+            // written from a valid one
+            let event = unsafe { chunk.as_ptr().cast::<libc::input_event>().read_unaligned() };
+            if event.type_ == EventType::SYNCHRONIZATION.0
+                && event.code == Synchronization::SYN_REPORT.0
+            {
+                frames.push(mem::take(&mut frame));
+            } else {
+                frame.push(InputEvent::from(event));
+            }
+        }
+        assert!(frame.is_empty(), "last frame should end with a SYN_REPORT");
+        frames
+    }
+}
+
+fn syn_report(time: Option<libc::timeval>) -> libc::input_event {
+    libc::input_event {
+        time: time.unwrap_or(libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        }),
+        type_: EventType::SYNCHRONIZATION.0,
+        code: Synchronization::SYN_REPORT.0,
+        value: 0,
+    }
+}
+
+fn as_bytes(events: &[libc::input_event]) -> &[u8] {
+    // SAFETY: `input_event` is a plain `repr(C)` struct, so any initialized
+    // value of it can be viewed as bytes
+    unsafe { std::slice::from_raw_parts(events.as_ptr().cast::<u8>(), mem::size_of_val(events)) }
+}
+
+/// Writes `bytes` to `fd`, starting from `written` bytes in and advancing
+/// `written` as bytes are written
+///
+/// Each write is at most `max_write` bytes, which should be a multiple of the
+/// event size.
+///
+/// On failure, `written` holds how far the write got, so that it can be
+/// resumed.
+fn write_from(fd: RawFd, bytes: &[u8], written: &mut usize, max_write: usize) -> io::Result<()> {
+    while *written < bytes.len() {
+        let rest = &bytes[*written..bytes.len().min(written.saturating_add(max_write))];
+        // SAFETY: `rest` is valid for reads of `rest.len()` bytes
+        let n = unsafe { libc::write(fd, rest.as_ptr().cast(), rest.len()) };
+        match usize::try_from(n) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => *written += n,
+            Err(_) => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn create_trackpad(config: &Config) -> Result<(Sink, Vec<PathBuf>)> {
+    let spec = TrackpadSpec::new(config);
+    let (device, sys_path, mut dev_nodes) = if let Some(fd) = config.uinput_fd {
+        let (fd, sys_path, dev_nodes) = inherit_device(fd)
+            .with_context(|| format!("failed to use inherited uinput fd {fd}"))?;
+        info!(
+            "Using inherited virtual trackpad from fd {}",
+            fd.as_raw_fd()
+        );
+        (SinkDevice::Inherited(fd), sys_path, dev_nodes)
+    } else {
+        let mut dev = spec.build()?;
+        // we need a slight delay after creating the input device
+        // so that other processes (i.e. compositor) can recognize it
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        info!("Created virtual trackpad");
+
+        let dev_nodes = own_dev_nodes(&mut dev, "virtual trackpad").await;
+        let sys_path = dev
+            .get_syspath()
+            .with_context(|| "failed to get sys path of device")?;
+        (SinkDevice::Created(dev), sys_path, dev_nodes)
+    };
+    info!("  bus = {:?}", config.bus);
+    info!("  sys path = {sys_path:?}");
+    for dev_node in &dev_nodes {
+        info!("  dev node = {dev_node:?}");
+    }
+    if let Some(timeout) = config.wait_for_consumer {
+        wait_for_consumer(&dev_nodes, timeout).await;
+    }
+    let event_node = dev_nodes
+        .iter()
+        .find(|dev_node| {
+            dev_node
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("event"))
+        })
+        .cloned();
+
+    let mut pointer =
+        if config.cursor_lock || config.scroll_key.is_some() || config.scroll_two_finger {
+            Some(Pointer::build()?)
+        } else {
+            None
+        };
+
+    if let Some(pointer) = &mut pointer {
+        let pointer_nodes = own_dev_nodes(pointer.device_mut(), "virtual pointer").await;
+        info!("Created virtual pointer");
+        for dev_node in &pointer_nodes {
+            info!("  dev node = {dev_node:?}");
+        }
+        dev_nodes.extend(pointer_nodes);
+    }
+
+    let mut passthrough = if config.passthrough {
+        Some(Passthrough::build()?)
+    } else {
+        None
+    };
+    if let Some(passthrough) = &mut passthrough {
+        let passthrough_nodes = own_dev_nodes(passthrough.device_mut(), "passthrough device").await;
+        info!("Created passthrough device");
+        for dev_node in &passthrough_nodes {
+            info!("  dev node = {dev_node:?}");
+        }
+        dev_nodes.extend(passthrough_nodes);
+    }
+
+    let recorders = start_recorders(config, &spec, event_node.as_deref())?;
+
+    Ok((
+        Sink {
+            device,
+            origin: spec.origin,
+            bounds: spec.bounds,
+            recorders,
+            pointer,
+            passthrough,
+            next_tracking_id: 0,
+            max_write: match config.max_batch_events {
+                0 => usize::MAX,
+                events => events * mem::size_of::<libc::input_event>(),
+            },
+            latency: config.measure_latency.then(Latency::default),
+            auto_tune: config.auto_tune.then(AutoTune::default),
+            swipe_commands: (!config.swipe_cmd.is_empty())
+                .then(|| SwipeCommands::new(config.swipe_cmd.clone(), config.swipe_cmd_debounce)),
+            muted: config.swipe_cmd_only,
+            #[cfg(feature = "notifications")]
+            notifier: (!config.notify.is_empty())
+                .then(|| crate::notifications::Notifier::start(config.notify.clone())),
+            frames: 0,
+            events: 0,
+            last_emitted: Instant::now(),
+            jitter: (config.jitter > 0).then(|| Jitter::new(config.jitter, config.jitter_seed)),
+            sys_path,
+        },
+        dev_nodes,
+    ))
+}
+
+/// Takes ownership of a `uinput` file descriptor which another process created
+/// a device on, and finds its sys path and dev nodes
+///
+/// The handoff works like this:
+/// - a privileged helper opens `/dev/uinput`, sets up a device with the
+///   capabilities that `fukomaster describe-target` prints, and creates it with
+///   `UI_DEV_CREATE`
+/// - the helper starts fukomaster with the file descriptor left open (not
+///   `O_CLOEXEC`), passing its number with `--uinput-fd`
+/// - fukomaster only writes events to it, and closing it (when fukomaster
+///   exits) destroys the device
+fn inherit_device(fd: RawFd) -> Result<(OwnedFd, PathBuf, Vec<PathBuf>)> {
+    /// `UI_GET_SYSNAME(len)` from `linux/uinput.h`
+    const fn ui_get_sysname(len: usize) -> libc::c_ulong {
+        const IOC_READ: libc::c_ulong = 2;
+        (IOC_READ << 30) | ((len as libc::c_ulong) << 16) | ((b'U' as libc::c_ulong) << 8) | 0x2c
+    }
+
+    // SAFETY: `F_GETFD` only reads the flags of `fd`, and fails if it isn't open
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+        return Err(io::Error::last_os_error()).with_context(|| "file descriptor is not open");
+    }
+    // SAFETY: `fd` is open, and it was handed to us to own, so nothing else in
+    // this process uses it
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut sysname = [0u8; 64];
+    // SAFETY: `sysname` is valid for writes of its length, which is the
+    // length encoded in the request
+    let res = unsafe {
+        libc::ioctl(
+            fd.as_raw_fd(),
+            ui_get_sysname(sysname.len()),
+            sysname.as_mut_ptr(),
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| "not a uinput device with a created input device");
+    }
+    let sysname = CStr::from_bytes_until_nul(&sysname)
+        .with_context(|| "sys name is not terminated")?
+        .to_str()
+        .with_context(|| "sys name is not UTF-8")?;
+
+    let sys_path = Path::new("/sys/devices/virtual/input").join(sysname);
+    let dev_nodes = dev_nodes_in(&sys_path)?;
+    Ok((fd, sys_path, dev_nodes))
+}
+
+/// Waits until a process other than us has one of `dev_nodes` open, or
+/// `timeout` has passed
+async fn wait_for_consumer(dev_nodes: &[PathBuf], timeout: Duration) {
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    info!("Waiting for another process to open the virtual trackpad");
+    let started_at = Instant::now();
+    loop {
+        if let Some((pid, dev_node)) = find_consumer(dev_nodes) {
+            let comm = fs::read_to_string(format!("/proc/{pid}/comm")).unwrap_or_default();
+            info!(
+                "Virtual trackpad was opened by {} (PID {pid}) after {}ms, at {}",
+                comm.trim(),
+                started_at.elapsed().as_millis(),
+                dev_node.display()
+            );
+            return;
+        }
+        if started_at.elapsed() >= timeout {
+            warn!(
+                "No other process opened the virtual trackpad within {}ms, the first gestures \
+                 may be lost",
+                timeout.as_millis()
+            );
+            return;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Finds a process other than us which has one of `dev_nodes` open, returning
+/// its PID and the dev node
+///
+/// Processes whose open files we aren't allowed to see are skipped.
+fn find_consumer(dev_nodes: &[PathBuf]) -> Option<(u32, PathBuf)> {
+    let own_pid = process::id();
+    fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| *pid != own_pid)
+        .find_map(|pid| {
+            fs::read_dir(format!("/proc/{pid}/fd"))
+                .ok()?
+                .filter_map(|fd| fs::read_link(fd.ok()?.path()).ok())
+                .find(|target| dev_nodes.contains(target))
+                .map(|dev_node| (pid, dev_node))
+        })
+}
+
+/// Determines if `device` is one of the virtual devices that we create, by
+/// its name
+///
+/// This recognizes our own devices even if their dev nodes couldn't be found,
+/// so that they are never tracked and their events fed back into them.
+pub fn is_own_device(device: &Device) -> bool {
+    device.name().is_some_and(|name| {
+        [
+            VIRTUAL_DEVICE_NAME,
+            pointer::VIRTUAL_DEVICE_NAME,
+            passthrough::VIRTUAL_DEVICE_NAME,
+        ]
+        .contains(&name)
+    })
+}
+
+/// Finds the dev nodes of a device we created, or of the device at `sys_path`
+/// if they can't be enumerated
+///
+/// `what` names the device in warnings. If no dev nodes can be found at all,
+/// the device is still recognized by [`is_own_device`], so this doesn't fail.
+async fn own_dev_nodes(device: &mut VirtualDevice, what: &str) -> Vec<PathBuf> {
+    let err = match collect_dev_nodes(device).await {
+        Ok(nodes) if !nodes.is_empty() => return nodes,
+        Ok(_) => anyhow!("device has no dev nodes yet"),
+        Err(err) => err,
+    };
+    warn!("Failed to enumerate dev nodes of {what}, looking in its sys path instead: {err:#}");
+    let nodes = device
+        .get_syspath()
+        .with_context(|| "failed to get sys path of device")
+        .and_then(|sys_path| dev_nodes_in(&sys_path));
+    match nodes {
+        Ok(nodes) if !nodes.is_empty() => nodes,
+        Ok(_) => {
+            warn!("Found no dev nodes of {what}, recognizing it by name instead");
+            Vec::new()
+        }
+        Err(err) => {
+            warn!("Failed to find dev nodes of {what}, recognizing it by name instead: {err:#}");
+            Vec::new()
+        }
+    }
+}
+
+/// Lists the dev nodes of the input device at `sys_path`
+fn dev_nodes_in(sys_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut dev_nodes = Vec::new();
+    for entry in
+        fs::read_dir(sys_path).with_context(|| format!("failed to list {}", sys_path.display()))?
+    {
+        let entry = entry.with_context(|| format!("failed to list {}", sys_path.display()))?;
+        if let Some(name) = entry.file_name().to_str() {
+            if name.starts_with("event") {
+                dev_nodes.push(Path::new("/dev/input").join(name));
+            }
+        }
+    }
+    Ok(dev_nodes)
+}
+
+async fn collect_dev_nodes(device: &mut VirtualDevice) -> Result<Vec<PathBuf>> {
+    let mut iter = device.enumerate_dev_nodes().await?;
+    let mut nodes = Vec::new();
+    while let Ok(Some(node)) = iter.next_entry().await {
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
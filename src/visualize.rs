@@ -0,0 +1,88 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use evdev::{AbsoluteAxisType, InputEvent, InputEventKind, Key};
+
+use crate::{
+    evemu,
+    trackpad::{self, TrackpadSpec},
+};
+
+/// Writes the path of the emitted fingers as CSV, one row per frame, so that
+/// it can be plotted live while gestures are performed
+///
+/// Each row is `time,event,x,y`: the seconds since the first frame, whether
+/// the fingers went `down`, `move`d or went `up`, and their position relative
+/// to the origin in trackpad units, with Y increasing downwards. The position
+/// is the mean of every finger reported in the frame, so jitter between
+/// fingers averages out. Frames which report no position and don't lift the
+/// fingers, like those only changing the finger count, are skipped.
+pub struct Visualizer {
+    out: Box<dyn Write + Send>,
+    origin: (i32, i32),
+    start: Option<libc::timeval>,
+    /// Last position written, which fingers lift at
+    last: (i64, i64),
+}
+
+impl Visualizer {
+    /// Starts writing the path to `path` (or stdout if `-`)
+    pub fn create(path: &Path, spec: &TrackpadSpec) -> io::Result<Self> {
+        let mut out = evemu::open_output(path)?;
+        writeln!(out, "time,event,x,y")?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            origin: spec.origin,
+            start: None,
+            last: (0, 0),
+        })
+    }
+}
+
+impl trackpad::Recorder for Visualizer {
+    fn record(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        let Some(last) = events.last() else {
+            return Ok(());
+        };
+        let mut touch = None;
+        let (mut xs, mut ys) = (Vec::new(), Vec::new());
+        for event in events {
+            match event.kind() {
+                InputEventKind::Key(Key::BTN_TOUCH) => touch = Some(event.value() != 0),
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_X) => {
+                    xs.push(i64::from(event.value()) - i64::from(self.origin.0));
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_MT_POSITION_Y) => {
+                    ys.push(i64::from(event.value()) - i64::from(self.origin.1));
+                }
+                _ => {}
+            }
+        }
+        let mean = |values: &[i64], last: i64| match i64::try_from(values.len()) {
+            Ok(len) if len > 0 => values.iter().sum::<i64>() / len,
+            _ => last,
+        };
+        let (x, y) = (mean(&xs, self.last.0), mean(&ys, self.last.1));
+        let event = match touch {
+            Some(true) => "down",
+            Some(false) => "up",
+            None if xs.is_empty() && ys.is_empty() => return Ok(()),
+            None => "move",
+        };
+        self.last = (x, y);
+
+        let time = last.as_ref().time;
+        let start = *self.start.get_or_insert(time);
+        let micros = (time.tv_sec - start.tv_sec) * 1_000_000 + (time.tv_usec - start.tv_usec);
+        writeln!(
+            self.out,
+            "{}.{:06},{event},{x},{y}",
+            micros / 1_000_000,
+            micros % 1_000_000
+        )?;
+        self.out.flush()
+    }
+}
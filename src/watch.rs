@@ -0,0 +1,123 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ahash::AHashSet;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use notify::{RecommendedWatcher, Watcher};
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+
+use crate::{enumerate_devices, NotifyEvent, DEV_INPUT};
+
+/// How device changes under [`DEV_INPUT`] are detected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum WatchMode {
+    /// Use inotify, which reports changes as soon as they happen
+    Inotify,
+    /// Periodically list the files under [`DEV_INPUT`], for environments
+    /// where inotify doesn't work
+    Poll,
+    /// Use inotify, falling back to polling if the watcher can't be set up
+    #[default]
+    Auto,
+}
+
+/// Starts reporting device changes to `send_notifs`, until the returned
+/// watcher is dropped
+///
+/// `known` are the paths which already exist, which polling compares the
+/// first listing against.
+pub fn watch_devices(
+    mode: WatchMode,
+    poll_interval: Duration,
+    known: Vec<PathBuf>,
+    send_notifs: &mpsc::UnboundedSender<NotifyEvent>,
+) -> Result<Option<RecommendedWatcher>> {
+    match mode {
+        WatchMode::Inotify => watch_inotify(send_notifs.clone()).map(Some),
+        WatchMode::Poll => {
+            poll(poll_interval, known, send_notifs.clone());
+            Ok(None)
+        }
+        WatchMode::Auto => match watch_inotify(send_notifs.clone()) {
+            Ok(watcher) => Ok(Some(watcher)),
+            Err(err) => {
+                warn!("Falling back to polling for device changes: {err:#}");
+                poll(poll_interval, known, send_notifs.clone());
+                Ok(None)
+            }
+        },
+    }
+}
+
+fn watch_inotify(send_notifs: mpsc::UnboundedSender<NotifyEvent>) -> Result<RecommendedWatcher> {
+    let mut dev_watcher = notify::recommended_watcher(move |res| match res {
+        Ok(notify::Event {
+            kind: notify::EventKind::Create(_),
+            paths,
+            ..
+        }) => {
+            for path in paths {
+                debug!("{path:?} created");
+                let _ = send_notifs.send(NotifyEvent::Created(path));
+            }
+        }
+        Ok(notify::Event {
+            kind: notify::EventKind::Remove(_),
+            paths,
+            ..
+        }) => {
+            for path in paths {
+                debug!("{path:?} removed");
+                let _ = send_notifs.send(NotifyEvent::Removed(path));
+            }
+        }
+        Ok(_) => {}
+        Err(err) => {
+            warn!(
+                "Error while watching {DEV_INPUT:?}: {:#}",
+                anyhow::Error::new(err)
+            );
+        }
+    })
+    .with_context(|| format!("failed to create {DEV_INPUT:?} watcher"))?;
+
+    dev_watcher
+        .watch(Path::new(DEV_INPUT), notify::RecursiveMode::NonRecursive)
+        .with_context(|| format!("failed to start watching {DEV_INPUT:?}"))?;
+    info!("Watching {DEV_INPUT:?} for device changes");
+    Ok(dev_watcher)
+}
+
+/// Lists the files under [`DEV_INPUT`] every `interval`, reporting the ones
+/// which appeared or disappeared since the last listing
+fn poll(interval: Duration, known: Vec<PathBuf>, send_notifs: mpsc::UnboundedSender<NotifyEvent>) {
+    info!("Polling {DEV_INPUT:?} for device changes every {interval:?}");
+    let mut known = known.into_iter().collect::<AHashSet<_>>();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let paths = match enumerate_devices() {
+                Ok(paths) => paths.into_iter().collect::<AHashSet<_>>(),
+                Err(err) => {
+                    warn!("Failed to poll for device changes: {err:#}");
+                    continue;
+                }
+            };
+
+            let removed = known.difference(&paths).cloned().map(NotifyEvent::Removed);
+            let created = paths.difference(&known).cloned().map(NotifyEvent::Created);
+            for event in removed.chain(created) {
+                debug!("{event:?} found by polling");
+                if send_notifs.send(event).is_err() {
+                    return;
+                }
+            }
+            known = paths;
+        }
+    });
+}